@@ -22,7 +22,7 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            qevy::MapAssetLoaderPlugin::default(),
+            qevy::QevyPlugins::default(),
             PhysicsPlugins::default(), // XPBD
                                        //PhysicsDebugPlugin::default(),
         ))
@@ -34,8 +34,6 @@ fn main() {
                 grab_mouse,
                 my_post_build_map_system,
                 door_system,
-                qevy::load::post_build_map_system,
-                qevy::gameplay_systems::xpbd_trigger_system,
             ),
         )
         .run();
@@ -0,0 +1,229 @@
+//! Terrain blending between two textures, e.g. dirt to grass.
+//!
+//! Brush entities with a `_blend_to` property, the name of a second map
+//! texture, draw their faces blended from their own texture to it. The blend
+//! follows the vertex alpha, which goes from 0 to 1 between the heights of
+//! `_blend_height` (`bottom top`, in map units) when set and is 1 otherwise,
+//! times the red channel of the `_blend_mask` texture when there is one,
+//! tiled `_blend_mask_scale` times over the face UVs.
+//!
+//! Add [`TerrainBlendPlugin`] to swap the materials of blended brushes for a
+//! [`BlendMaterial`].
+
+use bevy::asset::load_internal_asset;
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::utils::HashMap;
+
+use crate::components::{Map, MapEntityProperties, MapUnits};
+use crate::conversions::to_bevy_distance;
+use crate::intern::InternedStr;
+use crate::picking::BrushFaceMesh;
+use crate::MapAsset;
+
+const BLEND_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x2b8e_61f0_c4d7_4a35_9e02_7f3a_b816_5cd4);
+
+pub type BlendMaterial = ExtendedMaterial<StandardMaterial, BlendExtension>;
+
+#[derive(Default)]
+pub struct TerrainBlendPlugin;
+
+impl Plugin for TerrainBlendPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            BLEND_SHADER_HANDLE,
+            "shaders/blend.wgsl",
+            Shader::from_wgsl
+        );
+        // vertex heights need the propagated transforms of new meshes
+        app.add_plugins(MaterialPlugin::<BlendMaterial>::default())
+            .add_systems(
+                PostUpdate,
+                apply_terrain_blend_system
+                    .after(bevy::transform::TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct BlendExtension {
+    #[uniform(100)]
+    pub settings: BlendSettings,
+    /// base color texture of the second layer
+    #[texture(101)]
+    #[sampler(102)]
+    pub blend_texture: Option<Handle<Image>>,
+    #[texture(103)]
+    #[sampler(104)]
+    pub mask_texture: Option<Handle<Image>>,
+}
+
+impl MaterialExtension for BlendExtension {
+    fn fragment_shader() -> ShaderRef {
+        BLEND_SHADER_HANDLE.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        BLEND_SHADER_HANDLE.into()
+    }
+}
+
+#[derive(ShaderType, Reflect, Debug, Clone, Copy)]
+pub struct BlendSettings {
+    /// how many times the mask repeats over the face UVs
+    pub mask_scale: f32,
+    /// whether `mask_texture` is set
+    pub use_mask: u32,
+}
+
+/// Blends the faces of a brush entity to a second texture.
+#[derive(Component, Debug, Clone)]
+pub struct TerrainBlend {
+    /// name of the second texture
+    pub to: InternedStr,
+    /// path of the mask texture
+    pub mask: Option<InternedStr>,
+    pub mask_scale: f32,
+    /// heights where the vertex alpha goes from 0 to 1, in Bevy units
+    /// relative to the map
+    pub height: Option<(f32, f32)>,
+}
+
+impl TerrainBlend {
+    /// The vertex alpha at a height.
+    pub fn alpha(&self, height: f32) -> f32 {
+        match self.height {
+            Some((bottom, top)) if top != bottom => {
+                ((height - bottom) / (top - bottom)).clamp(0.0, 1.0)
+            }
+            Some((bottom, _)) => (height >= bottom) as u8 as f32,
+            None => 1.0,
+        }
+    }
+}
+
+/// Inserts a [`TerrainBlend`] for brush entities with a `_blend_to` property.
+pub fn apply_terrain_blend_properties(
+    map_units: &MapUnits,
+    scale: f32,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let Some(to) = props.properties.get("_blend_to").cloned() else {
+        return;
+    };
+    let height = props
+        .get_property_as_string("_blend_height", None)
+        .and_then(|value| {
+            let values = value
+                .split_whitespace()
+                .filter_map(|v| v.parse::<f32>().ok())
+                .map(|v| to_bevy_distance(v, map_units) * scale)
+                .collect::<Vec<_>>();
+            match values[..] {
+                [bottom, top] => Some((bottom, top)),
+                _ => None,
+            }
+        });
+    commands.entity(entity).insert(TerrainBlend {
+        to,
+        mask: props.properties.get("_blend_mask").cloned(),
+        mask_scale: props.get_property_as_f32("_blend_mask_scale", 1.0),
+        height,
+    });
+}
+
+/// Swaps the materials of the meshes of [`TerrainBlend`] brushes for
+/// [`BlendMaterial`]s, and writes their vertex alpha.
+pub fn apply_terrain_blend_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    map_assets: Res<Assets<MapAsset>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut blend_materials: ResMut<Assets<BlendMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut created: Local<HashMap<(Entity, AssetId<StandardMaterial>), Handle<BlendMaterial>>>,
+    q_blends: Query<&TerrainBlend>,
+    q_parents: Query<&Parent>,
+    q_maps: Query<(&Map, &GlobalTransform)>,
+    q_meshes: Query<(
+        Entity,
+        &BrushFaceMesh,
+        &Handle<StandardMaterial>,
+        &Handle<Mesh>,
+        &GlobalTransform,
+    )>,
+) {
+    if q_blends.is_empty() {
+        return;
+    }
+    for (entity, faces, material, mesh, transform) in q_meshes.iter() {
+        let Ok(blend) = q_blends.get(faces.brush_entity) else {
+            continue;
+        };
+        let Some(base) = standard_materials.get(material) else {
+            continue;
+        };
+        let Some((map_asset, map_transform)) = q_parents
+            .iter_ancestors(faces.brush_entity)
+            .find_map(|ancestor| q_maps.get(ancestor).ok())
+            .and_then(|(map, map_transform)| Some((map_assets.get(&map.asset)?, map_transform)))
+        else {
+            continue;
+        };
+
+        let handle = created
+            .entry((faces.brush_entity, material.id()))
+            .or_insert_with(|| {
+                let blend_texture = map_asset
+                    .material_handles
+                    .get(&blend.to)
+                    .and_then(|handle| standard_materials.get(handle))
+                    .and_then(|material| material.base_color_texture.clone());
+                if blend_texture.is_none() {
+                    warn!(texture = %blend.to, "no texture to blend to");
+                }
+                blend_materials.add(BlendMaterial {
+                    base: base.clone(),
+                    extension: BlendExtension {
+                        settings: BlendSettings {
+                            mask_scale: blend.mask_scale,
+                            use_mask: blend.mask.is_some() as u32,
+                        },
+                        blend_texture,
+                        mask_texture: blend
+                            .mask
+                            .as_ref()
+                            .map(|path| asset_server.load(path.to_string())),
+                    },
+                })
+            })
+            .clone();
+
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            if let Some(VertexAttributeValues::Float32x3(positions)) =
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+            {
+                let to_map = map_transform.affine().inverse() * transform.affine();
+                let colors = positions
+                    .iter()
+                    .map(|position| {
+                        let height = to_map.transform_point3(Vec3::from(*position)).y;
+                        [1.0, 1.0, 1.0, blend.alpha(height)]
+                    })
+                    .collect::<Vec<_>>();
+                mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+            }
+        }
+
+        commands
+            .entity(entity)
+            .remove::<Handle<StandardMaterial>>()
+            .insert(handle);
+    }
+}
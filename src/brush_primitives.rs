@@ -0,0 +1,499 @@
+//! Doom 3 / Quake 3 style brush primitives.
+//!
+//! The map parser only understands classic Quake brushes, so `brushDef2` and
+//! `brushDef3` brushes are rewritten into classic brushes before parsing,
+//! keeping their texture matrices aside to generate UVs. `patchDef2` and
+//! `patchDef3` bezier patches are removed from the text and tessellated into
+//! render-only meshes.
+
+use bevy::math::{Vec2, Vec3};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tracing::warn;
+
+/// Subdivisions per 3x3 bezier sub-patch when a patch doesn't specify its own.
+pub const DEFAULT_PATCH_SUBDIVISIONS: usize = 8;
+
+/// Brush primitive data pulled out of a map before parsing.
+#[derive(Debug, Default, Clone)]
+pub struct BrushPrimitives {
+    /// texture matrices keyed by the index of the face in the map
+    pub face_matrices: BTreeMap<usize, TextureMatrix>,
+    pub patches: Vec<Patch>,
+    /// 1-based line of each face in the original text, by face index
+    pub face_lines: Vec<usize>,
+}
+
+/// A brush primitive texture matrix, mapping positions projected on the face
+/// plane to normalized texture coordinates.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TextureMatrix {
+    /// face plane normal, in map space
+    pub normal: Vec3,
+    pub rows: [[f32; 3]; 2],
+}
+
+impl TextureMatrix {
+    /// Normalized UVs of a map space position on the face.
+    pub fn uv(&self, position: Vec3) -> Vec2 {
+        let (s_axis, t_axis) = axis_base(self.normal);
+        let s = position.dot(s_axis);
+        let t = position.dot(t_axis);
+        Vec2::new(
+            self.rows[0][0] * s + self.rows[0][1] * t + self.rows[0][2],
+            self.rows[1][0] * s + self.rows[1][1] * t + self.rows[1][2],
+        )
+    }
+}
+
+/// The texture axes brush primitives project onto, as computed by Radiant.
+fn axis_base(normal: Vec3) -> (Vec3, Vec3) {
+    let clean = |v: f32| if v.abs() < 1e-6 { 0.0 } else { v };
+    let normal = Vec3::new(clean(normal.x), clean(normal.y), clean(normal.z));
+    let rot_y = -normal
+        .z
+        .atan2((normal.y * normal.y + normal.x * normal.x).sqrt());
+    let rot_z = normal.y.atan2(normal.x);
+    let s_axis = Vec3::new(-rot_z.sin(), rot_z.cos(), 0.0);
+    let t_axis = Vec3::new(
+        -rot_y.sin() * rot_z.cos(),
+        -rot_y.sin() * rot_z.sin(),
+        -rot_y.cos(),
+    );
+    (s_axis, t_axis)
+}
+
+/// A bezier patch, in map space.
+#[derive(Debug, Default, Clone)]
+pub struct Patch {
+    /// index of the entity the patch belongs to
+    pub entity: usize,
+    pub texture: String,
+    pub width: usize,
+    pub height: usize,
+    pub subdivisions: usize,
+    /// `width` rows of `height` control points
+    pub control_points: Vec<(Vec3, Vec2)>,
+}
+
+/// Triangle mesh of a tessellated patch, in map space.
+#[derive(Debug, Default, Clone)]
+pub struct PatchMesh {
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<u32>,
+}
+
+impl Patch {
+    fn control_point(&self, i: usize, j: usize) -> (Vec3, Vec2) {
+        self.control_points[i * self.height + j]
+    }
+
+    /// Tessellates the patch into triangles, `None` if the patch isn't made of
+    /// whole 3x3 sub-patches.
+    pub fn tessellate(&self) -> Option<PatchMesh> {
+        if self.width < 3
+            || self.height < 3
+            || self.width % 2 == 0
+            || self.height % 2 == 0
+            || self.control_points.len() != self.width * self.height
+        {
+            return None;
+        }
+
+        let subdivisions = self.subdivisions.max(1);
+        let sub_i = (self.width - 1) / 2;
+        let sub_j = (self.height - 1) / 2;
+        let count_i = sub_i * subdivisions + 1;
+        let count_j = sub_j * subdivisions + 1;
+
+        let locate = |index: usize, sub_count: usize| {
+            let sub = (index / subdivisions).min(sub_count - 1);
+            (
+                sub,
+                (index - sub * subdivisions) as f32 / subdivisions as f32,
+            )
+        };
+
+        let mut mesh = PatchMesh::default();
+        for gi in 0..count_i {
+            let (si, u) = locate(gi, sub_i);
+            for gj in 0..count_j {
+                let (sj, v) = locate(gj, sub_j);
+                let rows = [0, 1, 2].map(|row| {
+                    let points =
+                        [0, 1, 2].map(|col| self.control_point(si * 2 + row, sj * 2 + col));
+                    (
+                        bezier(points.map(|p| p.0), v),
+                        bezier2(points.map(|p| p.1), v),
+                    )
+                });
+                mesh.vertices.push(bezier(rows.map(|r| r.0), u));
+                mesh.uvs.push(bezier2(rows.map(|r| r.1), u));
+            }
+        }
+
+        let index = |gi: usize, gj: usize| (gi * count_j + gj) as u32;
+        for gi in 0..count_i - 1 {
+            for gj in 0..count_j - 1 {
+                let (a, b, c, d) = (
+                    index(gi, gj),
+                    index(gi + 1, gj),
+                    index(gi + 1, gj + 1),
+                    index(gi, gj + 1),
+                );
+                mesh.indices.extend([a, b, c, a, c, d]);
+            }
+        }
+
+        // normals from the neighboring grid points
+        for gi in 0..count_i {
+            for gj in 0..count_j {
+                let du = mesh.vertices[index((gi + 1).min(count_i - 1), gj) as usize]
+                    - mesh.vertices[index(gi.saturating_sub(1), gj) as usize];
+                let dv = mesh.vertices[index(gi, (gj + 1).min(count_j - 1)) as usize]
+                    - mesh.vertices[index(gi, gj.saturating_sub(1)) as usize];
+                mesh.normals.push(du.cross(dv).normalize_or_zero());
+            }
+        }
+
+        Some(mesh)
+    }
+}
+
+fn bezier(points: [Vec3; 3], t: f32) -> Vec3 {
+    let it = 1.0 - t;
+    points[0] * it * it + points[1] * 2.0 * it * t + points[2] * t * t
+}
+
+fn bezier2(points: [Vec2; 3], t: f32) -> Vec2 {
+    let it = 1.0 - t;
+    points[0] * it * it + points[1] * 2.0 * it * t + points[2] * t * t
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    OpenBrace,
+    CloseBrace,
+    OpenParen,
+    CloseParen,
+    Word(&'a str),
+}
+
+fn tokenize(text: &str) -> Vec<(Token<'_>, usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'/' && bytes.get(i + 1) == Some(&b'/') {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else if c == b'"' {
+            let start = i;
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += 1;
+            }
+            i = (i + 1).min(bytes.len());
+            let inner = &text[start + 1..i.saturating_sub(1).max(start + 1)];
+            tokens.push((Token::Word(inner), start, i));
+        } else if let Some(token) = match c {
+            b'{' => Some(Token::OpenBrace),
+            b'}' => Some(Token::CloseBrace),
+            b'(' => Some(Token::OpenParen),
+            b')' => Some(Token::CloseParen),
+            _ => None,
+        } {
+            tokens.push((token, i, i + 1));
+            i += 1;
+        } else {
+            let start = i;
+            while i < bytes.len()
+                && !bytes[i].is_ascii_whitespace()
+                && !matches!(bytes[i], b'{' | b'}' | b'(' | b')' | b'"')
+            {
+                i += 1;
+            }
+            tokens.push((Token::Word(&text[start..i]), start, i));
+        }
+    }
+    tokens
+}
+
+struct Cursor<'t, 'a> {
+    tokens: &'t [(Token<'a>, usize, usize)],
+    position: usize,
+}
+
+impl<'t, 'a> Cursor<'t, 'a> {
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.position).map(|t| t.0);
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).map(|t| t.0)
+    }
+
+    fn expect(&mut self, expected: Token) -> Option<()> {
+        (self.next()? == expected).then_some(())
+    }
+
+    fn word(&mut self) -> Option<&'a str> {
+        match self.next()? {
+            Token::Word(word) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.word()?.parse().ok()
+    }
+
+    fn numbers<const N: usize>(&mut self) -> Option<[f32; N]> {
+        self.expect(Token::OpenParen)?;
+        let mut numbers = [0.0; N];
+        for n in numbers.iter_mut() {
+            *n = self.number()?;
+        }
+        self.expect(Token::CloseParen)?;
+        Some(numbers)
+    }
+}
+
+struct PrimitiveFace {
+    points: [Vec3; 3],
+    normal: Vec3,
+    rows: [[f32; 3]; 2],
+    texture: String,
+}
+
+fn parse_brush_def(cursor: &mut Cursor, with_plane: bool) -> Option<Vec<PrimitiveFace>> {
+    cursor.expect(Token::OpenBrace)?;
+    let mut faces = Vec::new();
+    while cursor.peek()? != Token::CloseBrace {
+        let (points, normal) = if with_plane {
+            let [a, b, c, d] = cursor.numbers::<4>()?;
+            let normal = Vec3::new(a, b, c).normalize_or_zero();
+            // p1 is on the plane, and (p0 - p1) x (p2 - p1) points outward
+            let origin = normal * -d;
+            let u = normal.any_orthonormal_vector() * 64.0;
+            let v = normal.cross(u);
+            ([origin + u, origin, origin + v], normal)
+        } else {
+            let points = [0, 1, 2].map(|_| cursor.numbers::<3>().map(Vec3::from_array));
+            let [p0, p1, p2] = [points[0]?, points[1]?, points[2]?];
+            ([p0, p1, p2], (p0 - p1).cross(p2 - p1).normalize_or_zero())
+        };
+        cursor.expect(Token::OpenParen)?;
+        let row0 = cursor.numbers::<3>()?;
+        let row1 = cursor.numbers::<3>()?;
+        cursor.expect(Token::CloseParen)?;
+        let texture = cursor.word()?;
+        // content flags, surface flags and value
+        while let Some(Token::Word(_)) = cursor.peek() {
+            cursor.next();
+        }
+        faces.push(PrimitiveFace {
+            points,
+            normal,
+            rows: [row0, row1],
+            texture: strip_texture_prefix(texture).to_string(),
+        });
+    }
+    cursor.expect(Token::CloseBrace)?;
+    Some(faces)
+}
+
+fn parse_patch_def(
+    cursor: &mut Cursor,
+    entity: usize,
+    explicit_subdivisions: bool,
+) -> Option<Patch> {
+    cursor.expect(Token::OpenBrace)?;
+    let texture = strip_texture_prefix(cursor.word()?).to_string();
+    cursor.expect(Token::OpenParen)?;
+    let width = cursor.number()? as usize;
+    let height = cursor.number()? as usize;
+    let mut subdivisions = DEFAULT_PATCH_SUBDIVISIONS;
+    if explicit_subdivisions {
+        let subdivisions_x = cursor.number()? as usize;
+        let subdivisions_y = cursor.number()? as usize;
+        subdivisions = subdivisions_x.max(subdivisions_y).max(1);
+    }
+    while let Some(Token::Word(_)) = cursor.peek() {
+        cursor.next();
+    }
+    cursor.expect(Token::CloseParen)?;
+
+    let mut control_points = Vec::with_capacity(width * height);
+    cursor.expect(Token::OpenParen)?;
+    for _ in 0..width {
+        cursor.expect(Token::OpenParen)?;
+        for _ in 0..height {
+            let [x, y, z, u, v] = cursor.numbers::<5>()?;
+            control_points.push((Vec3::new(x, y, z), Vec2::new(u, v)));
+        }
+        cursor.expect(Token::CloseParen)?;
+    }
+    cursor.expect(Token::CloseParen)?;
+    cursor.expect(Token::CloseBrace)?;
+
+    Some(Patch {
+        entity,
+        texture,
+        width,
+        height,
+        subdivisions,
+        control_points,
+    })
+}
+
+/// Doom 3 texture names are paths starting with `textures/`, which qevy already prepends.
+fn strip_texture_prefix(texture: &str) -> &str {
+    texture.strip_prefix("textures/").unwrap_or(texture)
+}
+
+/// Rewrites brush primitives into classic Quake brushes, returning the new
+/// map text and the data needed to restore their UVs and patches.
+/// Maps without brush primitives are returned unchanged.
+/// The line of each brush face, assuming braces on their own lines like
+/// editors write them.
+fn face_lines(text: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut depth = 0;
+    let mut in_patch = false;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        match line {
+            "{" => depth += 1,
+            "}" => {
+                depth -= 1;
+                if depth <= 1 {
+                    in_patch = false;
+                }
+            }
+            _ if line.starts_with("patchDef") => in_patch = true,
+            _ if depth >= 2 && !in_patch && line.starts_with('(') => lines.push(number + 1),
+            _ => {}
+        }
+    }
+    lines
+}
+
+pub fn preprocess(text: &str) -> (String, BrushPrimitives) {
+    let mut primitives = BrushPrimitives {
+        face_lines: face_lines(text),
+        ..Default::default()
+    };
+    if !text.contains("brushDef") && !text.contains("patchDef") {
+        return (text.to_string(), primitives);
+    }
+
+    let tokens = tokenize(text);
+    let mut output = String::with_capacity(text.len());
+    let mut copied_until = 0;
+    let mut depth = 0;
+    let mut entity_index = 0;
+    let mut face_index = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let (token, start, _) = tokens[i];
+        match token {
+            Token::OpenBrace if depth == 1 => {
+                // a brush, find where it ends
+                let mut end = i + 1;
+                let mut brush_depth = 1;
+                while end < tokens.len() && brush_depth > 0 {
+                    match tokens[end].0 {
+                        Token::OpenBrace => brush_depth += 1,
+                        Token::CloseBrace => brush_depth -= 1,
+                        _ => {}
+                    }
+                    end += 1;
+                }
+                let brush_end = tokens[end - 1].2;
+
+                let mut cursor = Cursor {
+                    tokens: &tokens[i + 1..end - 1],
+                    position: 1,
+                };
+                match tokens.get(i + 1).map(|t| t.0) {
+                    Some(Token::Word(kind @ ("brushDef2" | "brushDef3"))) => {
+                        output.push_str(&text[copied_until..start]);
+                        copied_until = brush_end;
+                        match parse_brush_def(&mut cursor, kind == "brushDef3") {
+                            Some(faces) => {
+                                output.push_str("{\n");
+                                for face in faces {
+                                    let [p0, p1, p2] = face.points;
+                                    let _ = writeln!(
+                                        output,
+                                        "( {} {} {} ) ( {} {} {} ) ( {} {} {} ) {} 0 0 0 1 1",
+                                        p0.x,
+                                        p0.y,
+                                        p0.z,
+                                        p1.x,
+                                        p1.y,
+                                        p1.z,
+                                        p2.x,
+                                        p2.y,
+                                        p2.z,
+                                        face.texture
+                                    );
+                                    primitives.face_matrices.insert(
+                                        face_index,
+                                        TextureMatrix {
+                                            normal: face.normal,
+                                            rows: face.rows,
+                                        },
+                                    );
+                                    face_index += 1;
+                                }
+                                output.push_str("}\n");
+                            }
+                            None => warn!("skipping malformed {} in entity {}", kind, entity_index),
+                        }
+                    }
+                    Some(Token::Word(kind @ ("patchDef2" | "patchDef3"))) => {
+                        output.push_str(&text[copied_until..start]);
+                        copied_until = brush_end;
+                        match parse_patch_def(&mut cursor, entity_index, kind == "patchDef3") {
+                            Some(patch) => primitives.patches.push(patch),
+                            None => warn!("skipping malformed {} in entity {}", kind, entity_index),
+                        }
+                    }
+                    _ => {
+                        // classic brush, every face has three points
+                        let parens = tokens[i..end]
+                            .iter()
+                            .filter(|t| t.0 == Token::OpenParen)
+                            .count();
+                        face_index += parens / 3;
+                    }
+                }
+                i = end;
+                continue;
+            }
+            Token::OpenBrace => depth += 1,
+            Token::CloseBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    entity_index += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    output.push_str(&text[copied_until..]);
+
+    (output, primitives)
+}
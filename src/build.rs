@@ -1,420 +1,1508 @@
+use bevy::math::bounding::Aabb3d;
+#[cfg(feature = "render")]
+use bevy::pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder};
 use bevy::prelude::*;
-use bevy::render::mesh::Indices;
+#[cfg(feature = "render")]
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+#[cfg(feature = "render")]
 use bevy::render::render_asset::RenderAssetUsages;
+#[cfg(feature = "render")]
 use bevy::render::render_resource::PrimitiveTopology;
+#[cfg(feature = "render")]
+use bevy::render::view::RenderLayers;
+use bevy::utils::Instant;
 #[cfg(feature = "rapier")]
 use bevy_rapier3d::geometry::ActiveCollisionTypes;
 use std::collections::BTreeMap;
+#[cfg(feature = "render")]
+use std::collections::BTreeSet;
 use std::time::Duration;
+use thiserror::Error;
 
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+use crate::colliders::{configure_sensor_body, simplify_hull, PendingCollider};
 use crate::components::*;
 use crate::conversions::*;
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+use crate::core::is_clip_texture;
+#[cfg(feature = "render")]
+use crate::core::{
+    is_foliage_texture, is_nodraw_texture, parse_vec3, CompiledEntity, UvProjection,
+};
+#[cfg(feature = "render")]
+use crate::picking::BrushFaceMesh;
+use crate::random::entity_seed;
+use crate::registry::MapEntityRegistry;
+#[cfg(feature = "render")]
+use crate::settings::BrushMeshMode;
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+use crate::settings::ColliderGeneration;
+use crate::settings::{DegenerateGeometryPolicy, MapBuildMode, MapBuildSettings, UvLock};
 
 use crate::{MapAsset, PostBuildMapEvent};
 
+#[cfg(feature = "render")]
 #[derive(Event)]
 pub struct SpawnMeshEvent {
     map: Entity,
     mesh: Mesh,
     collider: Option<Entity>,
     material: Handle<StandardMaterial>,
+    /// set when the mesh should have world-locked UVs
+    uv_projection: Option<UvProjection>,
+    /// size of the texture the UVs were computed against
+    texture_size: Option<UVec2>,
+    name: Name,
+    render_layers: Option<RenderLayers>,
+    /// the brush faces drawn by the mesh
+    faces: Option<BrushFaceMesh>,
+    foliage: bool,
 }
 
+#[derive(Debug, Error)]
+pub enum MapBuildError {
+    #[error("{0} degenerate faces, see the DegenerateGeometry events")]
+    DegenerateGeometry(usize),
+}
+
+#[cfg_attr(
+    not(all(feature = "render", any(feature = "xpbd", feature = "rapier"))),
+    allow(unused_variables, unused_mut)
+)]
 pub fn build_map(
     map_units: &MapUnits,
+    build_settings: &MapBuildSettings,
     map_entity: Entity,
     map_asset: &mut MapAsset,
     commands: &mut Commands,
-    spawn_mesh_event: &mut EventWriter<SpawnMeshEvent>,
+    #[cfg(feature = "render")] spawn_mesh_event: &mut EventWriter<SpawnMeshEvent>,
     post_build_map_event: &mut EventWriter<PostBuildMapEvent>,
-) {
-    let geomap = map_asset.geomap.as_ref().unwrap();
-
-    let face_trangle_planes = &geomap.face_planes;
-    let face_planes = shambler::face::face_planes(&face_trangle_planes);
-    let brush_hulls = shambler::brush::brush_hulls(&geomap.brush_faces, &face_planes);
-    let (face_vertices, _face_vertex_planes) =
-        shambler::face::face_vertices(&geomap.brush_faces, &face_planes, &brush_hulls);
-    let face_centers = shambler::face::face_centers(&face_vertices);
-    let face_indices = shambler::face::face_indices(
-        &geomap.face_planes,
-        &face_planes,
-        &face_vertices,
-        &face_centers,
-        shambler::face::FaceWinding::Clockwise,
-    );
-    let face_triangle_indices = shambler::face::face_triangle_indices(&face_indices);
-    let face_normals = shambler::face::normals_flat(&face_vertices, &face_planes);
-
-    let face_uvs = shambler::face::new(
-        &geomap.faces,
-        &geomap.textures,
-        &geomap.face_textures,
-        &face_vertices,
-        &face_planes,
-        &geomap.face_offsets,
-        &geomap.face_angles,
-        &geomap.face_scales,
-        &shambler::texture::texture_sizes(
-            &geomap.textures,
-            map_asset.get_texture_names_with_size(),
-        ),
+    degenerate_geometry_event: &mut EventWriter<DegenerateGeometry>,
+    registry: &MapEntityRegistry,
+    scale: f32,
+    mirror: Option<MapMirror>,
+) -> Result<MapMetadata, MapBuildError> {
+    let _span = debug_span!("build_map", map = ?map_entity).entered();
+    let build_start = Instant::now();
+    let mut compiled_map = map_asset.compile(map_units).unwrap();
+    debug!(
+        elapsed = ?build_start.elapsed(),
+        entities = compiled_map.entities.len(),
+        "compiled map"
     );
-
-    // spawn entities (@PointClass)
-    geomap
-        .entity_properties
-        .iter()
-        .for_each(|(entity_id, props)| {
-            // if it's an entity brush we process it later
-            if geomap.entity_brushes.get(entity_id).is_some() {
-                return;
+    if build_settings.degenerate_geometry != DegenerateGeometryPolicy::Skip {
+        for face in compiled_map.geometry_report.dropped_faces.iter() {
+            if build_settings.degenerate_geometry == DegenerateGeometryPolicy::Warn {
+                warn!("{}", face);
             }
+            degenerate_geometry_event.send(DegenerateGeometry {
+                map: map_entity,
+                face: face.clone(),
+            });
+        }
+    }
+    let dropped_faces = compiled_map.geometry_report.dropped_faces.len();
+    if build_settings.degenerate_geometry == DegenerateGeometryPolicy::Fail && dropped_faces > 0 {
+        return Err(MapBuildError::DegenerateGeometry(dropped_faces));
+    }
+    #[cfg_attr(not(any(feature = "xpbd", feature = "rapier")), allow(unused_mut))]
+    let mut memory_usage = MapMemoryUsage {
+        texture_bytes: map_asset.texture_bytes,
+        ..default()
+    };
+    for compiled_entity in compiled_map.entities.iter_mut() {
+        registry.apply(compiled_entity);
+    }
+    if scale != 1.0 {
+        compiled_map.scale(scale);
+    }
+    if let Some(mirror) = mirror {
+        compiled_map.mirror(mirror);
+    }
+    let spawns_geometry = map_asset.build_mode.spawns_geometry();
+    let spawns_logic = map_asset.build_mode.spawns_logic();
+    let map_seed = compiled_map
+        .entities
+        .iter()
+        .find(|entity| entity.classname == "worldspawn")
+        .and_then(|worldspawn| worldspawn.properties.get("_seed"))
+        .and_then(|seed| seed.trim().parse::<u64>().ok())
+        .unwrap_or(build_settings.seed);
+    let metadata = MapMetadata {
+        bounds: compiled_map.bounds(),
+        classname_counts: compiled_map.classname_counts(),
+    };
 
-            // map properties into btree
-            // just easier to access props
-            let mut props = props
-                .iter()
-                .map(|p| (p.key.as_str(), p.value.as_str()))
-                .collect::<BTreeMap<_, _>>();
-
-            let classname = props.get(&"classname").unwrap_or(&"").to_string();
-            let translation = props.get(&"origin").unwrap_or(&"0 0 0").to_string();
-            let rotation = props.get(&"angles").unwrap_or(&"0 0 0").to_string();
-
-            let translation = translation.split(" ").collect::<Vec<&str>>();
-            let translation = if translation.len() == 3 {
-                to_bevy_position(
-                    &Vec3::new(
-                        translation[0].parse::<f32>().unwrap(),
-                        translation[1].parse::<f32>().unwrap(),
-                        translation[2].parse::<f32>().unwrap(),
-                    ),
-                    &map_units,
-                )
-            } else {
-                Vec3::ZERO
-            };
+    // spawn entities (@PointClass)
+    for compiled_entity in compiled_map.entities.iter() {
+        // if it's an entity brush we process it later
+        if compiled_entity.is_brush_entity() {
+            continue;
+        }
 
-            let rotation = rotation.split(" ").collect::<Vec<&str>>();
-            let rotation = if rotation.len() == 3 {
-                to_bevy_rotation(&Vec3::new(
-                    rotation[0].parse::<f32>().unwrap(),
-                    rotation[1].parse::<f32>().unwrap(),
-                    rotation[2].parse::<f32>().unwrap(),
-                ))
-            } else {
-                Quat::IDENTITY
-            };
+        let props = &compiled_entity.properties;
 
-            commands.entity(map_entity).with_children(|children| {
-                let mut entity = children.spawn((MapEntityProperties {
-                    classname: classname.to_string(),
-                    transform: Transform::from_translation(translation)
-                        * Transform::from_rotation(rotation),
+        commands.entity(map_entity).with_children(|children| {
+            let mut entity = children.spawn((
+                MapPointEntity,
+                point_entity_name(&compiled_entity.classname, compiled_entity.translation),
+                MapEntityProperties {
+                    classname: map_asset.interner.intern(&compiled_entity.classname),
+                    transform: Transform::from_translation(compiled_entity.translation)
+                        * Transform::from_rotation(compiled_entity.rotation),
                     properties: props
-                        .iter_mut()
-                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .iter()
+                        .map(|(k, v)| (map_asset.interner.intern(k), map_asset.interner.intern(v)))
                         .collect(),
-                },));
+                    seed: entity_seed(map_seed, compiled_entity.index),
+                },
+            ));
 
-                if let Some(target_name) = props.get("targetname") {
-                    entity.insert(TriggerTarget {
-                        target_name: target_name.to_string(),
-                    });
+            if let Some(target_name) = props.get("targetname").filter(|_| spawns_logic) {
+                entity.insert(TriggerTarget {
+                    target_name: map_asset.interner.intern(target_name),
+                });
+            }
+
+            // included maps are built as nested maps
+            if let Some(external_map) = map_asset.external_maps.get(&compiled_entity.index) {
+                entity.insert((
+                    Map {
+                        asset: external_map.clone(),
+                        ..default()
+                    },
+                    MapScale(scale),
+                    MapSpatialBundle::from_transform(
+                        Transform::from_translation(compiled_entity.translation)
+                            * Transform::from_rotation(compiled_entity.rotation),
+                    ),
+                ));
+                if let Some(mirror) = mirror {
+                    entity.insert(mirror);
                 }
-            });
+            }
         });
+    }
 
-    // spawn brush entities (@SolidClass)
-    for (entity_id, brushes) in geomap.entity_brushes.iter() {
-        let entity_properties = geomap.entity_properties.get(&entity_id);
+    debug!(elapsed = ?build_start.elapsed(), "spawned point entities");
 
-        if let None = entity_properties {
-            panic!("brush entity {} has no properties!", entity_id);
+    // spawn brush entities (@SolidClass), with the structural ones grouped
+    // under a single parent
+    let world_geometry = commands
+        .spawn((
+            WorldGeometry,
+            MapSpatialBundle::default(),
+            Name::new("world geometry"),
+        ))
+        .set_parent(map_entity)
+        .id();
+    let door_groups = crate::movers::door_groups(&compiled_map);
+    for compiled_entity in compiled_map.entities.iter() {
+        if !compiled_entity.is_brush_entity() {
+            continue;
         }
 
-        // map properties into btree
-        // just easier to access props
-        let mut props = entity_properties
-            .unwrap()
-            .iter()
-            .map(|p| (p.key.as_str(), p.value.as_str()))
-            .collect::<BTreeMap<_, _>>();
-        let classname = props.get(&"classname").unwrap_or(&"").to_string();
+        let props = &compiled_entity.properties;
+        let classname = compiled_entity.classname.as_str();
+        let uv_lock = props
+            .get("_uv_lock")
+            .and_then(|value| UvLock::from_property(value))
+            .unwrap_or(build_settings.uv_lock);
+
+        // geometry is spawned relative to the entity's origin, so moving or
+        // reparenting the brush entity carries its meshes and colliders along
+        let origin = if props.contains_key("origin") {
+            compiled_entity.translation
+        } else {
+            compiled_entity.center()
+        };
+        let origin_transform = Transform::from_translation(origin);
+
+        let brush_faces = spawns_geometry.then(|| {
+            crate::picking::BrushFaces::from_compiled(
+                compiled_entity,
+                origin,
+                &mut map_asset.interner,
+            )
+        });
         let brush_entity = (
             BrushEntity {},
             MapEntityProperties {
-                classname: classname.to_string(),
+                classname: map_asset.interner.intern(classname),
+                transform: origin_transform,
                 properties: props
-                    .iter_mut()
-                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .iter()
+                    .map(|(k, v)| (map_asset.interner.intern(k), map_asset.interner.intern(v)))
                     .collect(),
-                ..default()
+                seed: entity_seed(map_seed, compiled_entity.index),
             },
-            SpatialBundle::default(),
+            MapSpatialBundle::from_transform(origin_transform),
         );
 
-        commands.entity(map_entity).with_children(|children| {
-            let mut entity = children.spawn(brush_entity);
+        let parent = match classname {
+            "worldspawn" | "func_group" => world_geometry,
+            _ => map_entity,
+        };
+        commands.entity(parent).with_children(|children| {
+            let mut entity = children.spawn((brush_entity, brush_entity_name(classname, props)));
+            if let Some(brush_faces) = brush_faces {
+                entity.insert(brush_faces);
+            }
+            // volumes are tested against rather than collided with, and
+            // only drawn when visual
+            if is_volume_classname(classname) {
+                entity.insert(BrushVolume::from_compiled(compiled_entity, origin));
+                if !is_visual_only_classname(classname) {
+                    return;
+                }
+            }
+            let brush_entity_id = entity.id();
             entity.with_children(|gchildren| {
-                for brush_id in brushes.iter() {
-                    let brush_faces = geomap.brush_faces.get(brush_id).unwrap();
-                    let mut brush_vertices: Vec<Vec3> = Vec::new();
-
-                    let mut meshes_to_spawn = Vec::new();
-
-                    for face_id in brush_faces.iter() {
-                        let texture_id = geomap.face_textures.get(face_id).unwrap();
-                        let texture_name = geomap.textures.get(texture_id).unwrap();
-
-                        let indices =
-                            to_bevy_indecies(&face_triangle_indices.get(&face_id).unwrap());
-                        let vertices =
-                            to_bevy_vertices(&face_vertices.get(&face_id).unwrap(), &map_units);
-                        let normals = to_bevy_vec3s(&face_normals.get(&face_id).unwrap());
-                        let uvs = uvs_to_bevy_vec2s(&face_uvs.get(&face_id).unwrap());
-                        brush_vertices.extend(vertices.clone());
-
-                        // we don't render anything for these textures
-                        if texture_name == "trigger"
-                            || texture_name == "clip"
-                            || texture_name == "common/trigger"
-                            || texture_name == "common/clip"
-                        {
-                            continue;
-                        }
-
-                        let mut mesh = Mesh::new(
-                            PrimitiveTopology::TriangleList,
-                            RenderAssetUsages::RENDER_WORLD,
-                        );
-                        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
-                        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-                        mesh.insert_indices(Indices::U32(indices));
-
-                        if uvs.len() > 0 {
-                            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-                            if let Err(e) = mesh.generate_tangents() {
-                                println!("error generating tangents: {:?}", e);
-                            }
-                        }
-
-                        meshes_to_spawn.push((mesh, texture_name));
-                    }
+                for brush in compiled_entity.brushes.iter().filter(|_| spawns_geometry) {
+                    #[cfg(feature = "render")]
+                    let mut meshes_to_spawn = Some(brush_meshes(
+                        brush,
+                        origin,
+                        brush_entity_id,
+                        uv_lock,
+                        build_settings,
+                    ));
 
-                    // spawn it's collider
+                    // spawn it's collider, except for func_illusionary and
+                    // func_liquid brushes which are drawn but get no collider
                     #[cfg(feature = "xpbd")]
-                    {
-                        if let Some(convex_hull) =
-                            bevy_xpbd_3d::prelude::Collider::convex_hull(brush_vertices)
-                        {
+                    if !is_visual_only_classname(classname) {
+                        let brush_vertices = brush_collider_vertices(brush, origin, build_settings);
+                        let hull_bytes = brush_vertices.len() * std::mem::size_of::<Vec3>();
+                        let defer_collider = build_settings.collider_generation
+                            != ColliderGeneration::Immediate
+                            && !classname.starts_with("trigger_");
+                        let (convex_hull, pending_collider) = if defer_collider {
+                            let pending = PendingCollider::new(map_entity, origin, brush_vertices);
+                            (None, Some(pending))
+                        } else {
+                            let convex_hull =
+                                bevy_xpbd_3d::prelude::Collider::convex_hull(brush_vertices);
+                            (convex_hull, None)
+                        };
+                        if convex_hull.is_some() || pending_collider.is_some() {
+                            memory_usage.collider_bytes += hull_bytes;
                             let mut collider =
-                                gchildren.spawn((convex_hull, TransformBundle::default()));
-                            if classname == "trigger_multiple" {
-                                collider.insert((
-                                    TriggerMultiple {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
-                                    bevy_xpbd_3d::prelude::RigidBody::Dynamic,
-                                    bevy_xpbd_3d::prelude::Sensor,
-                                ));
-                            } else if classname == "trigger_once" {
+                                gchildren.spawn((TransformBundle::default(), brush_name(brush)));
+                            if let Some(convex_hull) = convex_hull {
+                                collider.insert(convex_hull);
+                            }
+                            if let Some(pending_collider) = pending_collider {
+                                collider.insert(pending_collider);
+                            }
+                            if is_sensor_classname(classname) {
                                 collider.insert((
-                                    TriggerOnce {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
                                     bevy_xpbd_3d::prelude::RigidBody::Dynamic,
                                     bevy_xpbd_3d::prelude::Sensor,
                                 ));
+                                configure_sensor_body(&mut collider, &build_settings.physics);
                             } else {
                                 collider.insert((bevy_xpbd_3d::prelude::RigidBody::Static,));
                             }
 
-                            for (mesh, texture_name) in meshes_to_spawn {
-                                if map_asset.material_handles.contains_key(texture_name) {
-                                    spawn_mesh_event.send(SpawnMeshEvent {
-                                        map: map_entity,
-                                        mesh: mesh,
-                                        collider: Some(collider.id()),
-                                        material: map_asset
-                                            .material_handles
-                                            .get(texture_name)
-                                            .unwrap()
-                                            .clone(),
-                                    });
-                                }
+                            insert_collider_markers(
+                                &mut collider,
+                                classname,
+                                compiled_entity.index,
+                                brush,
+                            );
+                            if spawns_logic {
+                                insert_trigger_logic(
+                                    &mut collider,
+                                    classname,
+                                    props,
+                                    map_entity,
+                                    &mut map_asset.interner,
+                                );
                             }
+
+                            #[cfg(feature = "render")]
+                            send_brush_meshes(
+                                meshes_to_spawn.take().unwrap_or_default(),
+                                collider.id(),
+                                map_entity,
+                                map_asset,
+                                build_settings,
+                                props,
+                                spawn_mesh_event,
+                            );
                         }
                     }
 
                     #[cfg(feature = "rapier")]
                     #[cfg(not(feature = "xpbd"))]
-                    {
-                        if let Some(convex_hull) =
-                            bevy_rapier3d::prelude::Collider::convex_hull(&brush_vertices)
-                        {
+                    if !is_visual_only_classname(classname) {
+                        let brush_vertices = brush_collider_vertices(brush, origin, build_settings);
+                        let hull_bytes = brush_vertices.len() * std::mem::size_of::<Vec3>();
+                        let defer_collider = build_settings.collider_generation
+                            != ColliderGeneration::Immediate
+                            && !classname.starts_with("trigger_");
+                        let (convex_hull, pending_collider) = if defer_collider {
+                            let pending = PendingCollider::new(map_entity, origin, brush_vertices);
+                            (None, Some(pending))
+                        } else {
+                            let convex_hull =
+                                bevy_rapier3d::prelude::Collider::convex_hull(&brush_vertices);
+                            (convex_hull, None)
+                        };
+                        if convex_hull.is_some() || pending_collider.is_some() {
+                            memory_usage.collider_bytes += hull_bytes;
                             let mut collider =
-                                gchildren.spawn((convex_hull, TransformBundle::default()));
-                            if classname == "trigger_multiple" {
-                                collider.insert((
-                                    TriggerMultiple {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
-                                    bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,
-                                    bevy_rapier3d::prelude::Sensor,
-                                    ActiveCollisionTypes::default()
-                                        | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
-                                ));
-                            } else if classname == "trigger_once" {
+                                gchildren.spawn((TransformBundle::default(), brush_name(brush)));
+                            if let Some(convex_hull) = convex_hull {
+                                collider.insert(convex_hull);
+                            }
+                            if let Some(pending_collider) = pending_collider {
+                                collider.insert(pending_collider);
+                            }
+                            if is_sensor_classname(classname) {
                                 collider.insert((
-                                    TriggerOnce {
-                                        target: props.get("target").unwrap().to_string(),
-                                    },
                                     bevy_rapier3d::prelude::RigidBody::KinematicPositionBased,
                                     bevy_rapier3d::prelude::Sensor,
                                     ActiveCollisionTypes::default()
                                         | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
                                 ));
+                                configure_sensor_body(&mut collider, &build_settings.physics);
                             } else {
                                 collider.insert((bevy_rapier3d::prelude::RigidBody::Fixed,));
                             }
 
-                            for (mesh, texture_name) in meshes_to_spawn {
-                                if map_asset.material_handles.contains_key(texture_name) {
-                                    spawn_mesh_event.send(SpawnMeshEvent {
-                                        map: map_entity,
-                                        mesh: mesh,
-                                        collider: Some(collider.id()),
-                                        material: map_asset
-                                            .material_handles
-                                            .get(texture_name)
-                                            .unwrap()
-                                            .clone(),
-                                    });
-                                }
+                            insert_collider_markers(
+                                &mut collider,
+                                classname,
+                                compiled_entity.index,
+                                brush,
+                            );
+                            if spawns_logic {
+                                insert_trigger_logic(
+                                    &mut collider,
+                                    classname,
+                                    props,
+                                    map_entity,
+                                    &mut map_asset.interner,
+                                );
                             }
+
+                            #[cfg(feature = "render")]
+                            send_brush_meshes(
+                                meshes_to_spawn.take().unwrap_or_default(),
+                                collider.id(),
+                                map_entity,
+                                map_asset,
+                                build_settings,
+                                props,
+                                spawn_mesh_event,
+                            );
                         }
                     }
+
+                    // brushes without a collider are drawn from the brush entity
+                    #[cfg(feature = "render")]
+                    if let Some(meshes_to_spawn) = meshes_to_spawn {
+                        send_brush_meshes(
+                            meshes_to_spawn,
+                            brush_entity_id,
+                            map_entity,
+                            map_asset,
+                            build_settings,
+                            props,
+                            spawn_mesh_event,
+                        );
+                    }
                 }
             });
 
-            if let Some(target_name) = props.get("targetname") {
+            if let Some(target_name) = props.get("targetname").filter(|_| spawns_logic) {
                 entity.insert(TriggerTarget {
-                    target_name: target_name.to_string(),
+                    target_name: map_asset.interner.intern(target_name),
                 });
             }
+
+            let toggleable = classname == "func_wall_toggle"
+                || props.contains_key("start_disabled")
+                || props.get("_toggle").map(|v| v.as_str()) == Some("1");
+            if spawns_logic && toggleable {
+                entity.insert(Toggleable {
+                    enabled: props.get("start_disabled").map(|v| v.as_str()) != Some("1"),
+                });
+            }
+
+            if spawns_logic && crate::movers::is_door(compiled_entity) {
+                entity.insert(BrushVolume::from_compiled(compiled_entity, origin));
+            }
+            if let Some(&id) = door_groups
+                .get(&compiled_entity.index)
+                .filter(|_| spawns_logic)
+            {
+                entity.insert(DoorGroup {
+                    map: map_entity,
+                    id,
+                    leader: id == compiled_entity.index,
+                });
+            }
+
+            #[cfg(feature = "render")]
+            if spawns_logic && classname == "func_wall" {
+                let swaps = texture_frame_swaps(compiled_entity, map_asset);
+                if !swaps.is_empty() {
+                    entity.insert(TextureFrames {
+                        swaps,
+                        alternate: false,
+                    });
+                }
+            }
         });
     }
 
+    debug!(elapsed = ?build_start.elapsed(), "spawned brush entities");
+
+    // spawn bezier patches, they are render only
+    #[cfg(feature = "render")]
+    for patch in compiled_map
+        .patches
+        .iter()
+        .cloned()
+        .filter(|_| spawns_geometry)
+    {
+        let Some(material) = map_asset.material_handles.get(patch.texture.as_str()) else {
+            continue;
+        };
+        let render_layers = compiled_map
+            .entities
+            .iter()
+            .find(|entity| entity.index == patch.entity)
+            .and_then(|entity| {
+                mesh_render_layers(build_settings, &entity.properties, &patch.texture)
+            });
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, patch.vertices);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, patch.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, patch.uvs);
+        mesh.insert_indices(Indices::U32(patch.indices));
+        if let Err(e) = mesh.generate_tangents() {
+            warn!(texture = %patch.texture, "error generating tangents: {:?}", e);
+        }
+
+        spawn_mesh_event.send(SpawnMeshEvent {
+            map: map_entity,
+            mesh,
+            collider: None,
+            material: material.clone(),
+            uv_projection: None,
+            texture_size: None,
+            name: Name::new(format!("patch ({})", patch.texture)),
+            render_layers,
+            faces: None,
+            foliage: is_foliage_texture(&patch.texture),
+        });
+    }
+
+    debug!(
+        elapsed = ?build_start.elapsed(),
+        patches = compiled_map.patches.len(),
+        "spawned patches"
+    );
+
+    commands.entity(map_entity).insert(map_asset.build_mode);
+    commands.entity(map_entity).insert(MapSeed(map_seed));
+    commands.entity(map_entity).insert(memory_usage);
+    commands.entity(map_entity).insert(MapBuildReport {
+        entities: compiled_map.entities.len(),
+        brushes: compiled_map
+            .entities
+            .iter()
+            .map(|entity| entity.brushes.len())
+            .sum(),
+        secrets: compiled_map
+            .entities
+            .iter()
+            .filter(|entity| entity.classname == "trigger_secret")
+            .count(),
+        build_time: build_start.elapsed(),
+        geometry: compiled_map.geometry_report.clone(),
+    });
+
+    #[cfg(feature = "render")]
+    if let Some(sun) = compiled_map
+        .entities
+        .iter()
+        .find(|entity| entity.classname == "worldspawn")
+        .filter(|_| spawns_logic)
+        .and_then(|worldspawn| sun_light(&worldspawn.properties, &metadata, build_settings, mirror))
+    {
+        commands
+            .spawn((sun, Name::new("sun")))
+            .set_parent(map_entity);
+    }
+
     post_build_map_event.send(PostBuildMapEvent { map: map_entity });
+
+    Ok(metadata)
+}
+
+/// A directional light for the `_sunlight`, `_sun_mangle` and
+/// `_sunlight_color` worldspawn properties of Quake's light tools, `None`
+/// when the map has no sun. `_sun_mangle` is "yaw pitch roll", where a pitch
+/// of -90 (the default) shines straight down.
+#[cfg(feature = "render")]
+fn sun_light(
+    props: &BTreeMap<String, String>,
+    metadata: &MapMetadata,
+    build_settings: &MapBuildSettings,
+    mirror: Option<MapMirror>,
+) -> Option<DirectionalLightBundle> {
+    let sunlight = props
+        .get("_sunlight")
+        .and_then(|value| value.trim().parse::<f32>().ok());
+    let mangle = props.get("_sun_mangle").and_then(|value| parse_vec3(value));
+    if sunlight.is_none() && mangle.is_none() || sunlight.is_some_and(|light| light <= 0.0) {
+        return None;
+    }
+
+    let mangle = mangle.unwrap_or(Vec3::new(0.0, -90.0, 0.0));
+    let (yaw, pitch) = (mangle.x.to_radians(), mangle.y.to_radians());
+    let direction = Vec3::new(
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+        yaw.cos() * pitch.cos(),
+    );
+    let direction = mirror.map_or(direction, |mirror| mirror.mirror_vec3(direction));
+    let up = if direction.abs().abs_diff_eq(Vec3::Y, 1e-4) {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+
+    let mut sun = DirectionalLightBundle {
+        transform: Transform::default().looking_to(direction, up),
+        directional_light: DirectionalLight {
+            color: props
+                .get("_sunlight_color")
+                .and_then(|value| parse_color(value))
+                .map_or(Color::WHITE, |(color, _)| color),
+            illuminance: sunlight.map_or(10000.0, |light| {
+                build_settings.lights.directional_illuminance(light)
+            }),
+            shadows_enabled: true,
+            ..default()
+        },
+        ..default()
+    };
+    sun.cascade_shadow_config = cascade_shadow_config(metadata.bounds, None, None);
+    Some(sun)
+}
+
+/// Shadow cascades of a directional light, reaching `shadow_distance` in
+/// Bevy units with `cascade_count` cascades. Without a distance the cascades
+/// cover the whole of the map's bounds, since Bevy's defaults spread too few
+/// texels over large brush maps.
+#[cfg(feature = "render")]
+fn cascade_shadow_config(
+    bounds: Option<Aabb3d>,
+    shadow_distance: Option<f32>,
+    cascade_count: Option<usize>,
+) -> CascadeShadowConfig {
+    let mut builder = CascadeShadowConfigBuilder::default();
+    let fitted_distance = bounds.map(|bounds| (bounds.max - bounds.min).length());
+    if let Some(distance) = shadow_distance.or(fitted_distance) {
+        let distance = distance.max(1.0);
+        builder.minimum_distance = distance * 0.001;
+        builder.maximum_distance = distance;
+        builder.first_cascade_far_bound = distance * 0.05;
+    }
+    if let Some(cascade_count) = cascade_count {
+        builder.num_cascades = cascade_count.max(1);
+    }
+    builder.build()
+}
+
+/// The entity's `_render_layers` property, or the layers of its texture in
+/// [`MapBuildSettings::texture_render_layers`].
+#[cfg(feature = "render")]
+fn mesh_render_layers(
+    build_settings: &MapBuildSettings,
+    props: &BTreeMap<String, String>,
+    texture_name: &str,
+) -> Option<RenderLayers> {
+    match props.get("_render_layers") {
+        Some(value) => parse_render_layers(value),
+        None => build_settings.texture_render_layers(texture_name),
+    }
+}
+
+/// e.g. `light @ (1.0, 2.0, 3.0)`
+fn point_entity_name(classname: &str, translation: Vec3) -> Name {
+    Name::new(format!(
+        "{} @ ({:.1}, {:.1}, {:.1})",
+        classname, translation.x, translation.y, translation.z
+    ))
+}
+
+/// e.g. `func_door door1` or `trigger_once → door1`
+fn brush_entity_name(classname: &str, props: &BTreeMap<String, String>) -> Name {
+    let mut name = classname.to_string();
+    if let Some(target_name) = props.get("targetname") {
+        name.push_str(&format!(" {}", target_name));
+    }
+    if let Some(target) = props.get("target") {
+        name.push_str(&format!(" → {}", target));
+    }
+    Name::new(name)
+}
+
+/// e.g. `brush 14 (metal/wall01)`, with the brush's most used texture
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn brush_name(brush: &crate::core::CompiledBrush) -> Name {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for face in brush.faces.iter() {
+        *counts.entry(face.texture.as_str()).or_default() += 1;
+    }
+    match counts.into_iter().max_by_key(|(_, count)| *count) {
+        Some((texture, _)) => Name::new(format!("brush {} ({})", brush.index, texture)),
+        None => Name::new(format!("brush {}", brush.index)),
+    }
+}
+
+/// The simplified hull of a brush relative to `origin`, for its collider.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn brush_collider_vertices(
+    brush: &crate::core::CompiledBrush,
+    origin: Vec3,
+    build_settings: &MapBuildSettings,
+) -> Vec<Vec3> {
+    simplify_hull(brush, &build_settings.physics.hull_simplification)
+        .into_iter()
+        .map(|vertex| vertex - origin)
+        .collect()
+}
+
+/// The meshes of a brush relative to `origin`, with the texture, world-locked
+/// UV projection and drawn faces of each.
+#[cfg(feature = "render")]
+fn brush_meshes(
+    brush: &crate::core::CompiledBrush,
+    origin: Vec3,
+    brush_entity: Entity,
+    uv_lock: UvLock,
+    build_settings: &MapBuildSettings,
+) -> Vec<(Mesh, String, Option<UvProjection>, BrushFaceMesh)> {
+    let mut meshes_to_spawn = Vec::new();
+
+    let merged = (build_settings.brush_mesh_mode == BrushMeshMode::PerBrush
+        && uv_lock == UvLock::Entity)
+        .then(|| brush.merged_mesh());
+    if let Some(merged) = merged.as_ref() {
+        // bevy meshes can't draw a sub range of a shared buffer,
+        // so each texture range gets a mesh with its own slice
+        for range in merged.ranges.iter() {
+            let mut mesh = Mesh::new(
+                PrimitiveTopology::TriangleList,
+                RenderAssetUsages::RENDER_WORLD,
+            );
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                merged.vertices[range.vertices.clone()]
+                    .iter()
+                    .map(|vertex| *vertex - origin)
+                    .collect::<Vec<_>>(),
+            );
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_NORMAL,
+                merged.normals[range.vertices.clone()].to_vec(),
+            );
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_UV_0,
+                merged.uvs[range.vertices.clone()].to_vec(),
+            );
+            mesh.insert_indices(Indices::U32(
+                merged.indices[range.indices.clone()]
+                    .iter()
+                    .map(|index| index - range.vertices.start as u32)
+                    .collect(),
+            ));
+            if let Err(e) = mesh.generate_tangents() {
+                warn!(
+                    brush = brush.index,
+                    texture = %range.texture,
+                    "error generating tangents: {:?}",
+                    e
+                );
+            }
+            let faces = BrushFaceMesh {
+                brush_entity,
+                faces: range.faces.clone(),
+            };
+            meshes_to_spawn.push((mesh, range.texture.clone(), None, faces));
+        }
+    }
+
+    for face in brush.faces.iter().filter(|_| merged.is_none()) {
+        let texture_name = &face.texture;
+
+        // we don't render anything for these textures
+        if is_nodraw_texture(texture_name) {
+            continue;
+        }
+
+        let uv_projection = match uv_lock {
+            UvLock::Entity => None,
+            UvLock::World => face.uv_projection(),
+        };
+
+        // world-locked UVs are recomputed in place as the brush moves, so
+        // those meshes are kept in the main world. The others only live in
+        // the render world, and are rebuilt from the brush's faces when
+        // their texture changes size, see `rescale_brush_uvs_system`
+        let usages = match uv_projection {
+            Some(_) => RenderAssetUsages::default(),
+            None => RenderAssetUsages::RENDER_WORLD,
+        };
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, usages);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            face.vertices
+                .iter()
+                .map(|vertex| *vertex - origin)
+                .collect::<Vec<_>>(),
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, face.normals.clone());
+        mesh.insert_indices(Indices::U32(face.indices.clone()));
+
+        if face.uvs.len() > 0 {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, face.uvs.clone());
+            if let Err(e) = mesh.generate_tangents() {
+                warn!(
+                    brush = brush.index,
+                    face = face.index,
+                    texture = %texture_name,
+                    "error generating tangents: {:?}",
+                    e
+                );
+            }
+        }
+
+        let faces = BrushFaceMesh {
+            brush_entity,
+            faces: vec![face.index],
+        };
+        meshes_to_spawn.push((mesh, texture_name.clone(), uv_projection, faces));
+    }
+
+    meshes_to_spawn
+}
+
+/// Sends a [`SpawnMeshEvent`] for each mesh of a brush whose texture has a
+/// material, parented to `parent`.
+#[cfg(feature = "render")]
+fn send_brush_meshes(
+    meshes_to_spawn: Vec<(Mesh, String, Option<UvProjection>, BrushFaceMesh)>,
+    parent: Entity,
+    map_entity: Entity,
+    map_asset: &MapAsset,
+    build_settings: &MapBuildSettings,
+    props: &BTreeMap<String, String>,
+    spawn_mesh_event: &mut EventWriter<SpawnMeshEvent>,
+) {
+    for (mesh, texture_name, uv_projection, faces) in meshes_to_spawn {
+        let Some(material) = map_asset.material_handles.get(texture_name.as_str()) else {
+            continue;
+        };
+        spawn_mesh_event.send(SpawnMeshEvent {
+            map: map_entity,
+            mesh,
+            collider: Some(parent),
+            uv_projection,
+            texture_size: map_asset
+                .texture_sizes
+                .get(texture_name.as_str())
+                .map(|(w, h)| UVec2::new(*w, *h)),
+            name: mesh_name(&faces.faces, &texture_name),
+            render_layers: mesh_render_layers(build_settings, props, &texture_name),
+            faces: Some(faces),
+            foliage: is_foliage_texture(&texture_name),
+            material: material.clone(),
+        });
+    }
+}
+
+/// e.g. `face 83 (metal/wall01)`, or `faces (metal/wall01)` for meshes of several faces
+#[cfg(feature = "render")]
+pub(crate) fn mesh_name(faces: &[usize], texture_name: &str) -> Name {
+    match faces {
+        [face] => Name::new(format!("face {} ({})", face, texture_name)),
+        _ => Name::new(format!("faces ({})", texture_name)),
+    }
+}
+
+/// The materials a `func_wall` swaps between, see [`TextureFrames`].
+#[cfg(feature = "render")]
+fn texture_frame_swaps(
+    compiled_entity: &CompiledEntity,
+    map_asset: &MapAsset,
+) -> Vec<(Handle<StandardMaterial>, Handle<StandardMaterial>)> {
+    let material = |name: &str| map_asset.material_handles.get(name).cloned();
+    let props = &compiled_entity.properties;
+    if let (Some(on), Some(off)) = (props.get("texture_on"), props.get("texture_off")) {
+        return material(on).zip(material(off)).into_iter().collect();
+    }
+
+    let textures = compiled_entity
+        .brushes
+        .iter()
+        .flat_map(|brush| brush.faces.iter())
+        .map(|face| face.texture.as_str())
+        .collect::<BTreeSet<_>>();
+    textures
+        .into_iter()
+        .filter_map(|texture| {
+            // `+0name` animation frames toggle to their `+aname` alternates
+            let (dir, name) = texture.rsplit_once('/').unwrap_or(("", texture));
+            let mut chars = name.chars();
+            if chars.next() != Some('+') || !chars.next()?.is_ascii_digit() {
+                return None;
+            }
+            let alternate = match dir {
+                "" => format!("+a{}", chars.as_str()),
+                dir => format!("{}/+a{}", dir, chars.as_str()),
+            };
+            material(texture).zip(material(&alternate))
+        })
+        .collect()
+}
+
+/// Brush entities that are drawn but never collide.
+fn is_visual_only_classname(classname: &str) -> bool {
+    matches!(classname, "func_illusionary" | "func_liquid")
+}
+
+/// Brush classnames spawned with a [`BrushVolume`].
+pub(crate) fn is_volume_classname(classname: &str) -> bool {
+    matches!(
+        classname,
+        "func_fog_volume" | "func_liquid" | "trigger_hurt"
+    )
+}
+
+/// Brush classnames whose colliders are sensors.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn is_sensor_classname(classname: &str) -> bool {
+    matches!(
+        classname,
+        "trigger_multiple"
+            | "trigger_once"
+            | "trigger_secret"
+            | "trigger_objective_complete"
+            | "trigger_cutscene"
+            | "trigger_pressure"
+            | "trigger_look"
+    )
 }
 
+/// Inserts the gameplay components of a trigger collider, skipped with
+/// [`MapBuildMode::GeometryOnly`].
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn insert_trigger_logic(
+    collider: &mut bevy::ecs::system::EntityCommands,
+    classname: &str,
+    props: &BTreeMap<String, String>,
+    map_entity: Entity,
+    interner: &mut crate::intern::Interner,
+) {
+    let target = interner.intern(props.get("target").map_or("", |t| t.as_str()));
+    let requires_use = props
+        .get("spawnflags")
+        .and_then(|flags| flags.trim().parse::<i32>().ok())
+        .is_some_and(|flags| flags & crate::interaction::REQUIRES_USE != 0);
+    if requires_use && matches!(classname, "trigger_multiple" | "trigger_once") {
+        collider.insert(crate::interaction::RequiresUse);
+    }
+    match classname {
+        "trigger_multiple" => {
+            collider.insert(TriggerMultiple { target });
+        }
+        "trigger_once" | "trigger_secret" | "trigger_objective_complete" | "trigger_cutscene" => {
+            collider.insert(TriggerOnce { target });
+        }
+        "trigger_look" => {
+            let property = |key: &str, default: f32| {
+                props
+                    .get(key)
+                    .and_then(|value| value.trim().parse::<f32>().ok())
+                    .unwrap_or(default)
+            };
+            collider.insert(TriggerLook {
+                target,
+                look_target: interner.intern(props.get("look_target").map_or("", |t| t.as_str())),
+                angle: property("look_angle", 15.0).to_radians(),
+                duration: property("look_time", 0.5).max(0.0),
+                once: props.get("once").map(|v| v.as_str()) != Some("0"),
+                looking: Vec::new(),
+            });
+        }
+        "trigger_pressure" => {
+            collider.insert(TriggerPressure {
+                target,
+                target_release: interner
+                    .intern(props.get("target_release").map_or("", |t| t.as_str())),
+                required_count: props
+                    .get("required_count")
+                    .and_then(|count| count.trim().parse::<usize>().ok())
+                    .unwrap_or(1),
+                required_mass: props
+                    .get("required_mass")
+                    .and_then(|mass| mass.trim().parse::<f32>().ok()),
+                ..default()
+            });
+        }
+        _ => {}
+    }
+
+    if classname == "trigger_secret" {
+        collider.insert(Secret { map: map_entity });
+    }
+    if classname == "trigger_cutscene" {
+        collider.insert(crate::cutscenes::CutsceneTrigger {
+            timeline: props.get("timeline").cloned().unwrap_or_default(),
+        });
+        if let Some(targets) = props.get("targets") {
+            collider.insert(crate::cutscenes::Sequencer::parse(
+                targets,
+                props.get("delays").map(|d| d.as_str()),
+            ));
+        }
+    }
+}
+
+/// Marks trigger and clip brush colliders, see [`TriggerVolume`] and
+/// [`ClipGeometry`], and inserts the [`ColliderSource`] of every collider.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn insert_collider_markers(
+    collider: &mut bevy::ecs::system::EntityCommands,
+    classname: &str,
+    entity_index: usize,
+    brush: &crate::core::CompiledBrush,
+) {
+    collider.insert(ColliderSource {
+        brush_id: brush.index,
+        entity_id: entity_index,
+        face_count: brush.faces.len(),
+    });
+    if classname.starts_with("trigger_") {
+        collider.insert((TriggerVolume, TriggerOccupancy::default()));
+    } else if !brush.faces.is_empty()
+        && brush
+            .faces
+            .iter()
+            .all(|face| is_clip_texture(&face.texture))
+    {
+        collider.insert(ClipGeometry);
+    }
+}
+
+#[cfg(feature = "render")]
 pub fn mesh_spawn_system(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut spawn_mesh_event: EventReader<SpawnMeshEvent>,
+    mut q_memory_usage: Query<&mut MapMemoryUsage>,
 ) {
     for ev in spawn_mesh_event.read() {
-        // if this mesh has a collider, make it a child of the collider
-        if let Some(collider) = ev.collider {
-            commands.entity(collider).with_children(|children| {
-                children.spawn(PbrBundle {
-                    mesh: meshes.add(ev.mesh.to_owned()),
-                    material: ev.material.to_owned(),
-                    ..default()
-                });
-            });
+        if let Ok(mut memory_usage) = q_memory_usage.get_mut(ev.map) {
+            memory_usage.add_mesh(&ev.mesh);
+        }
+        // if this mesh has a collider, make it a child of the collider,
         // otherwise, it's a child of the map
-        } else {
-            commands.entity(ev.map).with_children(|children| {
-                children.spawn(PbrBundle {
+        let parent = ev.collider.unwrap_or(ev.map);
+        commands.entity(parent).with_children(|children| {
+            let mut mesh_entity = children.spawn((
+                PbrBundle {
                     mesh: meshes.add(ev.mesh.to_owned()),
                     material: ev.material.to_owned(),
                     ..default()
+                },
+                ev.name.clone(),
+                MapMesh,
+            ));
+            if let Some(projection) = ev.uv_projection {
+                mesh_entity.insert(WorldLockedUvs {
+                    map: ev.map,
+                    projection,
                 });
-            });
+            }
+            if let Some(texture_size) = ev.texture_size {
+                mesh_entity.insert(Brush {
+                    texture_size,
+                    authored_texture_size: texture_size,
+                });
+            }
+            if let Some(faces) = ev.faces.clone() {
+                mesh_entity.insert(faces);
+            }
+            if ev.foliage {
+                mesh_entity.insert(FoliageGeometry);
+            }
+            if let Some(render_layers) = ev.render_layers {
+                mesh_entity.insert(render_layers);
+            }
+        });
+    }
+}
+
+/// Recomputes the UVs of world-locked meshes when they move relative to their map.
+#[cfg(feature = "render")]
+pub fn world_locked_uvs_system(
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_maps: Query<&GlobalTransform, With<Map>>,
+    q_meshes: Query<(&WorldLockedUvs, &Handle<Mesh>, &GlobalTransform), Changed<GlobalTransform>>,
+) {
+    for (world_locked_uvs, handle, transform) in q_meshes.iter() {
+        let Ok(map_transform) = q_maps.get(world_locked_uvs.map) else {
+            continue;
+        };
+        let Some(mesh) = meshes.get_mut(handle) else {
+            continue;
+        };
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            continue;
+        };
+
+        let to_map = map_transform.affine().inverse() * transform.affine();
+        let uvs = positions
+            .iter()
+            .map(|position| {
+                world_locked_uvs
+                    .projection
+                    .project(to_map.transform_point3(Vec3::from(*position)))
+                    .to_array()
+            })
+            .collect::<Vec<[f32; 2]>>();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    }
+}
+
+/// Multiplies the UVs of a mesh by `scale`.
+#[cfg(feature = "render")]
+pub fn rescale_uvs(mesh: &mut Mesh, scale: Vec2) {
+    if let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+        for uv in uvs.iter_mut() {
+            uv[0] *= scale.x;
+            uv[1] *= scale.y;
         }
     }
 }
 
+/// Rescales the UVs of brush meshes whose material texture doesn't have the
+/// size they were computed against anymore, e.g. after swapping in an HD texture.
+/// Meshes only kept in the render world are rebuilt from their brush's faces.
+#[cfg(feature = "render")]
+pub fn rescale_brush_uvs_system(
+    mut meshes: ResMut<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    images: Res<Assets<Image>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut material_events: EventReader<AssetEvent<StandardMaterial>>,
+    mut q_brushes: Query<(
+        Ref<Handle<StandardMaterial>>,
+        &Handle<Mesh>,
+        &mut Brush,
+        Option<&mut WorldLockedUvs>,
+        Option<&BrushFaceMesh>,
+    )>,
+    q_brush_faces: Query<&crate::picking::BrushFaces>,
+) {
+    let images_changed = image_events.read().count() > 0;
+    let materials_changed = material_events.read().count() > 0;
+
+    for (material, mesh, mut brush, world_locked_uvs, face_mesh) in q_brushes.iter_mut() {
+        if !images_changed && !materials_changed && !material.is_changed() {
+            continue;
+        }
+        let Some(size) = materials
+            .get(material.id())
+            .and_then(|material| material.base_color_texture.as_ref())
+            .and_then(|texture| images.get(texture))
+            .map(|image| image.size())
+        else {
+            continue;
+        };
+        if size == brush.texture_size || size.cmpeq(UVec2::ZERO).any() {
+            continue;
+        }
+
+        let scale = brush.texture_size.as_vec2() / size.as_vec2();
+        match meshes.get_mut(mesh) {
+            Some(mesh) => rescale_uvs(mesh, scale),
+            None => {
+                let Some(mut rebuilt) = face_mesh.and_then(|face_mesh| {
+                    let brush_faces = q_brush_faces.get(face_mesh.brush_entity).ok()?;
+                    Some(brush_faces.mesh(&face_mesh.faces))
+                }) else {
+                    continue;
+                };
+                rescale_uvs(
+                    &mut rebuilt,
+                    brush.authored_texture_size.as_vec2() / size.as_vec2(),
+                );
+                meshes.insert(mesh.id(), rebuilt);
+            }
+        }
+        if let Some(mut world_locked_uvs) = world_locked_uvs {
+            world_locked_uvs.projection = world_locked_uvs.projection.scaled(scale);
+        }
+        brush.texture_size = size;
+    }
+}
+
 pub fn post_build_map_system(
     map_units: Res<MapUnits>,
+    build_settings: Res<MapBuildSettings>,
     mut commands: Commands,
     mut event_reader: EventReader<crate::PostBuildMapEvent>,
-    mut map_entities: Query<(Entity, &crate::components::MapEntityProperties)>,
+    map_entities: Query<&crate::components::MapEntityProperties>,
+    q_children: Query<&Children>,
+    q_parents: Query<&Parent>,
+    q_scales: Query<&MapScale>,
+    q_build_modes: Query<&MapBuildMode>,
+    q_maps: Query<&Map>,
 ) {
-    for _ in event_reader.read() {
+    for ev in event_reader.read() {
         // to set these up, see the .fgd file in the TrenchBroom
         // game folder for Qevy Example also see the readme
-        for (entity, props) in map_entities.iter_mut() {
-            match props.classname.as_str() {
-                "light" => {
-                    commands.entity(entity).insert(PointLightBundle {
-                        transform: props.transform,
-                        point_light: PointLight {
-                            color: props.get_property_as_color("color", Color::WHITE),
-                            radius: props.get_property_as_f32("radius", 0.0),
-                            range: props.get_property_as_f32("range", 10.0),
-                            intensity: props.get_property_as_f32("intensity", 800.0),
-                            shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
-                            ..default()
-                        },
-                        ..default()
-                    });
-                }
-                "directional_light" => {
-                    commands.entity(entity).insert(DirectionalLightBundle {
-                        transform: props.transform,
-                        directional_light: DirectionalLight {
-                            color: props.get_property_as_color("color", Color::WHITE),
-                            illuminance: props.get_property_as_f32("illuminance", 10000.0),
-                            shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
-                            ..default()
-                        },
-                        ..default()
-                    });
-                }
-                "mover" => {
-                    let mut mover_entity = commands.entity(entity);
-                    mover_entity.insert((
-                        Mover {
-                            moving_time: Duration::from_secs_f32(
-                                props.get_property_as_f32("moving_time", 1.0),
-                            ),
-                            destination_time: Duration::from_secs_f32(
+        for entity in q_children.iter_descendants(ev.map) {
+            let Ok(props) = map_entities.get(entity) else {
+                continue;
+            };
+            // entities of nested maps are set up when their own map is built
+            let owner = q_parents
+                .iter_ancestors(entity)
+                .find(|&ancestor| q_maps.contains(ancestor));
+            if owner != Some(ev.map) {
+                continue;
+            }
+            if !map_build_mode_of(entity, &q_parents, &q_build_modes).spawns_logic() {
+                continue;
+            }
+            let scale = map_scale_of(entity, &q_parents, &q_scales);
+            let bounds = map_bounds_of(entity, &q_parents, &q_maps);
+            apply_entity_properties(
+                &map_units,
+                &build_settings,
+                scale,
+                bounds,
+                &mut commands,
+                entity,
+                props,
+            );
+        }
+    }
+}
+
+/// The [`MapScale`] of the map an entity belongs to.
+pub fn map_scale_of(
+    entity: Entity,
+    q_parents: &Query<&Parent>,
+    q_scales: &Query<&MapScale>,
+) -> f32 {
+    q_parents
+        .iter_ancestors(entity)
+        .find_map(|ancestor| q_scales.get(ancestor).ok())
+        .map_or(1.0, |scale| scale.0)
+}
+
+/// The bounds of the map an entity belongs to, see [`Map::bounds`].
+pub fn map_bounds_of(
+    entity: Entity,
+    q_parents: &Query<&Parent>,
+    q_maps: &Query<&Map>,
+) -> Option<Aabb3d> {
+    q_parents
+        .iter_ancestors(entity)
+        .find_map(|ancestor| q_maps.get(ancestor).ok())
+        .and_then(|map| map.bounds())
+}
+
+/// The `color` of a light entity, or Quake's `_color` or Half-Life's `_light`.
+#[cfg(feature = "render")]
+fn light_color(props: &MapEntityProperties) -> Color {
+    ["color", "_color", "_light"]
+        .iter()
+        .find_map(|key| props.get_property_as_color_intensity(key))
+        .map_or(Color::WHITE, |(color, _)| color)
+}
+
+/// Quake's `light` brightness of a light entity, or the intensity in the
+/// fourth component of its `_light` color.
+#[cfg(feature = "render")]
+fn quake_light_value(props: &MapEntityProperties) -> Option<f32> {
+    props
+        .properties
+        .get("light")
+        .and_then(|light| light.trim().parse::<f32>().ok())
+        .or_else(|| {
+            props
+                .get_property_as_color_intensity("_light")
+                .and_then(|(_, intensity)| intensity)
+        })
+}
+
+/// The [`MapBuildMode`] of the map an entity belongs to.
+pub fn map_build_mode_of(
+    entity: Entity,
+    q_parents: &Query<&Parent>,
+    q_build_modes: &Query<&MapBuildMode>,
+) -> MapBuildMode {
+    q_parents
+        .iter_ancestors(entity)
+        .find_map(|ancestor| q_build_modes.get(ancestor).ok())
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Re-runs the property → component mapping of [`post_build_map_system`]
+/// for entities whose [`MapEntityProperties`] were changed at runtime.
+pub fn reapply_entity_properties_system(
+    map_units: Res<MapUnits>,
+    build_settings: Res<MapBuildSettings>,
+    mut commands: Commands,
+    mut event_reader: EventReader<crate::ReapplyEntityPropertiesEvent>,
+    map_entities: Query<&crate::components::MapEntityProperties>,
+    q_parents: Query<&Parent>,
+    q_scales: Query<&MapScale>,
+    q_build_modes: Query<&MapBuildMode>,
+    q_maps: Query<&Map>,
+) {
+    for ev in event_reader.read() {
+        if let Ok(props) = map_entities.get(ev.0) {
+            if !map_build_mode_of(ev.0, &q_parents, &q_build_modes).spawns_logic() {
+                continue;
+            }
+            let scale = map_scale_of(ev.0, &q_parents, &q_scales);
+            let bounds = map_bounds_of(ev.0, &q_parents, &q_maps);
+            apply_entity_properties(
+                &map_units,
+                &build_settings,
+                scale,
+                bounds,
+                &mut commands,
+                ev.0,
+                props,
+            );
+        }
+    }
+}
+
+/// Classnames with built-in components or geometry handling.
+pub const BUILT_IN_CLASSNAMES: &[&str] = &[
+    "worldspawn",
+    "func_group",
+    "func_wall",
+    "func_wall_toggle",
+    "func_illusionary",
+    "func_liquid",
+    "func_fog_volume",
+    "func_spawner",
+    "func_timer",
+    "light",
+    "directional_light",
+    "mover",
+    "info_camera",
+    "info_objective",
+    "path_corner",
+    "prop_static",
+    "target_sequence",
+    "target_speaker",
+    "misc_external_map",
+    "trigger_multiple",
+    "trigger_once",
+    "trigger_secret",
+    "trigger_objective_complete",
+    "trigger_cutscene",
+    "trigger_pressure",
+    "trigger_look",
+    "trigger_hurt",
+];
+
+/// Whether qevy handles entities of `classname` itself, see
+/// [`BUILT_IN_CLASSNAMES`]. Items are any `item_` classname.
+pub fn is_built_in_classname(classname: &str) -> bool {
+    BUILT_IN_CLASSNAMES.contains(&classname) || crate::items::is_item_classname(classname)
+}
+
+/// Inserts the built-in components for an entity based on its classname and properties.
+/// Distances are multiplied by `scale`, the [`MapScale`] of the entity's map,
+/// and directional light shadows fitted to its `bounds`.
+#[cfg_attr(not(feature = "render"), allow(unused_variables))]
+pub fn apply_entity_properties(
+    map_units: &MapUnits,
+    build_settings: &MapBuildSettings,
+    scale: f32,
+    bounds: Option<Aabb3d>,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    match props.classname.as_str() {
+        #[cfg(feature = "render")]
+        "light" => {
+            let lights = &build_settings.lights;
+            let light = quake_light_value(props);
+            let range = light.map_or(10.0, |light| lights.point_range(light, map_units, scale));
+            let intensity = light.map_or(800.0, |light| {
+                lights.point_intensity(light, map_units, scale)
+            });
+            commands.entity(entity).insert(PointLightBundle {
+                transform: props.transform,
+                point_light: PointLight {
+                    color: light_color(props),
+                    radius: props.get_property_as_f32("radius", 0.0),
+                    range: props.get_property_as_f32("range", range),
+                    intensity: props.get_property_as_f32("intensity", intensity),
+                    shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
+                    ..default()
+                },
+                ..default()
+            });
+        }
+        #[cfg(feature = "render")]
+        "directional_light" => {
+            commands.entity(entity).insert(DirectionalLightBundle {
+                transform: props.transform,
+                directional_light: DirectionalLight {
+                    color: light_color(props),
+                    illuminance: props.get_property_as_f32(
+                        "illuminance",
+                        quake_light_value(props).map_or(10000.0, |light| {
+                            build_settings.lights.directional_illuminance(light)
+                        }),
+                    ),
+                    shadows_enabled: props.get_property_as_bool("shadows_enabled", false),
+                    ..default()
+                },
+                cascade_shadow_config: cascade_shadow_config(
+                    bounds,
+                    props
+                        .properties
+                        .get("shadow_distance")
+                        .and_then(|value| value.trim().parse::<f32>().ok()),
+                    props
+                        .properties
+                        .get("cascade_count")
+                        .and_then(|value| value.trim().parse::<usize>().ok()),
+                ),
+                ..default()
+            });
+        }
+        "mover" => {
+            let mut mover_entity = commands.entity(entity);
+            mover_entity.insert((
+                Mover {
+                    moving_time: Duration::from_secs_f32(
+                        props.get_property_as_f32("moving_time", 1.0),
+                    ),
+                    // `wait` is the Quake name, negative for doors that stay open
+                    destination_time: Duration::from_secs_f32(
+                        props
+                            .get_property_as_f32(
+                                "wait",
                                 props.get_property_as_f32("destination_time", 2.0),
-                            ),
-                            destination_offset: {
-                                to_bevy_position(
-                                    &props.get_property_as_vec3("destination_offset", Vec3::ZERO),
-                                    &map_units,
-                                )
-                            },
-                            state: MoverState::default(),
-                        },
-                        TransformBundle {
-                            local: Transform::from_xyz(0.0, 0.0, 0.0),
-                            ..default()
-                        },
-                    ));
+                            )
+                            .max(0.0),
+                    ),
+                    destination_offset: {
+                        to_bevy_position(
+                            &props.get_property_as_vec3("destination_offset", Vec3::ZERO),
+                            &map_units,
+                        ) * scale
+                    },
+                    state: MoverState::default(),
+                },
+                MoverSync::default(),
+                TransformBundle {
+                    local: props.transform,
+                    ..default()
+                },
+            ));
 
-                    if let Some(mover_kind) =
-                        props.get_property_as_string("mover_kind", Some(&"linear".into()))
-                    {
-                        match mover_kind.as_str() {
-                            "door" => {
-                                mover_entity.insert(Door {
-                                    key: props.get_property_as_string("key", None).into(),
-                                    open_once: props.get_property_as_bool("open_once", false),
-                                });
-                            }
-                            _ => {}
-                        }
+            if let Some(mover_kind) =
+                props.get_property_as_string("mover_kind", Some(&"linear".into()))
+            {
+                match mover_kind.as_str() {
+                    "momentary" => {
+                        mover_entity.insert(MomentaryMover::default());
+                    }
+                    "door" => {
+                        let distance = |value: f32| to_bevy_distance(value, map_units) * scale;
+                        mover_entity.insert(Door {
+                            key: props.get_property_as_string("key", None).into(),
+                            open_once: props.get_property_as_bool("open_once", false)
+                                || props.get_property_as_f32("wait", 0.0) < 0.0,
+                            reopen_on_block: props.get_property_as_bool("reopen_on_block", true),
+                            block_margin: distance(props.get_property_as_f32("block_margin", 16.0)),
+                            open_radius: props
+                                .properties
+                                .contains_key("open_radius")
+                                .then(|| distance(props.get_property_as_f32("open_radius", 0.0))),
+                        });
                     }
+                    _ => {}
                 }
-                _ => {}
             }
         }
+        "info_camera" | "path_corner" => {
+            crate::camera_paths::apply_camera_path_properties(
+                map_units, scale, commands, entity, props,
+            );
+        }
+        "target_sequence" => {
+            crate::cutscenes::apply_sequence_properties(commands, entity, props);
+        }
+        "func_spawner" => {
+            crate::spawners::apply_spawner_properties(map_units, scale, commands, entity, props);
+        }
+        "func_liquid" => {
+            crate::liquids::apply_liquid_properties(map_units, scale, commands, entity, props);
+            if props.properties.contains_key("damage") {
+                crate::hazards::apply_hazard_properties(commands, entity, props);
+            }
+        }
+        "trigger_hurt" => {
+            crate::hazards::apply_hazard_properties(commands, entity, props);
+        }
+        #[cfg(feature = "render")]
+        "func_fog_volume" => {
+            crate::fog::apply_fog_volume_properties(map_units, scale, commands, entity, props);
+        }
+        #[cfg(feature = "render")]
+        "prop_static" => {
+            crate::props::apply_static_prop_properties(build_settings, commands, entity, props);
+        }
+        "func_timer" => {
+            crate::timers::apply_timer_properties(commands, entity, props);
+        }
+        "target_speaker" => {
+            crate::speakers::apply_speaker_properties(commands, entity, props);
+        }
+        "info_objective" => {
+            crate::objectives::apply_objective_properties(commands, entity, props);
+        }
+        classname if crate::items::is_item_classname(classname) => {
+            crate::items::apply_item_properties(map_units, scale, commands, entity, props);
+        }
+        _ => {}
     }
+
+    crate::damage::apply_damageable_properties(commands, entity, props);
+    crate::interaction::apply_interaction_properties(map_units, scale, commands, entity, props);
+    #[cfg(feature = "render")]
+    crate::blend::apply_terrain_blend_properties(map_units, scale, commands, entity, props);
 }
@@ -0,0 +1,215 @@
+//! Cinematic camera paths.
+//!
+//! An `info_camera` starts a path: its `target` is the first `path_corner`,
+//! whose own `target` is the next one, and so on. The camera moves along the
+//! path at `speed` map units per second (overridable per corner), with an
+//! optional `fov` in degrees, and looks at the entity named by `look_at` or
+//! along the path otherwise. The whole flythrough is eased in and out.
+//!
+//! Send a [`PlayCameraPathEvent`] with the `info_camera`'s `targetname` to
+//! animate a camera entity.
+
+use bevy::prelude::*;
+
+use crate::components::{MapEntityProperties, MapSpatialBundle, MapUnits, TriggerTarget};
+use crate::conversions::to_bevy_distance;
+use crate::intern::InternedStr;
+
+#[derive(Event, Debug, Clone)]
+pub struct PlayCameraPathEvent {
+    /// `targetname` of the `info_camera`
+    pub name: InternedStr,
+    pub camera: Entity,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct CameraPathFinishedEvent {
+    pub name: InternedStr,
+    pub camera: Entity,
+}
+
+/// A point of a camera path: `info_camera` or `path_corner`.
+#[derive(Component, Debug, Clone)]
+pub struct CameraPathNode {
+    /// speed towards the next node, in Bevy units per second
+    pub speed: f32,
+    /// vertical field of view, in radians
+    pub fov: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+struct PathPoint {
+    translation: Vec3,
+    fov: Option<f32>,
+    /// time at which the camera reaches this point, before easing
+    time: f32,
+}
+
+/// Inserted on a camera while it plays a path.
+#[derive(Component, Debug, Clone)]
+pub struct CameraPathPlayer {
+    pub name: InternedStr,
+    pub elapsed: f32,
+    points: Vec<PathPoint>,
+    look_at: Option<Vec3>,
+}
+
+impl CameraPathPlayer {
+    pub fn duration(&self) -> f32 {
+        self.points.last().map_or(0.0, |point| point.time)
+    }
+
+    fn sample(&self, time: f32) -> (Vec3, Option<f32>, Vec3) {
+        let next = self
+            .points
+            .iter()
+            .position(|point| point.time > time)
+            .unwrap_or(self.points.len() - 1)
+            .max(1);
+        let (from, to) = (&self.points[next - 1], &self.points[next]);
+        let span = to.time - from.time;
+        let t = if span > 0.0 {
+            ((time - from.time) / span).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let fov = match (from.fov, to.fov) {
+            (Some(a), Some(b)) => Some(a + (b - a) * t),
+            (a, b) => a.or(b),
+        };
+        (
+            from.translation.lerp(to.translation, t),
+            fov,
+            to.translation - from.translation,
+        )
+    }
+}
+
+/// Inserts the [`CameraPathNode`] component for `info_camera` and `path_corner` entities.
+pub fn apply_camera_path_properties(
+    map_units: &MapUnits,
+    scale: f32,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let fov = props.get_property_as_f32("fov", 0.0);
+    commands.entity(entity).insert((
+        CameraPathNode {
+            speed: to_bevy_distance(props.get_property_as_f32("speed", 100.0), map_units) * scale,
+            fov: (fov > 0.0).then(|| fov.to_radians()),
+        },
+        MapSpatialBundle::from_transform(props.transform),
+    ));
+}
+
+pub fn play_camera_path_system(
+    mut commands: Commands,
+    mut play_events: EventReader<PlayCameraPathEvent>,
+    q_nodes: Query<(
+        &TriggerTarget,
+        &MapEntityProperties,
+        &CameraPathNode,
+        &GlobalTransform,
+    )>,
+    q_targets: Query<(&TriggerTarget, &GlobalTransform)>,
+) {
+    for ev in play_events.read() {
+        let find_node = |name: &str| {
+            q_nodes
+                .iter()
+                .find(|(target, ..)| target.target_name == *name)
+        };
+        let Some((_, camera_props, first_node, _)) = find_node(&ev.name) else {
+            warn!("no info_camera named {}", ev.name);
+            continue;
+        };
+
+        let look_at = camera_props.properties.get("look_at").and_then(|name| {
+            q_targets
+                .iter()
+                .find(|(target, _)| target.target_name == *name)
+                .map(|(_, transform)| transform.translation())
+        });
+
+        // follow the target chain, stopping on loops
+        let mut points: Vec<PathPoint> = Vec::new();
+        let mut visited = Vec::new();
+        let mut time = 0.0;
+        let mut speed = first_node.speed;
+        let mut current = Some(ev.name.clone());
+        while let Some(name) = current.take() {
+            if visited.contains(&name) {
+                break;
+            }
+            let Some((_, props, node, transform)) = find_node(&name) else {
+                break;
+            };
+            let translation = transform.translation();
+            if let Some(previous) = points.last() {
+                time += previous.translation.distance(translation) / speed.max(f32::EPSILON);
+            }
+            speed = node.speed;
+            points.push(PathPoint {
+                translation,
+                fov: node.fov,
+                time,
+            });
+            visited.push(name);
+            current = props.properties.get("target").cloned();
+        }
+
+        if points.len() < 2 {
+            warn!("camera path {} has no path_corner", ev.name);
+            continue;
+        }
+
+        commands.entity(ev.camera).insert(CameraPathPlayer {
+            name: ev.name.clone(),
+            elapsed: 0.0,
+            points,
+            look_at,
+        });
+    }
+}
+
+#[cfg_attr(not(feature = "render"), allow(unused_variables))]
+pub fn camera_path_player_system(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut finished_events: EventWriter<CameraPathFinishedEvent>,
+    mut q_cameras: Query<(Entity, &mut CameraPathPlayer, &mut Transform)>,
+    #[cfg(feature = "render")] mut q_projections: Query<&mut Projection>,
+) {
+    for (entity, mut player, mut transform) in q_cameras.iter_mut() {
+        player.elapsed += time.delta_seconds();
+        let duration = player.duration();
+        let t = (player.elapsed / duration.max(f32::EPSILON)).clamp(0.0, 1.0);
+        // smoothstep ease in/out
+        let eased = t * t * (3.0 - 2.0 * t);
+
+        let (translation, fov, direction) = player.sample(eased * duration);
+        transform.translation = translation;
+        match player.look_at {
+            Some(look_at) => transform.look_at(look_at, Vec3::Y),
+            None if direction.length_squared() > 0.0 => {
+                transform.look_to(direction, Vec3::Y);
+            }
+            None => {}
+        }
+        #[cfg(feature = "render")]
+        if let (Some(fov), Ok(mut projection)) = (fov, q_projections.get_mut(entity)) {
+            if let Projection::Perspective(perspective) = projection.as_mut() {
+                perspective.fov = fov;
+            }
+        }
+
+        if t >= 1.0 {
+            commands.entity(entity).remove::<CameraPathPlayer>();
+            finished_events.send(CameraPathFinishedEvent {
+                name: player.name.clone(),
+                camera: entity,
+            });
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Brush collider setup and deferred generation.
+//!
+//! With [`ColliderGeneration::Deferred`], solid brushes are spawned with a
+//! [`PendingCollider`] instead of a collider, and their convex hulls are
+//! computed over the following frames, closest to a [`ColliderAnchor`] first.
+
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+
+use crate::components::Map;
+use crate::core::CompiledBrush;
+use crate::settings::{ColliderGeneration, HullSimplification, MapBuildSettings};
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+use crate::settings::{PhysicsBuildSettings, SensorActivation};
+
+/// Colliders are generated around this entity first, e.g. the player.
+#[derive(Component, Default)]
+pub struct ColliderAnchor;
+
+/// A brush collider waiting to be generated.
+#[derive(Component, Debug, Clone)]
+pub struct PendingCollider {
+    pub map: Entity,
+    /// center of the brush, in map space
+    pub center: Vec3,
+    /// vertices of the brush, relative to the collider entity
+    pub vertices: Vec<Vec3>,
+}
+
+impl PendingCollider {
+    pub fn new(map: Entity, origin: Vec3, vertices: Vec<Vec3>) -> Self {
+        let center = vertices.iter().sum::<Vec3>() / vertices.len().max(1) as f32;
+        Self {
+            map,
+            center: origin + center,
+            vertices,
+        }
+    }
+}
+
+/// The vertices a brush's convex hull is built from. Every face vertex is
+/// shared by several faces and brushes can have vertices along their edges
+/// or inside their faces, so the vertices are welded and only the corners,
+/// lying on at least three non-parallel faces, are kept. Brushes with more
+/// corners than [`HullSimplification::max_vertices`] are thinned down to it.
+pub fn simplify_hull(brush: &CompiledBrush, settings: &HullSimplification) -> Vec<Vec3> {
+    if !settings.enabled {
+        return brush.vertices.clone();
+    }
+
+    let max_distance_squared = settings.weld_distance * settings.weld_distance;
+    let mut welded: Vec<Vec3> = Vec::new();
+    for vertex in brush.vertices.iter() {
+        if !welded
+            .iter()
+            .any(|other| other.distance_squared(*vertex) <= max_distance_squared)
+        {
+            welded.push(*vertex);
+        }
+    }
+
+    let planes = brush
+        .faces
+        .iter()
+        .filter_map(|face| {
+            let triangle = face.indices.get(0..3)?;
+            let [a, b, c] = [0, 1, 2].map(|i| face.vertices[triangle[i] as usize]);
+            let normal = (b - a).cross(c - a).try_normalize()?;
+            Some((normal, normal.dot(a)))
+        })
+        .collect::<Vec<_>>();
+    let tolerance = settings.weld_distance.max(1e-4);
+    let corners = welded
+        .iter()
+        .copied()
+        .filter(|vertex| {
+            let normals = planes
+                .iter()
+                .filter(|(normal, distance)| (normal.dot(*vertex) - distance).abs() <= tolerance)
+                .map(|(normal, _)| *normal)
+                .collect::<Vec<_>>();
+            normals.iter().enumerate().any(|(i, a)| {
+                normals[i + 1..].iter().enumerate().any(|(j, b)| {
+                    normals[i + j + 2..]
+                        .iter()
+                        .any(|c| a.dot(b.cross(*c)).abs() > 1e-3)
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    // faces dropped by the geometry cleanup can leave too few corners
+    let mut vertices = if corners.len() >= 4 { corners } else { welded };
+
+    if let Some(max_vertices) = settings.max_vertices.filter(|max| *max >= 4) {
+        vertices = thin_vertices(vertices, max_vertices);
+    }
+    vertices
+}
+
+/// Keeps `count` of the vertices, each the farthest from the ones kept
+/// before it, starting from the farthest from their center.
+fn thin_vertices(vertices: Vec<Vec3>, count: usize) -> Vec<Vec3> {
+    if vertices.len() <= count {
+        return vertices;
+    }
+    let center = vertices.iter().sum::<Vec3>() / vertices.len() as f32;
+    let mut distances = vertices
+        .iter()
+        .map(|vertex| vertex.distance_squared(center))
+        .collect::<Vec<_>>();
+    let mut kept = Vec::with_capacity(count);
+    while kept.len() < count {
+        let (index, _) = distances
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        let vertex = vertices[index];
+        kept.push(vertex);
+        for (distance, other) in distances.iter_mut().zip(vertices.iter()) {
+            *distance = distance.min(other.distance_squared(vertex));
+        }
+    }
+    kept
+}
+
+/// Sets up the sleeping of a trigger sensor body, see [`PhysicsBuildSettings`].
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+pub fn configure_sensor_body(entity: &mut EntityCommands, settings: &PhysicsBuildSettings) {
+    #[cfg(feature = "xpbd")]
+    {
+        // sensors are dynamic so kinematic bodies touch them, but shouldn't fall
+        entity.insert(bevy_xpbd_3d::prelude::GravityScale(0.0));
+        match settings.sensor_activation {
+            SensorActivation::Sleeping => entity.insert(bevy_xpbd_3d::prelude::Sleeping),
+            SensorActivation::AlwaysAwake => entity.insert(bevy_xpbd_3d::prelude::SleepingDisabled),
+        };
+    }
+
+    #[cfg(feature = "rapier")]
+    #[cfg(not(feature = "xpbd"))]
+    {
+        entity.insert(match settings.sensor_activation {
+            SensorActivation::Sleeping => bevy_rapier3d::prelude::Sleeping {
+                sleeping: true,
+                ..default()
+            },
+            SensorActivation::AlwaysAwake => bevy_rapier3d::prelude::Sleeping::disabled(),
+        });
+    }
+}
+
+/// Builds every pending collider within the sync radius of an anchor, and
+/// the `per_frame` closest ones after that.
+pub fn deferred_collider_system(
+    mut commands: Commands,
+    build_settings: Res<MapBuildSettings>,
+    q_anchors: Query<&GlobalTransform, With<ColliderAnchor>>,
+    q_maps: Query<&GlobalTransform, With<Map>>,
+    q_pending: Query<(Entity, &PendingCollider)>,
+) {
+    if q_pending.is_empty() {
+        return;
+    }
+    let (sync_radius, per_frame) = match build_settings.collider_generation {
+        ColliderGeneration::Deferred {
+            sync_radius,
+            per_frame,
+        } => (sync_radius, per_frame),
+        // the settings changed after the map was built
+        ColliderGeneration::Immediate => (f32::MAX, 0),
+    };
+
+    let anchors = q_anchors
+        .iter()
+        .map(|transform| transform.translation())
+        .collect::<Vec<_>>();
+    let mut pending = q_pending
+        .iter()
+        .map(|(entity, pending)| {
+            let center = q_maps.get(pending.map).map_or(pending.center, |transform| {
+                transform.transform_point(pending.center)
+            });
+            let distance = anchors
+                .iter()
+                .map(|anchor| anchor.distance(center))
+                .fold(f32::MAX, f32::min);
+            (distance, entity, pending)
+        })
+        .collect::<Vec<_>>();
+    pending.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let in_sync_radius = pending
+        .iter()
+        .take_while(|(distance, ..)| *distance <= sync_radius)
+        .count();
+    for (_, entity, pending) in pending.into_iter().take(in_sync_radius + per_frame) {
+        let mut entity = commands.entity(entity);
+        entity.remove::<PendingCollider>();
+
+        #[cfg(feature = "xpbd")]
+        if let Some(convex_hull) =
+            bevy_xpbd_3d::prelude::Collider::convex_hull(pending.vertices.clone())
+        {
+            entity.insert(convex_hull);
+        }
+
+        #[cfg(feature = "rapier")]
+        #[cfg(not(feature = "xpbd"))]
+        if let Some(convex_hull) = bevy_rapier3d::prelude::Collider::convex_hull(&pending.vertices)
+        {
+            entity.insert(convex_hull);
+        }
+
+        #[cfg(not(any(feature = "xpbd", feature = "rapier")))]
+        let _ = pending;
+    }
+}
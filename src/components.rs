@@ -1,23 +1,105 @@
+#[cfg(feature = "render")]
+use crate::conversions::parse_color;
+use crate::conversions::{resolve_angles, to_bevy_rotation, AngleConvention};
+use crate::intern::InternedStr;
+use bevy::math::bounding::Aabb3d;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{collections::BTreeMap, time::Duration};
 
 #[derive(Default, Component)]
 pub struct Map {
     pub asset: Handle<crate::MapAsset>,
+    /// filled in when the map is built
+    pub metadata: MapMetadata,
 }
 
+impl Map {
+    /// Bounds of the world geometry, relative to the map entity
+    pub fn bounds(&self) -> Option<Aabb3d> {
+        self.metadata.bounds
+    }
+
+    pub fn classname_count(&self, classname: &str) -> usize {
+        self.metadata
+            .classname_counts
+            .get(classname)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Uniformly scales the geometry, colliders and entity positions of a map
+/// when it's built. Unlike a `Transform` scale, this keeps physics happy.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MapScale(pub f32);
+
+impl Default for MapScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Builds a mirrored copy of a map, flipped along the given axis around the
+/// map's origin. Winding order, normals and colliders are corrected.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapMirror {
+    /// flips the X axis
+    X,
+    /// flips the Z axis
+    Z,
+}
+
+impl MapMirror {
+    pub fn mirror_vec3(&self, vector: Vec3) -> Vec3 {
+        match self {
+            MapMirror::X => Vec3::new(-vector.x, vector.y, vector.z),
+            MapMirror::Z => Vec3::new(vector.x, vector.y, -vector.z),
+        }
+    }
+
+    pub fn mirror_quat(&self, rotation: Quat) -> Quat {
+        match self {
+            MapMirror::X => Quat::from_xyzw(rotation.x, -rotation.y, -rotation.z, rotation.w),
+            MapMirror::Z => Quat::from_xyzw(-rotation.x, -rotation.y, rotation.z, rotation.w),
+        }
+    }
+}
+
+/// Seed of a built map, from its worldspawn `_seed` property or
+/// [`crate::settings::MapBuildSettings::seed`]. See [`crate::random`].
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapSeed(pub u64);
+
+#[derive(Default, Debug, Clone)]
+pub struct MapMetadata {
+    pub bounds: Option<Aabb3d>,
+    pub classname_counts: BTreeMap<String, usize>,
+}
+
+/// The transform of spawned map entities, along with their visibility with
+/// the `render` feature.
+#[cfg(feature = "render")]
+pub type MapSpatialBundle = SpatialBundle;
+#[cfg(not(feature = "render"))]
+pub type MapSpatialBundle = TransformBundle;
+
 #[derive(Default, Bundle)]
 pub struct MapBundle {
     pub map: Map,
     pub transform: TransformBundle,
+    #[cfg(feature = "render")]
     pub visibility: VisibilityBundle,
 }
 
 #[derive(Default, Component)]
 pub struct MapEntityProperties {
-    pub classname: String,
+    pub classname: InternedStr,
     pub transform: Transform,
-    pub properties: BTreeMap<String, String>,
+    pub properties: BTreeMap<InternedStr, InternedStr>,
+    /// seed for the entity's randomized features, the same on every client
+    /// building the map, see [`crate::random`]
+    pub seed: u64,
 }
 
 impl MapEntityProperties {
@@ -25,7 +107,7 @@ impl MapEntityProperties {
 
     pub fn get_property_as_string(&self, key: &str, default: Option<&String>) -> Option<String> {
         if let Some(value) = self.properties.get(key) {
-            return Some(value.clone());
+            return Some(value.to_string());
         }
         default.cloned()
     }
@@ -61,20 +143,33 @@ impl MapEntityProperties {
         default
     }
 
+    /// Parses `255 128 0`, `1.0 0.5 0.0` or `#ff8000`, see [`parse_color`].
+    #[cfg(feature = "render")]
     pub fn get_property_as_color(&self, key: &str, default: Color) -> Color {
-        if let Some(value) = self.properties.get(key) {
-            let value = value.trim().split(" ").collect::<Vec<&str>>();
-            if value.len() == 3 {
-                if let (Ok(r), Ok(g), Ok(b)) = (
-                    value[0].parse::<f32>(),
-                    value[1].parse::<f32>(),
-                    value[2].parse::<f32>(),
-                ) {
-                    return Color::rgb(r, g, b);
-                }
-            }
-        }
-        default
+        self.get_property_as_color_intensity(key)
+            .map_or(default, |(color, _)| color)
+    }
+
+    /// Parses a color like [`Self::get_property_as_color`], along with the
+    /// optional 4th intensity component of Half-Life's `_light` (`R G B brightness`).
+    #[cfg(feature = "render")]
+    pub fn get_property_as_color_intensity(&self, key: &str) -> Option<(Color, Option<f32>)> {
+        parse_color(self.properties.get(key)?)
+    }
+
+    /// The orientation from the `angles`, `mangle` or `angle` property, with
+    /// the angle convention of the entity's classname.
+    pub fn get_rotation(&self) -> Quat {
+        self.get_rotation_with(AngleConvention::for_classname(&self.classname))
+    }
+
+    pub fn get_rotation_with(&self, convention: AngleConvention) -> Quat {
+        resolve_angles(
+            |key| self.properties.get(key).map(|value| value.as_str()),
+            convention,
+        )
+        .map(|angles| to_bevy_rotation(&angles))
+        .unwrap_or(Quat::IDENTITY)
     }
 
     pub fn get_property_as_vec3(&self, key: &str, default: Vec3) -> Vec3 {
@@ -97,36 +192,406 @@ impl MapEntityProperties {
 #[derive(Default, Component)]
 pub struct BrushEntity;
 
+/// A point entity of a map, e.g. a light or `info_player_start`.
 #[derive(Default, Component)]
-pub struct Brush;
+pub struct MapPointEntity;
 
+/// Child of a map holding its `worldspawn` and `func_group` brush entities,
+/// so the level geometry can be hidden or put on render layers at once.
+#[derive(Default, Component)]
+pub struct WorldGeometry;
+
+/// The sensor collider of a `trigger_*` brush.
+#[derive(Default, Component)]
+pub struct TriggerVolume;
+
+/// The convex brushes of a volume entity, e.g. `func_fog_volume`, as face
+/// planes relative to its transform. Volumes get no mesh or collider, so
+/// their bounds are tested with [`BrushVolume::contains`] instead. Doors get
+/// one too, to tell when they're blocked.
+#[derive(Component, Default, Debug, Clone)]
+pub struct BrushVolume {
+    /// outward normal and distance from the origin of each face, by brush
+    pub brushes: Vec<Vec<(Vec3, f32)>>,
+}
+
+impl BrushVolume {
+    /// The face planes of a compiled brush entity, relative to `origin`.
+    pub fn from_compiled(entity: &crate::core::CompiledEntity, origin: Vec3) -> Self {
+        let brushes = entity
+            .brushes
+            .iter()
+            .map(|brush| {
+                let center = brush.vertices.iter().copied().sum::<Vec3>()
+                    / brush.vertices.len().max(1) as f32;
+                brush
+                    .faces
+                    .iter()
+                    .filter_map(|face| {
+                        let point = *face.vertices.first()? - origin;
+                        let mut normal = *face.normals.first()?;
+                        // keep normals pointing out whatever the map's winding
+                        if normal.dot(point - (center - origin)) < 0.0 {
+                            normal = -normal;
+                        }
+                        Some((normal, normal.dot(point)))
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { brushes }
+    }
+
+    /// Whether a point relative to the volume's transform is inside any of its brushes.
+    pub fn contains(&self, point: Vec3) -> bool {
+        self.brushes.iter().any(|planes| {
+            !planes.is_empty()
+                && planes
+                    .iter()
+                    .all(|(normal, distance)| normal.dot(point) <= *distance)
+        })
+    }
+
+    /// Height of the top of the brush containing a point relative to the
+    /// volume's transform, e.g. a liquid's surface above it.
+    pub fn surface_height(&self, point: Vec3) -> Option<f32> {
+        let planes = self.brushes.iter().find(|planes| {
+            !planes.is_empty()
+                && planes
+                    .iter()
+                    .all(|(normal, distance)| normal.dot(point) <= *distance)
+        })?;
+        planes
+            .iter()
+            .filter(|(normal, _)| normal.y > 1e-4)
+            .map(|(normal, distance)| {
+                (distance - normal.x * point.x - normal.z * point.z) / normal.y
+            })
+            .min_by(|a, b| a.total_cmp(b))
+    }
+
+    /// Whether a point in world space is inside the volume.
+    pub fn contains_world(&self, transform: &GlobalTransform, point: Vec3) -> bool {
+        self.contains(transform.affine().inverse().transform_point3(point))
+    }
+
+    /// Whether a point in world space is inside the volume grown by
+    /// `margin`, or roughly within `margin` of it.
+    pub fn contains_world_within(
+        &self,
+        transform: &GlobalTransform,
+        point: Vec3,
+        margin: f32,
+    ) -> bool {
+        let point = transform.affine().inverse().transform_point3(point);
+        self.brushes.iter().any(|planes| {
+            !planes.is_empty()
+                && planes
+                    .iter()
+                    .all(|(normal, distance)| normal.dot(point) <= *distance + margin)
+        })
+    }
+}
+
+/// The collider of a brush textured with `clip` only, solid but invisible.
+#[derive(Default, Component)]
+pub struct ClipGeometry;
+
+/// The brush a generated collider was built from, by index in the .map file,
+/// to trace a misbehaving collider back to the map in physics debuggers and
+/// crash reports.
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct ColliderSource {
+    pub brush_id: usize,
+    pub entity_id: usize,
+    pub face_count: usize,
+}
+
+/// A mesh with an alpha tested texture, see [`crate::core::is_foliage_texture`].
+#[derive(Default, Component)]
+pub struct FoliageGeometry;
+
+/// Marks every mesh spawned for the brushes and patches of a map.
+#[derive(Default, Component, Debug, Clone, Copy)]
+pub struct MapMesh;
+
+/// A brush face mesh. Its UVs are rescaled by [`crate::build::rescale_brush_uvs_system`]
+/// when its material gets a texture of a different size.
+#[derive(Default, Component)]
+pub struct Brush {
+    /// size of the texture the UVs were computed against, in pixels
+    pub texture_size: UVec2,
+    /// size of the texture the UVs of the brush's
+    /// [`crate::picking::BrushFaces`] were computed against, which meshes
+    /// only kept in the render world are rebuilt from
+    pub authored_texture_size: UVec2,
+}
+
+/// Marks a mesh whose UVs stay fixed relative to its map when it moves
 #[derive(Component)]
+pub struct WorldLockedUvs {
+    pub map: Entity,
+    pub projection: crate::core::UvProjection,
+}
+
+#[derive(Component, Reflect, Serialize, Deserialize, Default, Debug, Clone, Copy)]
+#[reflect(Component)]
 pub struct TriggeredOnce;
 
 #[derive(Event)]
 pub struct TriggeredEvent {
-    pub target: String,
+    pub target: InternedStr,
+    pub triggered_by: Entity,
+}
+
+/// How trigger volumes fire their targets.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerAuthority {
+    /// Triggers fire right away
+    #[default]
+    Local,
+    /// Triggers only send [`WouldFireTrigger`], and fire once the game
+    /// confirms them with a [`ConfirmTriggerEvent`], e.g. from an
+    /// authoritative server. Predicted clients don't fire twice on rollback.
+    Confirmed,
+}
+
+/// Sent instead of firing a trigger with [`TriggerAuthority::Confirmed`].
+/// Sent again every frame the instigator stays inside, until confirmed.
+#[derive(Event, Debug, Clone)]
+pub struct WouldFireTrigger {
+    pub trigger: Entity,
+    pub target: InternedStr,
+    pub triggered_by: Entity,
+}
+
+/// Fires a trigger volume, see [`TriggerAuthority::Confirmed`].
+#[derive(Event, Debug, Clone)]
+pub struct ConfirmTriggerEvent {
+    pub trigger: Entity,
     pub triggered_by: Entity,
 }
 
 #[derive(Default, Component)]
 pub struct TriggerOnce {
-    pub target: String,
+    pub target: InternedStr,
 }
 
 #[derive(Default, Component)]
 pub struct TriggerMultiple {
-    pub target: String,
+    pub target: InternedStr,
+}
+
+/// A `trigger_look` volume, firing `target` once an instigator inside has
+/// looked at the `look_target` entity for `duration` seconds, within `angle`
+/// of its view direction and with no brush in between.
+#[derive(Component, Debug, Clone)]
+pub struct TriggerLook {
+    pub target: InternedStr,
+    /// targetname of the entity to look at
+    pub look_target: InternedStr,
+    /// largest angle between the view direction and the target, in radians
+    pub angle: f32,
+    pub duration: f32,
+    /// fire only the first time, otherwise again whenever an instigator
+    /// looks away and back
+    pub once: bool,
+    /// how long each instigator inside has been looking at the target
+    pub looking: Vec<(Entity, f32)>,
+}
+
+/// The view of a [`TriggerInstigator`] tested by [`TriggerLook`]s, e.g. its
+/// camera. Instigators without a descendant marked with it look along their
+/// own forward axis.
+#[derive(Default, Component)]
+pub struct LookEye;
+
+/// A `trigger_pressure` plate, pressed while at least `required_count`
+/// instigators weighing `required_mass` in total are inside. Fires `target`
+/// when pressed and `target_release` when released. Instigators without a
+/// physics mass weigh nothing.
+#[derive(Default, Component, Debug, Clone)]
+pub struct TriggerPressure {
+    pub target: InternedStr,
+    pub target_release: InternedStr,
+    pub required_count: usize,
+    pub required_mass: Option<f32>,
+    pub pressed: bool,
+    /// the instigator that last pressed or held down the plate
+    pub activator: Option<Entity>,
 }
 
 #[derive(Default, Component)]
 pub struct TriggerTarget {
-    pub target_name: String,
+    pub target_name: InternedStr,
 }
 
 #[derive(Default, Component)]
 pub struct TriggerInstigator;
 
+/// The [`TriggerInstigator`]s inside a [`TriggerVolume`], in the order they
+/// entered. Kept up to date by the physics backend's trigger system, which
+/// sends a [`TriggerEntered`] and [`TriggerExited`] event for each change.
+#[derive(Component, Default, Debug, Clone)]
+pub struct TriggerOccupancy {
+    pub occupants: Vec<Entity>,
+}
+
+impl TriggerOccupancy {
+    pub fn count(&self) -> usize {
+        self.occupants.len()
+    }
+
+    pub fn is_occupied(&self) -> bool {
+        !self.occupants.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.occupants.contains(&entity)
+    }
+}
+
+/// Sent when a [`TriggerInstigator`] moves into a trigger volume.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TriggerEntered {
+    pub trigger: Entity,
+    pub activator: Entity,
+    /// instigators inside the trigger, including the activator
+    pub occupants: usize,
+}
+
+/// Sent when a [`TriggerInstigator`] leaves a trigger volume, or is
+/// despawned while inside it.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TriggerExited {
+    pub trigger: Entity,
+    pub activator: Entity,
+    /// instigators left inside the trigger
+    pub occupants: usize,
+}
+
+/// A `trigger_secret` volume, counted in [`SecretsFound`] the first time it's triggered
+#[derive(Component)]
+pub struct Secret {
+    pub map: Entity,
+}
+
+#[derive(Event)]
+pub struct SecretFoundEvent {
+    pub map: Entity,
+    pub secret: Entity,
+}
+
+/// Number of secrets found per map entity
+#[derive(Resource, Default)]
+pub struct SecretsFound {
+    pub per_map: bevy::utils::HashMap<Entity, usize>,
+}
+
+impl SecretsFound {
+    pub fn get(&self, map: Entity) -> usize {
+        self.per_map.get(&map).copied().unwrap_or(0)
+    }
+}
+
+/// Summary of a map build, inserted on the map entity
+#[derive(Component, Default, Debug, Clone)]
+pub struct MapBuildReport {
+    pub entities: usize,
+    pub brushes: usize,
+    /// total number of `trigger_secret` volumes
+    pub secrets: usize,
+    /// time spent building the map's entities, excluding its meshes
+    pub build_time: Duration,
+    /// vertices and faces fixed or dropped by the geometry cleanup
+    pub geometry: crate::core::GeometryReport,
+}
+
+/// Estimated memory used by a built map, inserted on the map entity and
+/// updated as its meshes are spawned. See [`crate::diagnostics`].
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapMemoryUsage {
+    /// vertex buffers of the map's meshes
+    pub vertex_bytes: usize,
+    /// index buffers of the map's meshes
+    pub index_bytes: usize,
+    /// decoded base color and auxiliary textures
+    pub texture_bytes: usize,
+    /// points of the convex hulls of brush colliders
+    pub collider_bytes: usize,
+}
+
+impl MapMemoryUsage {
+    pub fn total(&self) -> usize {
+        self.vertex_bytes + self.index_bytes + self.texture_bytes + self.collider_bytes
+    }
+
+    #[cfg(feature = "render")]
+    pub(crate) fn add_mesh(&mut self, mesh: &Mesh) {
+        self.vertex_bytes += mesh
+            .attributes()
+            .map(|(_, values)| values.get_bytes().len())
+            .sum::<usize>();
+        self.index_bytes += match mesh.indices() {
+            Some(bevy::render::mesh::Indices::U16(indices)) => indices.len() * 2,
+            Some(bevy::render::mesh::Indices::U32(indices)) => indices.len() * 4,
+            None => 0,
+        };
+    }
+}
+
+/// Sent for each descendant of a map before it is despawned to rebuild the
+/// map, e.g. on hot reload. The entities are despawned the next frame, so
+/// systems reading this event can still persist or move their components.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MapDescendantDespawning {
+    pub map: Entity,
+    pub entity: Entity,
+}
+
+/// Sent for each face dropped by the geometry cleanup of a map, unless the
+/// [`crate::settings::DegenerateGeometryPolicy`] is `Skip`.
+#[derive(Event, Debug, Clone)]
+pub struct DegenerateGeometry {
+    pub map: Entity,
+    pub face: crate::core::DegenerateFace,
+}
+
+/// Marks a map whose descendants are despawned and rebuilt the next frame.
+#[derive(Component, Default)]
+pub struct MapRebuildPending;
+
+/// A brush entity that is shown/hidden and has its colliders enabled/disabled
+/// each time its targetname is triggered.
+/// Added to `func_wall_toggle` entities and any brush entity with a
+/// `start_disabled` or `_toggle` property.
+#[derive(Component, Reflect, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub struct Toggleable {
+    pub enabled: bool,
+}
+
+/// Material pairs a `func_wall` swaps between when triggered, from its
+/// `texture_on`/`texture_off` properties or its `+0` animated textures and
+/// their `+a` alternates.
+#[cfg(feature = "render")]
+#[derive(Component, Debug, Clone, Default)]
+pub struct TextureFrames {
+    /// (shown by default, shown when alternate)
+    pub swaps: Vec<(Handle<StandardMaterial>, Handle<StandardMaterial>)>,
+    pub alternate: bool,
+}
+
+/// Holds a collider removed from a disabled [`Toggleable`] brush entity
+#[derive(Component)]
+pub struct DisabledCollider<C: Component>(pub C);
+
+#[cfg(feature = "xpbd")]
+pub(crate) type PhysicsCollider = bevy_xpbd_3d::prelude::Collider;
+#[cfg(all(feature = "rapier", not(feature = "xpbd")))]
+pub(crate) type PhysicsCollider = bevy_rapier3d::prelude::Collider;
+
 #[derive(Default, Component)]
 pub struct Mover {
     pub state: MoverState,
@@ -138,6 +603,101 @@ pub struct Mover {
     pub destination_offset: Vec3,
 }
 
+impl Mover {
+    /// How far along the mover is from its start (0) to its destination (1).
+    pub fn progress(&self) -> f32 {
+        match &self.state {
+            MoverState::AtStart => 0.0,
+            MoverState::MovingToDestination(_) => self.state.get_fraction(),
+            MoverState::AtDestination(_) => 1.0,
+            MoverState::MovingToStart(_) => 1.0 - self.state.get_fraction(),
+        }
+    }
+}
+
+/// Carried by the [`Mover`] it stands on, see [`crate::movers`].
+/// [`TriggerInstigator`]s without one are carried as with the default.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MoverPassenger {
+    /// how far below its origin the passenger's feet are, plus some slack
+    pub ground_distance: f32,
+}
+
+impl Default for MoverPassenger {
+    fn default() -> Self {
+        Self {
+            ground_distance: 1.0,
+        }
+    }
+}
+
+/// Which part of its cycle a [`Mover`] is in, see [`MoverState`].
+#[derive(Reflect, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoverPhase {
+    #[default]
+    AtStart,
+    MovingToDestination,
+    AtDestination,
+    MovingToStart,
+}
+
+/// The runtime state of a [`Mover`], for networking crates to replicate.
+/// Kept up to date from the mover, and only marked changed when the state
+/// actually changes. Replicated values that differ from the mover's own
+/// state are applied back to it, see [`crate::movers`].
+#[derive(Component, Reflect, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub struct MoverSync {
+    pub phase: MoverPhase,
+    /// seconds spent in the current phase
+    pub elapsed: f32,
+    /// see [`Mover::progress`]
+    pub progress: f32,
+}
+
+impl MoverSync {
+    pub fn from_mover(mover: &Mover) -> Self {
+        let (phase, elapsed) = match &mover.state {
+            MoverState::AtStart => (MoverPhase::AtStart, 0.0),
+            MoverState::MovingToDestination(timer) => {
+                (MoverPhase::MovingToDestination, timer.elapsed_secs())
+            }
+            MoverState::AtDestination(timer) => (MoverPhase::AtDestination, timer.elapsed_secs()),
+            MoverState::MovingToStart(timer) => (MoverPhase::MovingToStart, timer.elapsed_secs()),
+        };
+        Self {
+            phase,
+            elapsed,
+            progress: mover.progress(),
+        }
+    }
+
+    /// The [`MoverState`] of `mover` for this phase and elapsed time.
+    pub fn to_state(&self, mover: &Mover) -> MoverState {
+        let timer = |duration: Duration| {
+            let mut timer = Timer::new(duration, TimerMode::Once);
+            timer.set_elapsed(Duration::from_secs_f32(self.elapsed.max(0.0)));
+            timer
+        };
+        match self.phase {
+            MoverPhase::AtStart => MoverState::AtStart,
+            MoverPhase::MovingToDestination => {
+                MoverState::MovingToDestination(timer(mover.moving_time))
+            }
+            MoverPhase::AtDestination => MoverState::AtDestination(timer(mover.destination_time)),
+            MoverPhase::MovingToStart => MoverState::MovingToStart(timer(mover.moving_time)),
+        }
+    }
+}
+
+/// The last two fixed timestep positions of a mover, to interpolate its
+/// rendered transform in between. See [`crate::settings::MoverTimestep`].
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MoverInterpolation {
+    pub previous: Vec3,
+    pub current: Vec3,
+}
+
 #[derive(Default)]
 pub enum MoverState {
     #[default]
@@ -158,18 +718,56 @@ impl MoverState {
     }
 }
 
+/// A mover whose progress follows an analog input instead of being
+/// triggered, e.g. a valve wheel or a crank driven drawbridge. Added to
+/// movers with a `mover_kind` of `momentary`, see [`SetMoverProgress`].
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct MomentaryMover {
+    /// progress the mover moves towards at its `moving_time` speed, from
+    /// its start (0) to its destination (1)
+    pub target_progress: f32,
+}
+
+/// Send to set the [`MomentaryMover::target_progress`] of a mover.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SetMoverProgress {
+    pub mover: Entity,
+    pub progress: f32,
+}
+
 #[derive(Component, Default)]
 pub struct Door {
     /// the key required to open the door
     pub key: Option<String>,
     /// whether the door should open only once and stay open
     pub open_once: bool,
+    /// reopen when a [`TriggerInstigator`] is in the way while closing
+    pub reopen_on_block: bool,
+    /// how close to the door's brushes an instigator blocks it
+    pub block_margin: f32,
+    /// open when an instigator comes within this distance of the door's
+    /// origin, and stay open while it's there
+    pub open_radius: Option<f32>,
+}
+
+/// Spawnflag of doors that aren't linked to the doors they touch.
+pub const DOOR_DONT_LINK: i32 = 4;
+
+/// Linked doors, opening and closing together and sharing their lock, see
+/// [`crate::movers::door_groups`].
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DoorGroup {
+    pub map: Entity,
+    /// index in the .map file of the group's first door
+    pub id: usize,
+    /// whether this is the first door, whose state the others follow
+    pub leader: bool,
 }
 
 /// The units used in the map
 /// Bevy units are the default units used in Bevy, which are 1 unit = 1 meter
 /// Trenchbroom units are the units used in Trenchbroom, which are 16 units = 1 foot
-#[derive(Resource, Clone)]
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
 pub enum MapUnits {
     Bevy,
     Trenchbroom,
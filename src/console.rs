@@ -0,0 +1,277 @@
+//! Quake style console commands for testing maps, behind the `console`
+//! feature.
+//!
+//! Lines sent as [`MapConsoleInput`] events, e.g. from the game's console UI
+//! or a `bevy_console` command, run one of:
+//!
+//! - `map <name>` replaces the loaded maps with `<map_directory>/<name>.map`
+//! - `map_restart` rebuilds the loaded maps from scratch
+//! - `map_report` prints the build report and issues of each map
+//! - `noclip_to <targetname>` moves the [`TriggerInstigator`]s to the
+//!   entity with that `targetname`
+//! - `fire <targetname>` fires a target, as a trigger would
+//!
+//! The replies are sent as [`MapConsoleOutput`] events and logged. With
+//! [`MapConsolePlugin::stdin`] set, lines typed in the terminal the game was
+//! started from are run as well, a minimal console for dedicated servers.
+
+use bevy::prelude::*;
+use std::io::BufRead;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Mutex;
+
+use crate::components::{
+    Map, MapBuildReport, MapBundle, MapDescendantDespawning, MapRebuildPending, TriggerInstigator,
+    TriggerTarget, TriggeredEvent,
+};
+use crate::registry::MapEntityRegistry;
+use crate::MapAsset;
+
+#[derive(Default)]
+pub struct MapConsolePlugin {
+    /// also read commands from the standard input
+    pub stdin: bool,
+}
+
+impl Plugin for MapConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapConsoleSettings>()
+            .add_event::<MapConsoleInput>()
+            .add_event::<MapConsoleOutput>()
+            .add_event::<TriggeredEvent>()
+            .add_event::<MapDescendantDespawning>();
+        if self.stdin {
+            app.insert_resource(StdinConsole::spawn()).add_systems(
+                PreUpdate,
+                read_stdin_console_system.before(map_console_system),
+            );
+        }
+        app.add_systems(PreUpdate, map_console_system);
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct MapConsoleSettings {
+    /// asset directory the maps loaded by `map` are in
+    pub map_directory: String,
+}
+
+impl Default for MapConsoleSettings {
+    fn default() -> Self {
+        Self {
+            map_directory: "maps".to_string(),
+        }
+    }
+}
+
+impl MapConsoleSettings {
+    /// Asset path of a map, `.map` being added when there's no extension.
+    pub fn map_path(&self, name: &str) -> String {
+        let file = match std::path::Path::new(name).extension() {
+            Some(_) => name.to_string(),
+            None => format!("{}.map", name),
+        };
+        match self.map_directory.trim_end_matches('/') {
+            "" => file,
+            directory => format!("{}/{}", directory, file),
+        }
+    }
+}
+
+/// A line typed in the console.
+#[derive(Event, Debug, Clone)]
+pub struct MapConsoleInput(pub String);
+
+/// A reply of the console, one line each.
+#[derive(Event, Debug, Clone)]
+pub struct MapConsoleOutput(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapConsoleCommand {
+    Map(String),
+    MapRestart,
+    MapReport,
+    NoclipTo(String),
+    Fire(String),
+}
+
+impl MapConsoleCommand {
+    pub const NAMES: &'static [&'static str] =
+        &["map", "map_restart", "map_report", "noclip_to", "fire"];
+
+    /// Parses a console line. Errors are the reply to print, e.g. the usage
+    /// of the command.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next();
+        if words.next().is_some() {
+            return Err(format!("too many arguments to {}", command));
+        }
+        match (command, argument) {
+            ("map", Some(name)) => Ok(Self::Map(name.to_string())),
+            ("map", None) => Err("usage: map <name>".to_string()),
+            ("map_restart", None) => Ok(Self::MapRestart),
+            ("map_report", None) => Ok(Self::MapReport),
+            ("noclip_to", Some(target_name)) => Ok(Self::NoclipTo(target_name.to_string())),
+            ("noclip_to", None) => Err("usage: noclip_to <targetname>".to_string()),
+            ("fire", Some(target)) => Ok(Self::Fire(target.to_string())),
+            ("fire", None) => Err("usage: fire <targetname>".to_string()),
+            (command, _) if Self::NAMES.contains(&command) => {
+                Err(format!("{} takes no arguments", command))
+            }
+            (command, _) => Err(format!("unknown command {}", command)),
+        }
+    }
+}
+
+/// Lines read from the standard input by a background thread.
+#[derive(Resource)]
+pub struct StdinConsole {
+    lines: Mutex<Receiver<String>>,
+}
+
+impl StdinConsole {
+    fn spawn() -> Self {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            lines: Mutex::new(receiver),
+        }
+    }
+}
+
+pub fn read_stdin_console_system(
+    console: Res<StdinConsole>,
+    mut input_events: EventWriter<MapConsoleInput>,
+) {
+    let Ok(lines) = console.lines.lock() else {
+        return;
+    };
+    for line in lines.try_iter() {
+        input_events.send(MapConsoleInput(line));
+    }
+}
+
+pub fn map_console_system(
+    settings: Res<MapConsoleSettings>,
+    asset_server: Res<AssetServer>,
+    map_assets: Res<Assets<MapAsset>>,
+    registry: Res<MapEntityRegistry>,
+    mut commands: Commands,
+    mut input_events: EventReader<MapConsoleInput>,
+    mut output_events: EventWriter<MapConsoleOutput>,
+    mut trigger_events: EventWriter<TriggeredEvent>,
+    mut despawning_events: EventWriter<MapDescendantDespawning>,
+    q_maps: Query<(Entity, &Map, Option<&MapBuildReport>)>,
+    q_children: Query<&Children>,
+    q_targets: Query<(&TriggerTarget, &GlobalTransform)>,
+    mut q_instigators: Query<(Entity, &mut Transform), With<TriggerInstigator>>,
+) {
+    let mut output = Vec::new();
+    for MapConsoleInput(line) in input_events.read() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match MapConsoleCommand::parse(line) {
+            Ok(command) => command,
+            Err(error) => {
+                output.push(error);
+                continue;
+            }
+        };
+        match command {
+            MapConsoleCommand::Map(name) => {
+                let path = settings.map_path(&name);
+                for (map_entity, ..) in q_maps.iter() {
+                    commands.entity(map_entity).despawn_recursive();
+                }
+                commands.spawn((
+                    MapBundle {
+                        map: Map {
+                            asset: asset_server.load(path.clone()),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Name::new(format!("map ({})", path)),
+                ));
+                output.push(format!("loading {}", path));
+            }
+            MapConsoleCommand::MapRestart => {
+                // the same two frame rebuild as when a map asset changes
+                for (map_entity, ..) in q_maps.iter() {
+                    for entity in q_children.iter_descendants(map_entity) {
+                        despawning_events.send(MapDescendantDespawning {
+                            map: map_entity,
+                            entity,
+                        });
+                    }
+                    commands.entity(map_entity).insert(MapRebuildPending);
+                }
+                output.push(format!("restarting {} map(s)", q_maps.iter().count()));
+            }
+            MapConsoleCommand::MapReport => {
+                if q_maps.is_empty() {
+                    output.push("no map loaded".to_string());
+                }
+                for (map_entity, map, report) in q_maps.iter() {
+                    let path = asset_server
+                        .get_path(map.asset.id())
+                        .map_or_else(|| format!("{:?}", map_entity), |path| path.to_string());
+                    let Some(report) = report else {
+                        output.push(format!("{}: not built", path));
+                        continue;
+                    };
+                    output.push(format!(
+                        "{}: {} entities, {} brushes, {} secrets, built in {:?}",
+                        path, report.entities, report.brushes, report.secrets, report.build_time
+                    ));
+                    for face in report.geometry.dropped_faces.iter() {
+                        output.push(format!("  {}", face));
+                    }
+                    if let Some(map_asset) = map_assets.get(&map.asset) {
+                        for issue in map_asset.validate_with_definitions(&registry.definitions) {
+                            output.push(format!("  {}", issue));
+                        }
+                    }
+                }
+            }
+            MapConsoleCommand::NoclipTo(target_name) => {
+                let Some((_, destination)) = q_targets
+                    .iter()
+                    .find(|(target, _)| target.target_name == target_name.as_str())
+                else {
+                    output.push(format!("no entity named {}", target_name));
+                    continue;
+                };
+                for (_, mut transform) in q_instigators.iter_mut() {
+                    transform.translation = destination.translation();
+                }
+            }
+            MapConsoleCommand::Fire(target) => {
+                let triggered_by = q_instigators
+                    .iter()
+                    .next()
+                    .map_or(Entity::PLACEHOLDER, |(entity, _)| entity);
+                trigger_events.send(TriggeredEvent {
+                    target: target.as_str().into(),
+                    triggered_by,
+                });
+            }
+        }
+    }
+    for line in output {
+        info!("{}", line);
+        output_events.send(MapConsoleOutput(line));
+    }
+}
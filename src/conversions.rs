@@ -1,7 +1,10 @@
 use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::render::view::RenderLayers;
 use shambler::{Vector2, Vector3};
 
 use crate::components::MapUnits;
+use crate::core::parse_vec3;
 
 pub const SHAMBLER_UNITS_TO_BEVY_METERS: f32 = 0.03125; // 1 meter = 32 units (1/32)
 
@@ -29,6 +32,54 @@ pub fn to_bevy_rotation(rotation: &Vec3) -> Quat {
     ) // * Quat::from_axis_angle(Vec3::Y, -90.0_f32.to_radians())
 }
 
+/// How an entity class encodes its orientation.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AngleConvention {
+    /// `angles` and `mangle` are "pitch yaw roll", with a positive pitch
+    /// looking down like Quake's view angles
+    #[default]
+    Standard,
+    /// `mangle` is "yaw pitch roll" with a positive pitch looking up, like
+    /// the spotlights of Quake's light tools
+    Light,
+}
+
+impl AngleConvention {
+    pub fn for_classname(classname: &str) -> Self {
+        if classname.starts_with("light") {
+            AngleConvention::Light
+        } else {
+            AngleConvention::Standard
+        }
+    }
+}
+
+/// Resolves the "pitch yaw roll" of an entity from its `angles`, `mangle`
+/// or `angle` property, in that order. An `angle` of -1 points up and -2
+/// down, like Quake's movers.
+pub fn resolve_angles<'a>(
+    get_property: impl Fn(&str) -> Option<&'a str>,
+    convention: AngleConvention,
+) -> Option<Vec3> {
+    if let Some(angles) = get_property("angles").and_then(parse_vec3) {
+        return Some(angles);
+    }
+    if let Some(mangle) = get_property("mangle").and_then(parse_vec3) {
+        return Some(match convention {
+            AngleConvention::Standard => mangle,
+            AngleConvention::Light => Vec3::new(-mangle.y, mangle.x, mangle.z),
+        });
+    }
+    let angle = get_property("angle")?.trim().parse::<f32>().ok()?;
+    Some(if angle == -1.0 {
+        Vec3::new(-90.0, 0.0, 0.0)
+    } else if angle == -2.0 {
+        Vec3::new(90.0, 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, angle, 0.0)
+    })
+}
+
 pub fn to_bevy_vertices(vertices: &Vec<Vector3>, map_units: &MapUnits) -> Vec<Vec3> {
     let mut bevy_vertices: Vec<Vec3> = Vec::new();
     for vertex in vertices {
@@ -56,3 +107,81 @@ pub fn uvs_to_bevy_vec2s(uvs: &Vec<Vector2>) -> Vec<Vec2> {
     }
     bevy_uvs
 }
+
+pub fn to_bevy_distance(distance: f32, map_units: &MapUnits) -> f32 {
+    match map_units {
+        MapUnits::Bevy => distance * SHAMBLER_UNITS_TO_BEVY_METERS,
+        MapUnits::Trenchbroom => distance,
+    }
+}
+
+/// Parses a color in 0–255 (`255 128 0`), 0–1 (`1.0 0.5 0.0`) or hex
+/// (`#ff8000`) format, with an optional 4th intensity component
+/// (`255 128 0 200`). Components above 1 mean the 0–255 range.
+#[cfg(feature = "render")]
+pub fn parse_color(value: &str) -> Option<(Color, Option<f32>)> {
+    parse_rgba(value).map(|([r, g, b, a], intensity)| (Color::rgba(r, g, b, a), intensity))
+}
+
+/// Parses a color like [`parse_color`], as sRGB components in the 0–1
+/// range, without depending on the renderer.
+pub fn parse_rgba(value: &str) -> Option<([f32; 4], Option<f32>)> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        return parse_hex(hex).map(|rgba| (rgba, None));
+    }
+
+    let components = value
+        .split_whitespace()
+        .map(|component| component.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (rgb, intensity) = match components.as_slice() {
+        [r, g, b] => ([*r, *g, *b], None),
+        [r, g, b, intensity] => ([*r, *g, *b], Some(*intensity)),
+        _ => return None,
+    };
+    let rgb = if rgb.iter().any(|component| *component > 1.0) {
+        rgb.map(|component| component / 255.0)
+    } else {
+        rgb
+    };
+    Some(([rgb[0], rgb[1], rgb[2], 1.0], intensity))
+}
+
+/// Parses `RGB`, `RGBA`, `RRGGBB` or `RRGGBBAA` hex colors.
+fn parse_hex(hex: &str) -> Option<[f32; 4]> {
+    if !hex.is_ascii() {
+        return None;
+    }
+    let digits = match hex.len() {
+        3 | 4 => hex
+            .chars()
+            .map(|digit| digit.to_digit(16).map(|v| v as u8 * 17))
+            .collect::<Option<Vec<_>>>()?,
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<_>>>()?,
+        _ => return None,
+    };
+    let component = |i: usize| digits.get(i).map_or(1.0, |v| *v as f32 / 255.0);
+    Some([component(0), component(1), component(2), component(3)])
+}
+
+/// Parses a list of render layer indices separated by spaces or commas, e.g. `0 2`.
+#[cfg(feature = "render")]
+pub fn parse_render_layers(value: &str) -> Option<RenderLayers> {
+    let layers = value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|layer| !layer.is_empty())
+        .map(|layer| layer.parse::<u8>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    if layers.is_empty()
+        || layers
+            .iter()
+            .any(|layer| *layer >= RenderLayers::TOTAL_LAYERS as u8)
+    {
+        return None;
+    }
+    Some(RenderLayers::from_layers(&layers))
+}
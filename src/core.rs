@@ -0,0 +1,746 @@
+//! The .map → geometry + entities pipeline, independent of the asset server
+//! and the ECS, so build scripts and CLI tools can precompile maps using the
+//! same code paths as the plugin.
+
+use bevy::math::bounding::Aabb3d;
+use bevy::math::{Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use thiserror::Error;
+
+use crate::brush_primitives::BrushPrimitives;
+use crate::components::{MapMirror, MapUnits};
+use crate::conversions::*;
+use crate::settings::GeometryTolerance;
+
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("map is not valid utf8")]
+    InvalidUtf8(#[from] std::str::Utf8Error),
+    #[error("invalid map")]
+    InvalidMap,
+}
+
+#[derive(Default, Clone)]
+pub struct CompileSettings {
+    pub units: MapUnits,
+    /// Texture sizes in pixels, used to generate UVs.
+    /// Textures missing from this map are treated as 1x1.
+    pub texture_sizes: BTreeMap<String, (u32, u32)>,
+    /// Textures replacing the ones of the faces with these indices, see
+    /// [`crate::MapAsset::retexture_face`].
+    pub face_textures: BTreeMap<usize, String>,
+    pub tolerance: GeometryTolerance,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CompiledMap {
+    pub entities: Vec<CompiledEntity>,
+    /// tessellated bezier patches from Doom 3 style maps
+    pub patches: Vec<CompiledPatch>,
+    #[serde(default)]
+    pub geometry_report: GeometryReport,
+}
+
+/// What the geometry cleanup changed, see [`GeometryTolerance`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeometryReport {
+    pub snapped_vertices: usize,
+    pub welded_vertices: usize,
+    pub dropped_triangles: usize,
+    /// faces dropped for having no triangles left
+    pub dropped_faces: Vec<DegenerateFace>,
+}
+
+/// A face of a tiny or malformed brush, without any triangle to draw.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DegenerateFace {
+    /// index of the entity in the .map file
+    pub entity: usize,
+    /// index of the brush in the .map file
+    pub brush: usize,
+    /// index of the face in the .map file
+    pub face: usize,
+    pub texture: String,
+    /// line of the face in the .map file, if known
+    pub line: Option<usize>,
+}
+
+impl std::fmt::Display for DegenerateFace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "degenerate face {} ({}) of brush {} in entity {}",
+            self.face, self.texture, self.brush, self.entity
+        )?;
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl GeometryReport {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl CompiledMap {
+    /// Bounds of the world geometry (every brush but triggers), in Bevy space.
+    pub fn bounds(&self) -> Option<Aabb3d> {
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+        for vertex in self
+            .entities
+            .iter()
+            .filter(|entity| !entity.classname.starts_with("trigger_"))
+            .flat_map(|entity| entity.brushes.iter())
+            .flat_map(|brush| brush.vertices.iter())
+        {
+            min = min.min(*vertex);
+            max = max.max(*vertex);
+        }
+        (min.cmple(max).all()).then_some(Aabb3d { min, max })
+    }
+
+    /// Uniformly scales every position of the map around its origin.
+    pub fn scale(&mut self, factor: f32) {
+        for entity in self.entities.iter_mut() {
+            entity.translation *= factor;
+            for brush in entity.brushes.iter_mut() {
+                brush
+                    .vertices
+                    .iter_mut()
+                    .for_each(|vertex| *vertex *= factor);
+                for face in brush.faces.iter_mut() {
+                    face.vertices
+                        .iter_mut()
+                        .for_each(|vertex| *vertex *= factor);
+                }
+            }
+        }
+        for patch in self.patches.iter_mut() {
+            patch
+                .vertices
+                .iter_mut()
+                .for_each(|vertex| *vertex *= factor);
+        }
+    }
+
+    /// Mirrors the map around its origin, flipping triangle winding so faces keep pointing outwards.
+    pub fn mirror(&mut self, mirror: MapMirror) {
+        for entity in self.entities.iter_mut() {
+            entity.translation = mirror.mirror_vec3(entity.translation);
+            entity.rotation = mirror.mirror_quat(entity.rotation);
+            for brush in entity.brushes.iter_mut() {
+                for vertex in brush.vertices.iter_mut() {
+                    *vertex = mirror.mirror_vec3(*vertex);
+                }
+                for face in brush.faces.iter_mut() {
+                    mirror_mesh(
+                        mirror,
+                        &mut face.vertices,
+                        &mut face.normals,
+                        &mut face.indices,
+                    );
+                }
+            }
+        }
+        for patch in self.patches.iter_mut() {
+            mirror_mesh(
+                mirror,
+                &mut patch.vertices,
+                &mut patch.normals,
+                &mut patch.indices,
+            );
+        }
+    }
+
+    pub fn classname_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for entity in self.entities.iter() {
+            *counts.entry(entity.classname.clone()).or_default() += 1;
+        }
+        counts
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CompiledEntity {
+    /// index of the entity in the .map file
+    pub index: usize,
+    pub classname: String,
+    pub properties: BTreeMap<String, String>,
+    /// parsed from the `origin` property, in Bevy space
+    pub translation: Vec3,
+    /// parsed from the `angles`, `mangle` or `angle` property, see [`resolve_angles`]
+    pub rotation: Quat,
+    /// empty for point entities (@PointClass)
+    pub brushes: Vec<CompiledBrush>,
+}
+
+fn mirror_mesh(
+    mirror: MapMirror,
+    vertices: &mut [Vec3],
+    normals: &mut [Vec3],
+    indices: &mut [u32],
+) {
+    for vertex in vertices.iter_mut() {
+        *vertex = mirror.mirror_vec3(*vertex);
+    }
+    for normal in normals.iter_mut() {
+        *normal = mirror.mirror_vec3(*normal);
+    }
+    for triangle in indices.chunks_exact_mut(3) {
+        triangle.swap(1, 2);
+    }
+}
+
+impl CompiledEntity {
+    /// Center of the bounds of the entity's brushes, or its translation for point entities.
+    pub fn center(&self) -> Vec3 {
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+        for vertex in self.brushes.iter().flat_map(|brush| brush.vertices.iter()) {
+            min = min.min(*vertex);
+            max = max.max(*vertex);
+        }
+        if min.cmple(max).all() {
+            (min + max) * 0.5
+        } else {
+            self.translation
+        }
+    }
+
+    pub fn is_brush_entity(&self) -> bool {
+        !self.brushes.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CompiledBrush {
+    /// index of the brush in the .map file
+    pub index: usize,
+    pub faces: Vec<CompiledFace>,
+    /// every face vertex of the brush, used to build its convex hull
+    pub vertices: Vec<Vec3>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CompiledFace {
+    /// index of the face in the .map file
+    pub index: usize,
+    pub texture: String,
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<u32>,
+}
+
+/// The drawable faces of a brush merged into shared buffers, sorted by texture
+/// so each texture covers one contiguous vertex and index range.
+#[derive(Debug, Default, Clone)]
+pub struct MergedBrushMesh {
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    /// indices into the shared vertex buffers
+    pub indices: Vec<u32>,
+    pub ranges: Vec<SubMeshRange>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SubMeshRange {
+    pub texture: String,
+    /// indices of the faces in the range, in the .map file
+    pub faces: Vec<usize>,
+    pub vertices: Range<usize>,
+    pub indices: Range<usize>,
+}
+
+impl CompiledBrush {
+    /// Merges the faces of the brush, skipping nodraw textures.
+    pub fn merged_mesh(&self) -> MergedBrushMesh {
+        let mut faces = self
+            .faces
+            .iter()
+            .filter(|face| !is_nodraw_texture(&face.texture))
+            .collect::<Vec<_>>();
+        faces.sort_by(|a, b| a.texture.cmp(&b.texture));
+
+        let mut merged = MergedBrushMesh::default();
+        for face in faces {
+            let base = merged.vertices.len();
+            let index_base = merged.indices.len();
+            merged.vertices.extend_from_slice(&face.vertices);
+            merged.normals.extend_from_slice(&face.normals);
+            if face.uvs.len() == face.vertices.len() {
+                merged.uvs.extend_from_slice(&face.uvs);
+            } else {
+                merged.uvs.resize(merged.vertices.len(), Vec2::ZERO);
+            }
+            merged
+                .indices
+                .extend(face.indices.iter().map(|index| index + base as u32));
+
+            match merged.ranges.last_mut() {
+                Some(range) if range.texture == face.texture => {
+                    range.faces.push(face.index);
+                    range.vertices.end = merged.vertices.len();
+                    range.indices.end = merged.indices.len();
+                }
+                _ => merged.ranges.push(SubMeshRange {
+                    texture: face.texture.clone(),
+                    faces: vec![face.index],
+                    vertices: base..merged.vertices.len(),
+                    indices: index_base..merged.indices.len(),
+                }),
+            }
+        }
+        merged
+    }
+}
+
+/// A tessellated bezier patch, render only.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CompiledPatch {
+    /// index of the entity the patch belongs to
+    pub entity: usize,
+    pub texture: String,
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<u32>,
+}
+
+impl CompiledFace {
+    /// The linear mapping from positions to UVs on this face, if the face
+    /// has UVs and at least one non-degenerate triangle.
+    pub fn uv_projection(&self) -> Option<UvProjection> {
+        if self.uvs.len() != self.vertices.len() {
+            return None;
+        }
+        self.indices.chunks_exact(3).find_map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            UvProjection::from_triangle(
+                [self.vertices[a], self.vertices[b], self.vertices[c]],
+                [self.uvs[a], self.uvs[b], self.uvs[c]],
+            )
+        })
+    }
+}
+
+/// UVs as a linear function of position: `uv = (u·p, v·p) + offset`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct UvProjection {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub offset: Vec2,
+}
+
+impl UvProjection {
+    pub fn from_triangle(positions: [Vec3; 3], uvs: [Vec2; 3]) -> Option<Self> {
+        let e1 = positions[1] - positions[0];
+        let e2 = positions[2] - positions[0];
+        let d1 = uvs[1] - uvs[0];
+        let d2 = uvs[2] - uvs[0];
+
+        // solve for gradients lying in the triangle's plane
+        let (a, b, c) = (e1.dot(e1), e1.dot(e2), e2.dot(e2));
+        let det = a * c - b * b;
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+        let gradient = |d1: f32, d2: f32| {
+            let s = (d1 * c - d2 * b) / det;
+            let t = (d2 * a - d1 * b) / det;
+            e1 * s + e2 * t
+        };
+        let u = gradient(d1.x, d2.x);
+        let v = gradient(d1.y, d2.y);
+        let offset = uvs[0] - Vec2::new(u.dot(positions[0]), v.dot(positions[0]));
+        Some(Self { u, v, offset })
+    }
+
+    pub fn project(&self, position: Vec3) -> Vec2 {
+        Vec2::new(self.u.dot(position), self.v.dot(position)) + self.offset
+    }
+
+    /// The same projection with its output multiplied by `scale`.
+    pub fn scaled(&self, scale: Vec2) -> Self {
+        Self {
+            u: self.u * scale.x,
+            v: self.v * scale.y,
+            offset: self.offset * scale,
+        }
+    }
+}
+
+/// Tool textures that are never rendered.
+pub fn is_nodraw_texture(texture_name: &str) -> bool {
+    matches!(
+        texture_name,
+        "trigger" | "clip" | "common/trigger" | "common/clip"
+    )
+}
+
+pub fn is_clip_texture(texture_name: &str) -> bool {
+    matches!(texture_name, "clip" | "common/clip")
+}
+
+/// Textures prefixed with `{` are alpha tested in Quake, used for foliage and fences.
+pub fn is_foliage_texture(texture_name: &str) -> bool {
+    texture_name
+        .rsplit('/')
+        .next()
+        .map_or(false, |name| name.starts_with('{'))
+}
+
+/// Parses and compiles a .map file in one go.
+pub fn compile_map(bytes: &[u8], settings: &CompileSettings) -> Result<CompiledMap, CompileError> {
+    let (geomap, brush_primitives) = parse_map(bytes)?;
+    Ok(compile_parsed_map(&geomap, &brush_primitives, settings))
+}
+
+/// Parses a .map file, see [`parse_map`]. Bezier patches are dropped.
+pub fn parse_geomap(bytes: &[u8]) -> Result<shambler::GeoMap, CompileError> {
+    Ok(parse_map(bytes)?.0)
+}
+
+/// Parses a .map file. Doom 3 style brush primitives are converted to classic
+/// brushes, with their texture matrices and patches returned alongside.
+pub fn parse_map(bytes: &[u8]) -> Result<(shambler::GeoMap, BrushPrimitives), CompileError> {
+    let (text, brush_primitives) = crate::brush_primitives::preprocess(std::str::from_utf8(bytes)?);
+    let map = text
+        .parse::<shalrath::repr::Map>()
+        .map_err(|_| CompileError::InvalidMap)?;
+    Ok((shambler::GeoMap::new(map), brush_primitives))
+}
+
+/// A map compiled ahead of time by the asset processor, see
+/// [`crate::process::MapProcessor`]. Texture sizes aren't known when
+/// processing, so standard face UVs are kept in texels and normalized by
+/// [`ProcessedMap::compile`] once the textures are loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessedMap {
+    /// units the map was compiled with
+    pub units: MapUnits,
+    pub compiled: CompiledMap,
+    /// faces with brush primitive UVs, which don't depend on texture sizes
+    pub size_independent_faces: BTreeSet<usize>,
+}
+
+impl ProcessedMap {
+    /// Parses and compiles a .map file, with UVs in texels.
+    pub fn new(
+        bytes: &[u8],
+        units: MapUnits,
+        tolerance: GeometryTolerance,
+    ) -> Result<Self, CompileError> {
+        let (geomap, brush_primitives) = parse_map(bytes)?;
+        let compiled = compile_parsed_map(
+            &geomap,
+            &brush_primitives,
+            &CompileSettings {
+                units: units.clone(),
+                tolerance,
+                ..Default::default()
+            },
+        );
+        Ok(Self {
+            units,
+            compiled,
+            size_independent_faces: brush_primitives.face_matrices.keys().copied().collect(),
+        })
+    }
+
+    /// The textures of the map's faces and patches.
+    pub fn texture_names(&self) -> BTreeSet<&str> {
+        self.compiled
+            .entities
+            .iter()
+            .flat_map(|entity| entity.brushes.iter())
+            .flat_map(|brush| brush.faces.iter())
+            .map(|face| face.texture.as_str())
+            .chain(
+                self.compiled
+                    .patches
+                    .iter()
+                    .map(|patch| patch.texture.as_str()),
+            )
+            .collect()
+    }
+
+    /// The compiled map, rescaled to `settings.units` and with its UVs
+    /// normalized by `settings.texture_sizes`.
+    pub fn compile(&self, settings: &CompileSettings) -> CompiledMap {
+        let mut compiled = self.compiled.clone();
+        let factor = to_bevy_distance(1.0, &settings.units) / to_bevy_distance(1.0, &self.units);
+        if factor != 1.0 {
+            compiled.scale(factor);
+        }
+        for face in compiled
+            .entities
+            .iter_mut()
+            .flat_map(|entity| entity.brushes.iter_mut())
+            .flat_map(|brush| brush.faces.iter_mut())
+        {
+            if let Some(replacement) = settings.face_textures.get(&face.index) {
+                face.texture = replacement.clone();
+            }
+            if self.size_independent_faces.contains(&face.index) {
+                continue;
+            }
+            if let Some((w, h)) = settings.texture_sizes.get(&face.texture) {
+                let size = Vec2::new(*w as f32, *h as f32);
+                face.uvs.iter_mut().for_each(|uv| *uv /= size);
+            }
+        }
+        compiled
+    }
+}
+
+pub fn compile_geomap(geomap: &shambler::GeoMap, settings: &CompileSettings) -> CompiledMap {
+    compile_parsed_map(geomap, &BrushPrimitives::default(), settings)
+}
+
+pub fn compile_parsed_map(
+    geomap: &shambler::GeoMap,
+    brush_primitives: &BrushPrimitives,
+    settings: &CompileSettings,
+) -> CompiledMap {
+    let face_trangle_planes = &geomap.face_planes;
+    let face_planes = shambler::face::face_planes(&face_trangle_planes);
+    let brush_hulls = shambler::brush::brush_hulls(&geomap.brush_faces, &face_planes);
+    let (face_vertices, _face_vertex_planes) =
+        shambler::face::face_vertices(&geomap.brush_faces, &face_planes, &brush_hulls);
+    let face_centers = shambler::face::face_centers(&face_vertices);
+    let face_indices = shambler::face::face_indices(
+        &geomap.face_planes,
+        &face_planes,
+        &face_vertices,
+        &face_centers,
+        shambler::face::FaceWinding::Clockwise,
+    );
+    let face_triangle_indices = shambler::face::face_triangle_indices(&face_indices);
+    let face_normals = shambler::face::normals_flat(&face_vertices, &face_planes);
+
+    let face_uvs = shambler::face::new(
+        &geomap.faces,
+        &geomap.textures,
+        &geomap.face_textures,
+        &face_vertices,
+        &face_planes,
+        &geomap.face_offsets,
+        &geomap.face_angles,
+        &geomap.face_scales,
+        &shambler::texture::texture_sizes(
+            &geomap.textures,
+            settings
+                .texture_sizes
+                .iter()
+                .map(|(name, size)| (name.as_str(), *size))
+                .collect(),
+        ),
+    );
+
+    let tolerance = settings.tolerance.to_bevy(&settings.units);
+    let mut geometry_report = GeometryReport::default();
+    let mut entities = Vec::new();
+    let mut brush_index = 0;
+    let mut face_index = 0;
+
+    for (entity_index, (entity_id, props)) in geomap.entity_properties.iter().enumerate() {
+        let properties = props
+            .iter()
+            .map(|p| (p.key.clone(), p.value.clone()))
+            .collect::<BTreeMap<_, _>>();
+
+        let classname = properties.get("classname").cloned().unwrap_or_default();
+        let translation = properties
+            .get("origin")
+            .and_then(|origin| parse_vec3(origin))
+            .map(|origin| to_bevy_position(&origin, &settings.units))
+            .unwrap_or(Vec3::ZERO);
+        let rotation = resolve_angles(
+            |key| properties.get(key).map(|value| value.as_str()),
+            AngleConvention::for_classname(&classname),
+        )
+        .map(|angles| to_bevy_rotation(&angles))
+        .unwrap_or(Quat::IDENTITY);
+
+        let mut brushes = Vec::new();
+        for brush_id in geomap.entity_brushes.get(entity_id).into_iter().flatten() {
+            let mut brush = CompiledBrush {
+                index: brush_index,
+                ..Default::default()
+            };
+            brush_index += 1;
+
+            for face_id in geomap.brush_faces.get(brush_id).into_iter().flatten() {
+                let texture = geomap
+                    .face_textures
+                    .get(face_id)
+                    .and_then(|texture_id| geomap.textures.get(texture_id))
+                    .cloned()
+                    .unwrap_or_default();
+
+                let uvs = match brush_primitives.face_matrices.get(&face_index) {
+                    Some(texture_matrix) => face_vertices[face_id]
+                        .iter()
+                        .map(|v| texture_matrix.uv(Vec3::new(v.x, v.y, v.z)))
+                        .collect(),
+                    None => uvs_to_bevy_vec2s(&face_uvs[face_id]),
+                };
+
+                let (texture, uvs) = match settings.face_textures.get(&face_index) {
+                    // standard UVs are normalized by the texture size,
+                    // brush primitive ones are already size independent
+                    Some(replacement)
+                        if !brush_primitives.face_matrices.contains_key(&face_index) =>
+                    {
+                        let size = |name: &str| {
+                            settings
+                                .texture_sizes
+                                .get(name)
+                                .map_or(Vec2::ONE, |(w, h)| Vec2::new(*w as f32, *h as f32))
+                        };
+                        let scale = size(&texture) / size(replacement);
+                        (
+                            replacement.clone(),
+                            uvs.into_iter().map(|uv: Vec2| uv * scale).collect(),
+                        )
+                    }
+                    Some(replacement) => (replacement.clone(), uvs),
+                    None => (texture, uvs),
+                };
+
+                let mut face = CompiledFace {
+                    index: face_index,
+                    texture,
+                    vertices: to_bevy_vertices(&face_vertices[face_id], &settings.units),
+                    normals: to_bevy_vec3s(&face_normals[face_id]),
+                    uvs,
+                    indices: to_bevy_indecies(&face_triangle_indices[face_id]),
+                };
+                face_index += 1;
+
+                if !clean_face(&mut face, &tolerance, &mut geometry_report) {
+                    geometry_report.dropped_faces.push(DegenerateFace {
+                        entity: entity_index,
+                        brush: brush.index,
+                        face: face.index,
+                        texture: face.texture,
+                        line: brush_primitives.face_lines.get(face.index).copied(),
+                    });
+                    continue;
+                }
+                brush.vertices.extend(face.vertices.iter().copied());
+                brush.faces.push(face);
+            }
+
+            brushes.push(brush);
+        }
+
+        entities.push(CompiledEntity {
+            index: entity_index,
+            classname,
+            properties,
+            translation,
+            rotation,
+            brushes,
+        });
+    }
+
+    let patches = brush_primitives
+        .patches
+        .iter()
+        .filter_map(|patch| {
+            let mesh = patch.tessellate()?;
+            Some(CompiledPatch {
+                entity: patch.entity,
+                texture: patch.texture.clone(),
+                vertices: mesh
+                    .vertices
+                    .iter()
+                    .map(|v| to_bevy_position(v, &settings.units))
+                    .collect(),
+                normals: mesh
+                    .normals
+                    .iter()
+                    .map(|n| Vec3::new(n.y, n.z, n.x))
+                    .collect(),
+                uvs: mesh.uvs,
+                indices: mesh.indices,
+            })
+        })
+        .collect();
+
+    CompiledMap {
+        entities,
+        patches,
+        geometry_report,
+    }
+}
+
+/// Snaps the vertices of a face to the grid, welds the close ones and drops
+/// degenerate triangles, returning false if none are left. `tolerance` is in
+/// Bevy space.
+fn clean_face(
+    face: &mut CompiledFace,
+    tolerance: &GeometryTolerance,
+    report: &mut GeometryReport,
+) -> bool {
+    if tolerance.grid_snap > 0.0 {
+        for vertex in face.vertices.iter_mut() {
+            let snapped = (*vertex / tolerance.grid_snap).round() * tolerance.grid_snap;
+            if snapped != *vertex {
+                *vertex = snapped;
+                report.snapped_vertices += 1;
+            }
+        }
+    }
+
+    // welded vertices stay in the buffers, just unreferenced
+    let max_distance_squared = tolerance.weld_distance * tolerance.weld_distance;
+    let mut welded_to = (0..face.vertices.len() as u32).collect::<Vec<_>>();
+    for i in 0..face.vertices.len() {
+        if let Some(j) = (0..i).find(|j| {
+            welded_to[*j] == *j as u32
+                && face.vertices[*j].distance_squared(face.vertices[i]) <= max_distance_squared
+        }) {
+            welded_to[i] = j as u32;
+            report.welded_vertices += 1;
+        }
+    }
+
+    let mut indices = Vec::with_capacity(face.indices.len());
+    for triangle in face.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| welded_to[triangle[i] as usize]);
+        let [pa, pb, pc] = [a, b, c].map(|i| face.vertices[i as usize]);
+        let area = (pb - pa).cross(pc - pa).length() * 0.5;
+        if a == b || b == c || a == c || area < tolerance.min_triangle_area {
+            report.dropped_triangles += 1;
+            continue;
+        }
+        indices.extend([a, b, c]);
+    }
+    face.indices = indices;
+    !face.indices.is_empty()
+}
+
+pub(crate) fn parse_vec3(value: &str) -> Option<Vec3> {
+    let value = value.split_whitespace().collect::<Vec<&str>>();
+    if value.len() == 3 {
+        if let (Ok(x), Ok(y), Ok(z)) = (
+            value[0].parse::<f32>(),
+            value[1].parse::<f32>(),
+            value[2].parse::<f32>(),
+        ) {
+            return Some(Vec3::new(x, y, z));
+        }
+    }
+    None
+}
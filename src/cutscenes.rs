@@ -0,0 +1,131 @@
+//! Cutscene hooks and a simple target sequencer.
+//!
+//! A `trigger_cutscene` volume sends a [`CutsceneRequested`] event with its
+//! `timeline` property the first time it's touched, for the game to play.
+//!
+//! Both `trigger_cutscene` volumes and `target_sequence` point entities can
+//! carry a [`Sequencer`]: a `targets` list fired one by one after the matching
+//! `delays` (seconds since the sequence started). A `target_sequence` starts
+//! when it's targeted.
+
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::components::{MapEntityProperties, TriggerTarget, TriggeredEvent, TriggeredOnce};
+use crate::intern::InternedStr;
+
+#[derive(Event, Debug, Clone)]
+pub struct CutsceneRequested(pub String);
+
+#[derive(Component, Debug, Clone)]
+pub struct CutsceneTrigger {
+    pub timeline: String,
+}
+
+#[derive(Component, Debug, Clone, Default)]
+pub struct Sequencer {
+    /// targets to fire, with their delay from the start of the sequence
+    pub steps: Vec<(Duration, InternedStr)>,
+}
+
+impl Sequencer {
+    /// Parses the `targets` and `delays` properties, both separated by commas
+    /// or whitespace. Missing delays default to the previous one.
+    pub fn parse(targets: &str, delays: Option<&str>) -> Self {
+        let mut delays = delays
+            .unwrap_or_default()
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|delay| !delay.is_empty())
+            .map(|delay| delay.parse::<f32>().unwrap_or(0.0).max(0.0));
+        let mut delay = 0.0;
+        let mut steps = targets
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|target| !target.is_empty())
+            .map(|target| {
+                delay = delays.next().unwrap_or(delay);
+                (Duration::from_secs_f32(delay), InternedStr::from(target))
+            })
+            .collect::<Vec<_>>();
+        steps.sort_by_key(|(delay, _)| *delay);
+        Self { steps }
+    }
+}
+
+/// Inserted on a [`Sequencer`] while it's playing.
+#[derive(Component, Debug, Clone, Default)]
+pub struct SequencePlayback {
+    pub elapsed: Duration,
+    pub next_step: usize,
+    pub triggered_by: Option<Entity>,
+}
+
+/// Inserts the [`Sequencer`] component for a `target_sequence` entity.
+pub fn apply_sequence_properties(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let targets = props
+        .get_property_as_string("targets", None)
+        .unwrap_or_default();
+    let delays = props.get_property_as_string("delays", None);
+    commands
+        .entity(entity)
+        .insert(Sequencer::parse(&targets, delays.as_deref()));
+}
+
+pub fn cutscene_trigger_system(
+    mut commands: Commands,
+    mut cutscene_events: EventWriter<CutsceneRequested>,
+    q_triggers: Query<(Entity, &CutsceneTrigger, Has<Sequencer>), Added<TriggeredOnce>>,
+) {
+    for (entity, cutscene_trigger, has_sequencer) in q_triggers.iter() {
+        if !cutscene_trigger.timeline.is_empty() {
+            cutscene_events.send(CutsceneRequested(cutscene_trigger.timeline.clone()));
+        }
+        if has_sequencer {
+            commands.entity(entity).insert(SequencePlayback::default());
+        }
+    }
+}
+
+pub fn activate_sequences_system(
+    mut commands: Commands,
+    mut trigger_events: EventReader<TriggeredEvent>,
+    q_sequencers: Query<(Entity, &TriggerTarget), With<Sequencer>>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (entity, trigger_target) in q_sequencers.iter() {
+            if trigger_target.target_name == trigger_event.target {
+                commands.entity(entity).insert(SequencePlayback {
+                    triggered_by: Some(trigger_event.triggered_by),
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+pub fn sequence_playback_system(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut trigger_events: EventWriter<TriggeredEvent>,
+    mut q_sequencers: Query<(Entity, &Sequencer, &mut SequencePlayback)>,
+) {
+    for (entity, sequencer, mut playback) in q_sequencers.iter_mut() {
+        playback.elapsed += time.delta();
+        while let Some((delay, target)) = sequencer.steps.get(playback.next_step) {
+            if *delay > playback.elapsed {
+                break;
+            }
+            trigger_events.send(TriggeredEvent {
+                target: target.clone(),
+                triggered_by: playback.triggered_by.unwrap_or(entity),
+            });
+            playback.next_step += 1;
+        }
+        if playback.next_step >= sequencer.steps.len() {
+            commands.entity(entity).remove::<SequencePlayback>();
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Damageable brush entities.
+//!
+//! Any entity with a `_health` property gets a [`MapDamageable`]. Games send
+//! a [`DamageEvent`] for the entity that was hit, either the brush entity or
+//! one of its colliders, and once its health is depleted a [`DeathEvent`] is
+//! sent and its `target` is fired, e.g. for shoot-to-open doors.
+
+use bevy::prelude::*;
+
+use crate::components::{MapEntityProperties, TriggeredEvent};
+use crate::intern::InternedStr;
+
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct MapDamageable {
+    pub health: f32,
+    pub max_health: f32,
+    /// fired when the health is depleted
+    #[reflect(ignore)]
+    pub target: Option<InternedStr>,
+}
+
+impl MapDamageable {
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+}
+
+/// Damage dealt to an entity, sent by the game.
+#[derive(Event, Debug, Clone)]
+pub struct DamageEvent {
+    /// the damaged entity, or any of its descendants
+    pub entity: Entity,
+    pub amount: f32,
+    pub attacker: Option<Entity>,
+}
+
+/// Sent when a [`MapDamageable`] runs out of health.
+#[derive(Event, Debug, Clone)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killed_by: Option<Entity>,
+}
+
+/// Inserts a [`MapDamageable`] for entities with a positive `_health`.
+pub fn apply_damageable_properties(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let health = props.get_property_as_f32("_health", 0.0);
+    if health <= 0.0 {
+        return;
+    }
+    commands.entity(entity).insert(MapDamageable {
+        health,
+        max_health: health,
+        target: props.properties.get("target").cloned(),
+    });
+}
+
+/// The entity itself or its closest ancestor with a [`MapDamageable`].
+pub fn damageable_of(
+    entity: Entity,
+    q_parents: &Query<&Parent>,
+    q_damageables: &Query<(Entity, &mut MapDamageable)>,
+) -> Option<Entity> {
+    std::iter::once(entity)
+        .chain(q_parents.iter_ancestors(entity))
+        .find(|entity| q_damageables.contains(*entity))
+}
+
+pub fn damage_system(
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut trigger_events: EventWriter<TriggeredEvent>,
+    q_parents: Query<&Parent>,
+    mut q_damageables: Query<(Entity, &mut MapDamageable)>,
+) {
+    for ev in damage_events.read() {
+        let Some(entity) = damageable_of(ev.entity, &q_parents, &q_damageables) else {
+            continue;
+        };
+        let Ok((_, mut damageable)) = q_damageables.get_mut(entity) else {
+            continue;
+        };
+        if damageable.is_dead() {
+            continue;
+        }
+        damageable.health -= ev.amount;
+        if !damageable.is_dead() {
+            continue;
+        }
+
+        death_events.send(DeathEvent {
+            entity,
+            killed_by: ev.attacker,
+        });
+        if let Some(target) = &damageable.target {
+            trigger_events.send(TriggeredEvent {
+                target: target.clone(),
+                triggered_by: ev.attacker.unwrap_or(entity),
+            });
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Detail textures, blended over the base color up close.
+//!
+//! A `textures/{name}.detail.png` texture (see
+//! [`crate::settings::MapLoaderSettings::texture_suffixes`]) is tiled over
+//! the base color, hiding the blurriness of low resolution textures. Its
+//! tiling, fade distance and strength can be set in the texture's material
+//! definition file:
+//!
+//! ```ron
+//! (
+//!     detail: Some((tiling: Some(8.0), fade_distance: Some(4.0))),
+//! )
+//! ```
+//!
+//! Add [`DetailTexturePlugin`] to swap the materials of textures that have a
+//! detail texture for a [`DetailMaterial`].
+
+use bevy::asset::load_internal_asset;
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+use crate::materials::TextureSlot;
+use crate::MapAsset;
+
+const DETAIL_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x5d1c_7a4e_93b2_4f0e_8c61_2a9d_0b47_e3f1);
+
+pub type DetailMaterial = ExtendedMaterial<StandardMaterial, DetailExtension>;
+
+#[derive(Default)]
+pub struct DetailTexturePlugin;
+
+impl Plugin for DetailTexturePlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            DETAIL_SHADER_HANDLE,
+            "shaders/detail.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugins(MaterialPlugin::<DetailMaterial>::default())
+            .init_resource::<DetailMaterials>()
+            .add_systems(
+                Update,
+                (
+                    create_detail_materials_system,
+                    apply_detail_materials_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct DetailExtension {
+    #[uniform(100)]
+    pub settings: DetailSettings,
+    #[texture(101)]
+    #[sampler(102)]
+    pub detail_texture: Handle<Image>,
+}
+
+impl MaterialExtension for DetailExtension {
+    fn fragment_shader() -> ShaderRef {
+        DETAIL_SHADER_HANDLE.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        DETAIL_SHADER_HANDLE.into()
+    }
+}
+
+#[derive(ShaderType, Reflect, Debug, Clone, Copy)]
+pub struct DetailSettings {
+    /// how many times the detail texture repeats over the base texture
+    pub tiling: f32,
+    /// distance at which the detail fully fades out, in Bevy units
+    pub fade_distance: f32,
+    pub strength: f32,
+}
+
+impl Default for DetailSettings {
+    fn default() -> Self {
+        Self {
+            tiling: 8.0,
+            fade_distance: 4.0,
+            strength: 1.0,
+        }
+    }
+}
+
+/// Detail parameters of a material definition, unset fields use [`DetailSettings::default`].
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct DetailDefinition {
+    pub tiling: Option<f32>,
+    pub fade_distance: Option<f32>,
+    pub strength: Option<f32>,
+}
+
+impl DetailDefinition {
+    pub fn settings(&self) -> DetailSettings {
+        let default = DetailSettings::default();
+        DetailSettings {
+            tiling: self.tiling.unwrap_or(default.tiling),
+            fade_distance: self.fade_distance.unwrap_or(default.fade_distance),
+            strength: self.strength.unwrap_or(default.strength),
+        }
+    }
+}
+
+/// Detail materials replacing the standard materials of map textures.
+#[derive(Resource, Default)]
+pub struct DetailMaterials {
+    pub materials: HashMap<AssetId<StandardMaterial>, Handle<DetailMaterial>>,
+}
+
+pub fn create_detail_materials_system(
+    mut detail_materials: ResMut<DetailMaterials>,
+    mut ev_asset: EventReader<AssetEvent<MapAsset>>,
+    map_assets: Res<Assets<MapAsset>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<DetailMaterial>>,
+) {
+    for ev in ev_asset.read() {
+        let AssetEvent::LoadedWithDependencies { id } = ev else {
+            continue;
+        };
+        let Some(map_asset) = map_assets.get(*id) else {
+            continue;
+        };
+        for (texture_name, slots) in map_asset.auxiliary_textures.iter() {
+            let Some(detail_texture) = slots.get(&TextureSlot::Detail) else {
+                continue;
+            };
+            let Some(handle) = map_asset.material_handles.get(texture_name) else {
+                continue;
+            };
+            let Some(base) = standard_materials.get(handle) else {
+                continue;
+            };
+            let settings = map_asset
+                .material_definitions
+                .get(texture_name)
+                .and_then(|definition| definition.detail.as_ref())
+                .map(|detail| detail.settings())
+                .unwrap_or_default();
+            let material = materials.add(DetailMaterial {
+                base: base.clone(),
+                extension: DetailExtension {
+                    settings,
+                    detail_texture: detail_texture.clone(),
+                },
+            });
+            detail_materials.materials.insert(handle.id(), material);
+        }
+    }
+}
+
+pub fn apply_detail_materials_system(
+    mut commands: Commands,
+    detail_materials: Res<DetailMaterials>,
+    q_meshes: Query<(Entity, &Handle<StandardMaterial>)>,
+) {
+    if detail_materials.materials.is_empty() {
+        return;
+    }
+    for (entity, handle) in q_meshes.iter() {
+        if let Some(material) = detail_materials.materials.get(&handle.id()) {
+            commands
+                .entity(entity)
+                .remove::<Handle<StandardMaterial>>()
+                .insert(material.clone());
+        }
+    }
+}
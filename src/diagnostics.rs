@@ -0,0 +1,85 @@
+//! Map stats for Bevy's diagnostics, e.g. to show them with
+//! `LogDiagnosticsPlugin` alongside FPS.
+//!
+//! Memory diagnostics are the sum of the [`MapMemoryUsage`] of every built map.
+//! The build time is measured each time a map is built, and the draw call
+//! estimate is the number of visible `MapMesh`es, with the `render` feature.
+
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::*;
+
+#[cfg(feature = "render")]
+use crate::components::MapMesh;
+use crate::components::{
+    MapBuildReport, MapMemoryUsage, Mover, TriggerMultiple, TriggerOnce, TriggeredOnce,
+};
+
+pub const BUILD_TIME: DiagnosticPath = DiagnosticPath::const_new("qevy/build_time_ms");
+pub const DRAW_CALL_ESTIMATE: DiagnosticPath = DiagnosticPath::const_new("qevy/draw_call_estimate");
+pub const ACTIVE_TRIGGERS: DiagnosticPath = DiagnosticPath::const_new("qevy/active_triggers");
+pub const MOVER_COUNT: DiagnosticPath = DiagnosticPath::const_new("qevy/mover_count");
+
+pub const VERTEX_BYTES: DiagnosticPath = DiagnosticPath::const_new("qevy/vertex_bytes");
+pub const INDEX_BYTES: DiagnosticPath = DiagnosticPath::const_new("qevy/index_bytes");
+pub const TEXTURE_BYTES: DiagnosticPath = DiagnosticPath::const_new("qevy/texture_bytes");
+pub const COLLIDER_BYTES: DiagnosticPath = DiagnosticPath::const_new("qevy/collider_bytes");
+
+#[derive(Default)]
+pub struct MapDiagnosticsPlugin;
+
+impl Plugin for MapDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        for path in [VERTEX_BYTES, INDEX_BYTES, TEXTURE_BYTES, COLLIDER_BYTES] {
+            app.register_diagnostic(Diagnostic::new(path).with_suffix("B"));
+        }
+        app.register_diagnostic(Diagnostic::new(BUILD_TIME).with_suffix("ms"))
+            .register_diagnostic(Diagnostic::new(DRAW_CALL_ESTIMATE))
+            .register_diagnostic(Diagnostic::new(ACTIVE_TRIGGERS))
+            .register_diagnostic(Diagnostic::new(MOVER_COUNT))
+            .add_systems(Update, (memory_diagnostics_system, map_diagnostics_system));
+    }
+}
+
+pub fn memory_diagnostics_system(
+    mut diagnostics: Diagnostics,
+    q_memory_usage: Query<&MapMemoryUsage>,
+) {
+    let mut total = MapMemoryUsage::default();
+    for memory_usage in q_memory_usage.iter() {
+        total.vertex_bytes += memory_usage.vertex_bytes;
+        total.index_bytes += memory_usage.index_bytes;
+        total.texture_bytes += memory_usage.texture_bytes;
+        total.collider_bytes += memory_usage.collider_bytes;
+    }
+    diagnostics.add_measurement(&VERTEX_BYTES, || total.vertex_bytes as f64);
+    diagnostics.add_measurement(&INDEX_BYTES, || total.index_bytes as f64);
+    diagnostics.add_measurement(&TEXTURE_BYTES, || total.texture_bytes as f64);
+    diagnostics.add_measurement(&COLLIDER_BYTES, || total.collider_bytes as f64);
+}
+
+pub fn map_diagnostics_system(
+    mut diagnostics: Diagnostics,
+    q_reports: Query<Ref<MapBuildReport>>,
+    #[cfg(feature = "render")] q_meshes: Query<&ViewVisibility, With<MapMesh>>,
+    q_triggers: Query<
+        (),
+        Or<(
+            With<TriggerMultiple>,
+            (With<TriggerOnce>, Without<TriggeredOnce>),
+        )>,
+    >,
+    q_movers: Query<(), With<Mover>>,
+) {
+    for report in q_reports.iter().filter(|report| report.is_changed()) {
+        diagnostics.add_measurement(&BUILD_TIME, || report.build_time.as_secs_f64() * 1000.0);
+    }
+    #[cfg(feature = "render")]
+    diagnostics.add_measurement(&DRAW_CALL_ESTIMATE, || {
+        q_meshes
+            .iter()
+            .filter(|visibility| visibility.get())
+            .count() as f64
+    });
+    diagnostics.add_measurement(&ACTIVE_TRIGGERS, || q_triggers.iter().count() as f64);
+    diagnostics.add_measurement(&MOVER_COUNT, || q_movers.iter().count() as f64);
+}
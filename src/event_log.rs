@@ -0,0 +1,236 @@
+//! A log of what map gameplay did recently, for debugging trigger chains.
+//!
+//! [`MapEventLog`] keeps the last `capacity` fired targets, trigger volume
+//! entries and exits, triggers awaiting confirmation and mover phase changes,
+//! each with the time it happened and the entities involved. With the
+//! `inspector` feature the log can also be shown on screen, see
+//! [`crate::inspector::MapInspectorSettings::show_event_log`].
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::components::{
+    MoverPhase, MoverSync, TriggerEntered, TriggerExited, TriggeredEvent, WouldFireTrigger,
+};
+use crate::intern::InternedStr;
+
+/// The most recent [`MapLogEntry`]s, oldest first.
+#[derive(Resource, Debug, Clone)]
+pub struct MapEventLog {
+    /// entries kept before the oldest ones are dropped
+    pub capacity: usize,
+    /// stop recording, e.g. to inspect the log at a given point
+    pub paused: bool,
+    entries: VecDeque<MapLogEntry>,
+}
+
+impl Default for MapEventLog {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            paused: false,
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl MapEventLog {
+    /// Adds an entry, dropping the oldest ones beyond the capacity.
+    pub fn record(&mut self, time: f32, kind: MapLogKind) {
+        if self.paused || self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(MapLogEntry { time, kind });
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &MapLogEntry> {
+        self.entries.iter()
+    }
+
+    /// Entries involving `entity`, oldest first.
+    pub fn entries_for(&self, entity: Entity) -> impl Iterator<Item = &MapLogEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.kind.entities().contains(&entity))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MapLogEntry {
+    /// seconds since startup, see [`Time::elapsed_seconds`]
+    pub time: f32,
+    pub kind: MapLogKind,
+}
+
+impl fmt::Display for MapLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:>8.3}] {}", self.time, self.kind)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MapLogKind {
+    /// a [`TriggeredEvent`] was sent
+    TargetFired {
+        target: InternedStr,
+        triggered_by: Entity,
+    },
+    TriggerEntered {
+        trigger: Entity,
+        activator: Entity,
+    },
+    TriggerExited {
+        trigger: Entity,
+        activator: Entity,
+    },
+    /// a trigger is waiting to be confirmed, see [`WouldFireTrigger`]
+    TriggerAwaitingConfirmation {
+        trigger: Entity,
+        target: InternedStr,
+        triggered_by: Entity,
+    },
+    MoverPhaseChanged {
+        mover: Entity,
+        from: MoverPhase,
+        to: MoverPhase,
+    },
+}
+
+impl MapLogKind {
+    /// The entities involved, for filtering.
+    pub fn entities(&self) -> Vec<Entity> {
+        match self {
+            Self::TargetFired { triggered_by, .. } => vec![*triggered_by],
+            Self::TriggerEntered { trigger, activator }
+            | Self::TriggerExited { trigger, activator } => vec![*trigger, *activator],
+            Self::TriggerAwaitingConfirmation {
+                trigger,
+                triggered_by,
+                ..
+            } => vec![*trigger, *triggered_by],
+            Self::MoverPhaseChanged { mover, .. } => vec![*mover],
+        }
+    }
+}
+
+impl fmt::Display for MapLogKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TargetFired {
+                target,
+                triggered_by,
+            } => write!(f, "{:?} fired \"{}\"", triggered_by, target),
+            Self::TriggerEntered { trigger, activator } => {
+                write!(f, "{:?} entered trigger {:?}", activator, trigger)
+            }
+            Self::TriggerExited { trigger, activator } => {
+                write!(f, "{:?} left trigger {:?}", activator, trigger)
+            }
+            Self::TriggerAwaitingConfirmation {
+                trigger,
+                target,
+                triggered_by,
+            } => write!(
+                f,
+                "trigger {:?} would fire \"{}\" for {:?}",
+                trigger, target, triggered_by
+            ),
+            Self::MoverPhaseChanged { mover, from, to } => {
+                write!(f, "mover {:?}: {:?} -> {:?}", mover, from, to)
+            }
+        }
+    }
+}
+
+pub fn record_trigger_events_system(
+    time: Res<Time>,
+    mut log: ResMut<MapEventLog>,
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut entered_events: EventReader<TriggerEntered>,
+    mut exited_events: EventReader<TriggerExited>,
+    mut would_fire_events: EventReader<WouldFireTrigger>,
+) {
+    let now = time.elapsed_seconds();
+    for ev in entered_events.read() {
+        log.record(
+            now,
+            MapLogKind::TriggerEntered {
+                trigger: ev.trigger,
+                activator: ev.activator,
+            },
+        );
+    }
+    for ev in exited_events.read() {
+        log.record(
+            now,
+            MapLogKind::TriggerExited {
+                trigger: ev.trigger,
+                activator: ev.activator,
+            },
+        );
+    }
+    for ev in would_fire_events.read() {
+        log.record(
+            now,
+            MapLogKind::TriggerAwaitingConfirmation {
+                trigger: ev.trigger,
+                target: ev.target.clone(),
+                triggered_by: ev.triggered_by,
+            },
+        );
+    }
+    for ev in trigger_events.read() {
+        log.record(
+            now,
+            MapLogKind::TargetFired {
+                target: ev.target.clone(),
+                triggered_by: ev.triggered_by,
+            },
+        );
+    }
+}
+
+pub fn record_mover_phases_system(
+    time: Res<Time>,
+    mut log: ResMut<MapEventLog>,
+    // the last phase seen of each mover
+    mut phases: Local<HashMap<Entity, MoverPhase>>,
+    q_movers: Query<(Entity, &MoverSync), Changed<MoverSync>>,
+    mut removed: RemovedComponents<MoverSync>,
+) {
+    for entity in removed.read() {
+        phases.remove(&entity);
+    }
+    for (mover, sync) in q_movers.iter() {
+        let Some(from) = phases.insert(mover, sync.phase) else {
+            continue;
+        };
+        if from != sync.phase {
+            log.record(
+                time.elapsed_seconds(),
+                MapLogKind::MoverPhaseChanged {
+                    mover,
+                    from,
+                    to: sync.phase,
+                },
+            );
+        }
+    }
+}
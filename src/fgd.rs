@@ -0,0 +1,604 @@
+//! Entity definitions (.fgd) of existing games.
+//!
+//! [`EntityDefinitions::parse`] reads the classes of an FGD file, in the
+//! syntax shared by TrenchBroom and Hammer, with their properties, defaults,
+//! choices and spawnflags. Registering them on the
+//! [`crate::registry::MapEntityRegistry`] fills in the defaults of entities
+//! without a built-in handler that maps leave out, and has property values
+//! checked against their declared types by
+//! [`crate::MapAsset::validate_with_definitions`], the build's issue log and
+//! the `map_report` console command:
+//!
+//! ```ignore
+//! let definitions = EntityDefinitions::parse(include_str!("quake.fgd"))?;
+//! app.world
+//!     .resource_mut::<MapEntityRegistry>()
+//!     .register_definitions(&definitions);
+//! ```
+//!
+//! Class sizes, colors, models and Hammer's inputs and outputs are skipped,
+//! and so are `@include`s, whose files should be parsed on their own.
+
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum FgdError {
+    #[error("line {line}: unterminated string")]
+    UnterminatedString { line: usize },
+    #[error("line {line}: expected {expected}, found `{found}`")]
+    Unexpected {
+        line: usize,
+        expected: &'static str,
+        found: String,
+    },
+    #[error("unexpected end of file, expected {expected}")]
+    UnexpectedEnd { expected: &'static str },
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EntityDefinitions {
+    pub classes: BTreeMap<String, EntityDefinition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassKind {
+    /// `@BaseClass`, only inherited from
+    Base,
+    Point,
+    Solid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityDefinition {
+    pub kind: ClassKind,
+    pub description: Option<String>,
+    /// classes whose properties this one inherits, from `base(...)`
+    pub bases: Vec<String>,
+    pub properties: Vec<PropertyDefinition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyDefinition {
+    pub name: String,
+    pub kind: PropertyKind,
+    pub display_name: Option<String>,
+    pub default: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyKind {
+    String,
+    Integer,
+    Float,
+    /// `color255` or `color1`
+    Color,
+    /// values and their labels
+    Choices(Vec<(String, String)>),
+    /// bits, their labels and whether they're set by default
+    Flags(Vec<(u32, String, bool)>),
+    /// any other type, e.g. `target_source` or `studio`, kept as written
+    Other(String),
+}
+
+impl EntityDefinitions {
+    pub fn parse(text: &str) -> Result<Self, FgdError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let mut definitions = Self::default();
+        while let Some(token) = parser.next() {
+            let Token::Word(word) = &token.token else {
+                return Err(token.unexpected("a class"));
+            };
+            let kind = match word.to_ascii_lowercase().as_str() {
+                "@baseclass" => ClassKind::Base,
+                "@pointclass" => ClassKind::Point,
+                "@solidclass" => ClassKind::Solid,
+                "@include" | "@mapsize" | "@materialexclusion" | "@autovisgroup" => {
+                    parser.skip_directive();
+                    continue;
+                }
+                _ => return Err(token.unexpected("a class")),
+            };
+            let (classname, definition) = parser.class(kind)?;
+            definitions.classes.insert(classname, definition);
+        }
+        Ok(definitions)
+    }
+
+    /// The properties of a class, its own overriding the ones of its bases.
+    pub fn properties(&self, classname: &str) -> Vec<&PropertyDefinition> {
+        let mut properties: Vec<&PropertyDefinition> = Vec::new();
+        self.collect_properties(classname, &mut properties, 0);
+        properties
+    }
+
+    fn collect_properties<'a>(
+        &'a self,
+        classname: &str,
+        properties: &mut Vec<&'a PropertyDefinition>,
+        depth: usize,
+    ) {
+        // bases that inherit from each other
+        if depth > 32 {
+            return;
+        }
+        let Some(class) = self.classes.get(classname) else {
+            return;
+        };
+        for base in class.bases.iter() {
+            self.collect_properties(base, properties, depth + 1);
+        }
+        for property in class.properties.iter() {
+            properties.retain(|p| p.name != property.name);
+            properties.push(property);
+        }
+    }
+
+    /// The default value of each property of a class that has one. The
+    /// default of flags is the sum of their bits set by default.
+    pub fn defaults(&self, classname: &str) -> Vec<(String, String)> {
+        self.properties(classname)
+            .into_iter()
+            .filter_map(|property| {
+                let default = match &property.kind {
+                    PropertyKind::Flags(flags) => {
+                        let bits = flags
+                            .iter()
+                            .filter(|(_, _, set)| *set)
+                            .fold(0, |bits, (bit, ..)| bits | bit);
+                        (bits != 0).then(|| bits.to_string())
+                    }
+                    _ => property.default.clone(),
+                }?;
+                Some((property.name.clone(), default))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(char),
+}
+
+#[derive(Debug, Clone)]
+struct LineToken {
+    token: Token,
+    line: usize,
+}
+
+impl LineToken {
+    fn unexpected(&self, expected: &'static str) -> FgdError {
+        FgdError::Unexpected {
+            line: self.line,
+            expected,
+            found: match &self.token {
+                Token::Word(word) => word.clone(),
+                Token::Quoted(text) => format!("\"{}\"", text),
+                Token::Symbol(symbol) => symbol.to_string(),
+            },
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<LineToken>, FgdError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|c| *c != '\n').is_some() {},
+            '"' => {
+                let start = line;
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\n') => {
+                            line += 1;
+                            quoted.push('\n');
+                        }
+                        Some(c) => quoted.push(c),
+                        None => return Err(FgdError::UnterminatedString { line: start }),
+                    }
+                }
+                // "long " + "descriptions" are concatenated
+                match tokens.len().checked_sub(2).map(|i| &tokens[i..]) {
+                    Some(
+                        [LineToken {
+                            token: Token::Quoted(previous),
+                            ..
+                        }, LineToken {
+                            token: Token::Symbol('+'),
+                            ..
+                        }],
+                    ) => {
+                        let joined = format!("{}{}", previous, quoted);
+                        tokens.truncate(tokens.len() - 2);
+                        tokens.push(LineToken {
+                            token: Token::Quoted(joined),
+                            line: start,
+                        });
+                    }
+                    _ => tokens.push(LineToken {
+                        token: Token::Quoted(quoted),
+                        line: start,
+                    }),
+                }
+            }
+            '(' | ')' | '[' | ']' | '{' | '}' | '=' | ':' | ',' | '+' => tokens.push(LineToken {
+                token: Token::Symbol(c),
+                line,
+            }),
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()[]{}=:,+\"".contains(*c))
+                {
+                    word.push(c);
+                }
+                tokens.push(LineToken {
+                    token: Token::Word(word),
+                    line,
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<LineToken>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<LineToken> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|token| &token.token)
+    }
+
+    fn expect_next(&mut self, expected: &'static str) -> Result<LineToken, FgdError> {
+        self.next().ok_or(FgdError::UnexpectedEnd { expected })
+    }
+
+    fn expect_symbol(&mut self, symbol: char, expected: &'static str) -> Result<(), FgdError> {
+        let token = self.expect_next(expected)?;
+        match token.token {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            _ => Err(token.unexpected(expected)),
+        }
+    }
+
+    fn word(&mut self, expected: &'static str) -> Result<String, FgdError> {
+        let token = self.expect_next(expected)?;
+        match token.token {
+            Token::Word(word) => Ok(word),
+            _ => Err(token.unexpected(expected)),
+        }
+    }
+
+    /// Skips the arguments of a directive such as `@include "base.fgd"`,
+    /// `@mapsize(-4096, 4096)`, `@MaterialExclusion [ "tools" ]` or
+    /// `@AutoVisGroup = "Triggers" [ ... ]`.
+    fn skip_directive(&mut self) {
+        if self.peek() == Some(&Token::Symbol('=')) {
+            self.position += 1;
+            if matches!(self.peek(), Some(Token::Quoted(_))) {
+                self.position += 1;
+            }
+        }
+        match self.peek() {
+            Some(Token::Quoted(_)) => self.position += 1,
+            Some(Token::Symbol('(' | '[')) => self.skip_group(),
+            _ => {}
+        }
+    }
+
+    /// Skips a balanced `(...)`, `[...]` or `{...}` group.
+    fn skip_group(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token.token {
+                Token::Symbol('(' | '[' | '{') => depth += 1,
+                Token::Symbol(')' | ']' | '}') => depth -= 1,
+                _ => {}
+            }
+            if depth <= 0 {
+                return;
+            }
+        }
+    }
+
+    fn class(&mut self, kind: ClassKind) -> Result<(String, EntityDefinition), FgdError> {
+        let mut bases = Vec::new();
+        loop {
+            let token = self.expect_next("`=`")?;
+            match token.token {
+                Token::Symbol('=') => break,
+                Token::Word(attribute) if attribute.eq_ignore_ascii_case("base") => {
+                    self.expect_symbol('(', "`(`")?;
+                    loop {
+                        let token = self.expect_next("`)`")?;
+                        match token.token {
+                            Token::Symbol(')') => break,
+                            Token::Symbol(',') => {}
+                            Token::Word(base) => bases.push(base),
+                            _ => return Err(token.unexpected("a base class")),
+                        }
+                    }
+                }
+                Token::Word(_) => {
+                    if self.peek() == Some(&Token::Symbol('(')) {
+                        self.skip_group();
+                    }
+                }
+                _ => return Err(token.unexpected("`=`")),
+            }
+        }
+
+        let classname = self.word("a classname")?;
+        let mut description = None;
+        if self.peek() == Some(&Token::Symbol(':')) {
+            self.position += 1;
+            description = self.optional_value();
+        }
+        self.expect_symbol('[', "`[`")?;
+        let mut properties = Vec::new();
+        loop {
+            let token = self.expect_next("`]`")?;
+            let name = match token.token {
+                Token::Symbol(']') => break,
+                Token::Word(name) => name,
+                _ => return Err(token.unexpected("a property")),
+            };
+            if name.eq_ignore_ascii_case("input") || name.eq_ignore_ascii_case("output") {
+                self.word("an input or output name")?;
+                self.skip_group();
+                if self.peek() == Some(&Token::Symbol(':')) {
+                    self.position += 1;
+                    self.optional_value();
+                }
+                continue;
+            }
+            properties.push(self.property(name)?);
+        }
+
+        Ok((
+            classname,
+            EntityDefinition {
+                kind,
+                description,
+                bases,
+                properties,
+            },
+        ))
+    }
+
+    /// A quoted or bare value, or nothing when the field is left empty.
+    fn optional_value(&mut self) -> Option<String> {
+        match self.peek()? {
+            Token::Quoted(text) | Token::Word(text) => {
+                let value = text.clone();
+                self.position += 1;
+                Some(value)
+            }
+            Token::Symbol(_) => None,
+        }
+    }
+
+    fn property(&mut self, name: String) -> Result<PropertyDefinition, FgdError> {
+        self.expect_symbol('(', "`(`")?;
+        let kind = self.word("a property type")?;
+        self.expect_symbol(')', "`)`")?;
+        while matches!(
+            self.peek(),
+            Some(Token::Word(marker)) if marker == "readonly" || marker == "report"
+        ) {
+            self.position += 1;
+        }
+
+        // `: "Display name" : default : "description"`, each optional
+        let mut fields = Vec::new();
+        while fields.len() < 3 && self.peek() == Some(&Token::Symbol(':')) {
+            self.position += 1;
+            fields.push(self.optional_value());
+        }
+        let mut fields = fields.into_iter();
+        let display_name = fields.next().flatten();
+        let default = fields.next().flatten();
+        let description = fields.next().flatten();
+
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "string" => PropertyKind::String,
+            "integer" => PropertyKind::Integer,
+            "float" => PropertyKind::Float,
+            "color255" | "color1" => PropertyKind::Color,
+            "choices" => PropertyKind::Choices(
+                self.options()?
+                    .into_iter()
+                    .map(|(value, label, _)| (value, label))
+                    .collect(),
+            ),
+            "flags" => PropertyKind::Flags(
+                self.options()?
+                    .into_iter()
+                    .filter_map(|(bit, label, set)| {
+                        Some((bit.parse().ok()?, label, set.is_some_and(|set| set != "0")))
+                    })
+                    .collect(),
+            ),
+            _ => PropertyKind::Other(kind),
+        };
+
+        Ok(PropertyDefinition {
+            name,
+            kind,
+            display_name,
+            default,
+            description,
+        })
+    }
+
+    /// The `= [ value : "label" : default ... ]` options of choices and flags.
+    #[allow(clippy::type_complexity)]
+    fn options(&mut self) -> Result<Vec<(String, String, Option<String>)>, FgdError> {
+        self.expect_symbol('=', "`=`")?;
+        self.expect_symbol('[', "`[`")?;
+        let mut options = Vec::new();
+        loop {
+            let token = self.expect_next("`]`")?;
+            let value = match token.token {
+                Token::Symbol(']') => break,
+                Token::Word(value) | Token::Quoted(value) => value,
+                _ => return Err(token.unexpected("an option")),
+            };
+            self.expect_symbol(':', "`:`")?;
+            let label = self.optional_value().unwrap_or_default();
+            let mut default = None;
+            if self.peek() == Some(&Token::Symbol(':')) {
+                self.position += 1;
+                default = self.optional_value();
+            }
+            options.push((value, label, default));
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FGD: &str = r#"
+        @include "base.fgd"
+        @mapsize(-16384, 16384)
+        @MaterialExclusion
+        [
+            "tools"
+            "dev"
+        ]
+        @AutoVisGroup = "Triggers"
+        [
+            "Once" [ "trigger_once" ]
+            "Multiple" [ "trigger_multiple" ]
+        ]
+
+        @BaseClass = Targetname [ targetname(target_source) : "Name" ]
+
+        @SolidClass base(Targetname) = func_door : "A door"
+        [
+            speed(integer) : "Speed" : 100 : "Units per second"
+            wait(float) : "Wait" : "3"
+            spawnflags(flags) =
+            [
+                1 : "Starts open" : 0
+                4 : "Don't link" : 1
+            ]
+            sounds(choices) : "Sounds" : 1 =
+            [
+                1 : "Stone"
+                2 : "Metal"
+            ]
+            input Open(void) : "Opens the door"
+            output OnOpen(void) : "Fired when opened"
+        ]
+    "#;
+
+    #[test]
+    fn parses_classes_and_properties() {
+        let definitions = EntityDefinitions::parse(FGD).unwrap();
+        let door = &definitions.classes["func_door"];
+        assert_eq!(door.kind, ClassKind::Solid);
+        assert_eq!(door.description.as_deref(), Some("A door"));
+        assert_eq!(door.bases, vec!["Targetname".to_string()]);
+
+        let names = definitions
+            .properties("func_door")
+            .iter()
+            .map(|property| property.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["targetname", "speed", "wait", "spawnflags", "sounds"]
+        );
+
+        let speed = &door.properties[0];
+        assert_eq!(speed.kind, PropertyKind::Integer);
+        assert_eq!(speed.default.as_deref(), Some("100"));
+        assert_eq!(speed.description.as_deref(), Some("Units per second"));
+        assert_eq!(
+            door.properties[3].kind,
+            PropertyKind::Choices(vec![
+                ("1".to_string(), "Stone".to_string()),
+                ("2".to_string(), "Metal".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn collects_defaults_and_flags() {
+        let definitions = EntityDefinitions::parse(FGD).unwrap();
+        assert_eq!(
+            definitions.defaults("func_door"),
+            vec![
+                ("speed".to_string(), "100".to_string()),
+                ("wait".to_string(), "3".to_string()),
+                ("spawnflags".to_string(), "4".to_string()),
+                ("sounds".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_hammer_directives() {
+        let definitions = EntityDefinitions::parse(
+            r#"
+            @MaterialExclusion [ "tools" ]
+            @AutoVisGroup = "Brush Entities" [ "Triggers" [ "trigger_once" ] ]
+            @PointClass = info_null []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            definitions.classes.keys().collect::<Vec<_>>(),
+            ["info_null"]
+        );
+    }
+
+    #[test]
+    fn joins_long_strings() {
+        let definitions =
+            EntityDefinitions::parse(r#"@PointClass = info_null : "first " + "second" []"#)
+                .unwrap();
+        assert_eq!(
+            definitions.classes["info_null"].description.as_deref(),
+            Some("first second")
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        assert_eq!(
+            EntityDefinitions::parse("\n\n@PointClass = info_null [ speed ]"),
+            Err(FgdError::Unexpected {
+                line: 3,
+                expected: "`(`",
+                found: "]".to_string(),
+            })
+        );
+    }
+}
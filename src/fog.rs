@@ -0,0 +1,147 @@
+//! `func_fog_volume` brush entities.
+//!
+//! Cameras with a [`FogVolumeCamera`] get a [`FogVolumeEntered`] event when
+//! they move into a fog volume and a [`FogVolumeExited`] one when they leave
+//! it. Unless `drive_fog_settings` is off, the camera's [`FogSettings`] are
+//! also swapped for the volume's while inside, and restored afterwards.
+//!
+//! A volume's fog has a `fog_color`, and is linear between `fog_start` and
+//! `fog_end` (in map units) when `fog_end` is set, or exponential with
+//! `fog_density` (per Bevy unit) otherwise. Where volumes overlap, the one
+//! with the highest `priority` wins.
+
+use bevy::pbr::{FogFalloff, FogSettings};
+use bevy::prelude::*;
+
+use crate::components::{BrushVolume, MapEntityProperties, MapUnits};
+use crate::conversions::to_bevy_distance;
+
+#[derive(Component, Debug, Clone)]
+pub struct FogVolume {
+    pub color: Color,
+    pub falloff: FogFalloff,
+    pub priority: i32,
+}
+
+impl FogVolume {
+    pub fn fog_settings(&self) -> FogSettings {
+        FogSettings {
+            color: self.color,
+            falloff: self.falloff.clone(),
+            ..default()
+        }
+    }
+}
+
+/// Tracks the fog volume a camera is in.
+#[derive(Component, Debug, Clone)]
+pub struct FogVolumeCamera {
+    /// swap the camera's [`FogSettings`] for the volume's while inside
+    pub drive_fog_settings: bool,
+    pub volume: Option<Entity>,
+    /// the camera's own fog, restored when it leaves the volume
+    outside_fog: Option<FogSettings>,
+}
+
+impl Default for FogVolumeCamera {
+    fn default() -> Self {
+        Self {
+            drive_fog_settings: true,
+            volume: None,
+            outside_fog: None,
+        }
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FogVolumeEntered {
+    pub camera: Entity,
+    pub volume: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FogVolumeExited {
+    pub camera: Entity,
+    pub volume: Entity,
+}
+
+/// Inserts the [`FogVolume`] component for a `func_fog_volume` entity.
+pub fn apply_fog_volume_properties(
+    map_units: &MapUnits,
+    scale: f32,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let distance = |key: &str, default: f32| {
+        to_bevy_distance(props.get_property_as_f32(key, default), map_units) * scale
+    };
+    let falloff = if props.properties.contains_key("fog_end") {
+        FogFalloff::Linear {
+            start: distance("fog_start", 0.0),
+            end: distance("fog_end", 0.0),
+        }
+    } else {
+        FogFalloff::Exponential {
+            density: props.get_property_as_f32("fog_density", 0.1),
+        }
+    };
+    commands.entity(entity).insert(FogVolume {
+        color: props.get_property_as_color("fog_color", Color::GRAY),
+        falloff,
+        priority: props.get_property_as_i32("priority", 0),
+    });
+}
+
+pub fn fog_volume_system(
+    mut commands: Commands,
+    mut entered_events: EventWriter<FogVolumeEntered>,
+    mut exited_events: EventWriter<FogVolumeExited>,
+    mut q_cameras: Query<(
+        Entity,
+        &mut FogVolumeCamera,
+        &GlobalTransform,
+        Option<&FogSettings>,
+    )>,
+    q_volumes: Query<(Entity, &FogVolume, &BrushVolume, &GlobalTransform)>,
+) {
+    for (camera, mut tracker, camera_transform, fog) in q_cameras.iter_mut() {
+        let position = camera_transform.translation();
+        let inside = q_volumes
+            .iter()
+            .filter(|(_, _, volume, transform)| volume.contains_world(transform, position))
+            .max_by_key(|(entity, fog_volume, ..)| (fog_volume.priority, *entity))
+            .map(|(entity, fog_volume, ..)| (entity, fog_volume));
+        let current = inside.map(|(entity, _)| entity);
+        if current == tracker.volume {
+            continue;
+        }
+
+        if let Some(volume) = tracker.volume {
+            exited_events.send(FogVolumeExited { camera, volume });
+        }
+        if let Some(volume) = current {
+            entered_events.send(FogVolumeEntered { camera, volume });
+        }
+
+        if tracker.drive_fog_settings {
+            match inside {
+                Some((_, fog_volume)) => {
+                    if tracker.volume.is_none() {
+                        tracker.outside_fog = fog.cloned();
+                    }
+                    commands.entity(camera).insert(fog_volume.fog_settings());
+                }
+                None => match tracker.outside_fog.take() {
+                    Some(outside_fog) => {
+                        commands.entity(camera).insert(outside_fog);
+                    }
+                    None => {
+                        commands.entity(camera).remove::<FogSettings>();
+                    }
+                },
+            }
+        }
+        tracker.volume = current;
+    }
+}
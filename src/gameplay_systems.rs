@@ -1,48 +1,428 @@
 use crate::components::*;
-use bevy::{prelude::*, utils::HashSet};
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+use crate::interaction::RequiresUse;
+use crate::intern::InternedStr;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
 
 #[cfg(feature = "rapier")]
 use bevy_rapier3d::prelude::*;
 
+pub fn toggle_brush_entities_system(
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut q_toggleables: Query<(&TriggerTarget, &mut Toggleable)>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (trigger_target, mut toggleable) in q_toggleables.iter_mut() {
+            if trigger_target.target_name == trigger_event.target {
+                toggleable.enabled = !toggleable.enabled;
+            }
+        }
+    }
+}
+
+/// Swaps the materials of triggered [`TextureFrames`] brush entities.
+#[cfg(feature = "render")]
+pub fn toggle_texture_frames_system(
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut q_frames: Query<(Entity, &TriggerTarget, &mut TextureFrames)>,
+    q_children: Query<&Children>,
+    mut q_materials: Query<&mut Handle<StandardMaterial>>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (entity, trigger_target, mut frames) in q_frames.iter_mut() {
+            if trigger_target.target_name != trigger_event.target {
+                continue;
+            }
+            frames.alternate = !frames.alternate;
+            for descendant in q_children.iter_descendants(entity) {
+                let Ok(mut material) = q_materials.get_mut(descendant) else {
+                    continue;
+                };
+                for (default, alternate) in frames.swaps.iter() {
+                    let (from, to) = match frames.alternate {
+                        true => (default, alternate),
+                        false => (alternate, default),
+                    };
+                    if *material == *from {
+                        *material = to.clone();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Shows/hides toggleable brush entities and stashes away their colliders while disabled
+#[cfg_attr(
+    not(all(feature = "render", any(feature = "xpbd", feature = "rapier"))),
+    allow(unused_variables, unused_mut)
+)]
+pub fn apply_brush_toggle_system(
+    mut commands: Commands,
+    q_toggleables: Query<(Entity, &Toggleable, &Children), Changed<Toggleable>>,
+    #[cfg(feature = "render")] mut q_visibilities: Query<&mut Visibility>,
+    #[cfg(any(feature = "xpbd", feature = "rapier"))] q_colliders: Query<(
+        Option<&PhysicsCollider>,
+        Option<&DisabledCollider<PhysicsCollider>>,
+    )>,
+) {
+    for (entity, toggleable, children) in q_toggleables.iter() {
+        #[cfg(feature = "render")]
+        if let Ok(mut visibility) = q_visibilities.get_mut(entity) {
+            *visibility = if toggleable.enabled {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+
+        #[cfg(any(feature = "xpbd", feature = "rapier"))]
+        for child in children.iter() {
+            let Ok((collider, disabled_collider)) = q_colliders.get(*child) else {
+                continue;
+            };
+            if toggleable.enabled {
+                if let Some(disabled_collider) = disabled_collider {
+                    commands
+                        .entity(*child)
+                        .insert(disabled_collider.0.clone())
+                        .remove::<DisabledCollider<PhysicsCollider>>();
+                }
+            } else if let Some(collider) = collider {
+                commands
+                    .entity(*child)
+                    .insert(DisabledCollider(collider.clone()))
+                    .remove::<PhysicsCollider>();
+            }
+        }
+    }
+}
+
+pub fn secret_found_system(
+    mut secrets_found: ResMut<SecretsFound>,
+    q_secrets: Query<(Entity, &Secret), Added<TriggeredOnce>>,
+    mut secret_events: EventWriter<SecretFoundEvent>,
+) {
+    for (secret_entity, secret) in q_secrets.iter() {
+        *secrets_found.per_map.entry(secret.map).or_default() += 1;
+        secret_events.send(SecretFoundEvent {
+            map: secret.map,
+            secret: secret_entity,
+        });
+    }
+}
+
+/// Fires trigger volumes according to the [`TriggerAuthority`].
+#[derive(SystemParam)]
+pub struct TriggerFiring<'w, 's> {
+    authority: Res<'w, TriggerAuthority>,
+    commands: Commands<'w, 's>,
+    trigger_events: EventWriter<'w, TriggeredEvent>,
+    would_fire_events: EventWriter<'w, WouldFireTrigger>,
+}
+
+impl<'w, 's> TriggerFiring<'w, 's> {
+    /// Fires `trigger`, or only sends a [`WouldFireTrigger`] when triggers
+    /// need to be confirmed. `once` triggers are marked [`TriggeredOnce`].
+    pub fn fire(
+        &mut self,
+        trigger: Entity,
+        target: &InternedStr,
+        triggered_by: Entity,
+        once: bool,
+    ) {
+        match *self.authority {
+            TriggerAuthority::Local => self.fire_confirmed(trigger, target, triggered_by, once),
+            TriggerAuthority::Confirmed => {
+                self.would_fire_events.send(WouldFireTrigger {
+                    trigger,
+                    target: target.clone(),
+                    triggered_by,
+                });
+            }
+        }
+    }
+
+    fn fire_confirmed(
+        &mut self,
+        trigger: Entity,
+        target: &InternedStr,
+        triggered_by: Entity,
+        once: bool,
+    ) {
+        self.trigger_events.send(TriggeredEvent {
+            target: target.clone(),
+            triggered_by,
+        });
+        if once {
+            self.commands.entity(trigger).insert(TriggeredOnce);
+        }
+    }
+}
+
+/// Fires the triggers confirmed through [`ConfirmTriggerEvent`]s.
+pub fn confirm_triggers_system(
+    mut firing: TriggerFiring,
+    mut confirm_events: EventReader<ConfirmTriggerEvent>,
+    q_triggers: Query<(
+        Option<&TriggerOnce>,
+        Option<&TriggerMultiple>,
+        Option<&TriggerPressure>,
+        Option<&TriggerLook>,
+        Has<TriggeredOnce>,
+    )>,
+) {
+    for ev in confirm_events.read() {
+        match q_triggers.get(ev.trigger) {
+            Ok((Some(trigger), _, _, _, false)) => {
+                firing.fire_confirmed(ev.trigger, &trigger.target, ev.triggered_by, true);
+            }
+            Ok((None, Some(trigger), _, _, _)) => {
+                firing.fire_confirmed(ev.trigger, &trigger.target, ev.triggered_by, false);
+            }
+            Ok((None, None, Some(plate), _, _)) => {
+                let target = match plate.pressed {
+                    true => &plate.target,
+                    false => &plate.target_release,
+                };
+                firing.fire_confirmed(ev.trigger, target, ev.triggered_by, false);
+            }
+            Ok((None, None, None, Some(look), false)) => {
+                firing.fire_confirmed(ev.trigger, &look.target, ev.triggered_by, look.once);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Presses and releases [`TriggerPressure`] plates from their
+/// [`TriggerOccupancy`].
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+pub fn trigger_pressure_system(
+    mut firing: TriggerFiring,
+    mut q_plates: Query<(Entity, &mut TriggerPressure, &TriggerOccupancy)>,
+    #[cfg(feature = "xpbd")] q_masses: Query<&bevy_xpbd_3d::prelude::Mass>,
+    #[cfg(feature = "rapier")]
+    #[cfg(not(feature = "xpbd"))]
+    q_masses: Query<&ReadMassProperties>,
+) {
+    for (entity, mut plate, occupancy) in q_plates.iter_mut() {
+        #[cfg(feature = "xpbd")]
+        let mass = |occupant: &Entity| q_masses.get(*occupant).map_or(0.0, |mass| mass.0);
+        #[cfg(feature = "rapier")]
+        #[cfg(not(feature = "xpbd"))]
+        let mass = |occupant: &Entity| q_masses.get(*occupant).map_or(0.0, |mass| mass.get().mass);
+        let pressed = occupancy.count() >= plate.required_count.max(1)
+            && plate.required_mass.map_or(true, |required| {
+                occupancy.occupants.iter().map(mass).sum::<f32>() >= required
+            });
+        if let Some(activator) = occupancy.occupants.last() {
+            plate.activator = Some(*activator);
+        }
+        if pressed == plate.pressed {
+            continue;
+        }
+        plate.pressed = pressed;
+        let Some(activator) = plate.activator else {
+            continue;
+        };
+        let target = match pressed {
+            true => plate.target.clone(),
+            false => plate.target_release.clone(),
+        };
+        if !target.is_empty() {
+            firing.fire(entity, &target, activator, false);
+        }
+    }
+}
+
+/// Updates the occupants of a trigger to the instigators `inside` it,
+/// sending the enter and exit events.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn update_trigger_occupancy(
+    trigger: Entity,
+    occupancy: &mut TriggerOccupancy,
+    inside: &[Entity],
+    entered_events: &mut EventWriter<TriggerEntered>,
+    exited_events: &mut EventWriter<TriggerExited>,
+) {
+    let mut index = 0;
+    while index < occupancy.occupants.len() {
+        let activator = occupancy.occupants[index];
+        if inside.contains(&activator) {
+            index += 1;
+            continue;
+        }
+        occupancy.occupants.remove(index);
+        exited_events.send(TriggerExited {
+            trigger,
+            activator,
+            occupants: occupancy.count(),
+        });
+    }
+    for activator in inside.iter().copied() {
+        if occupancy.contains(activator) {
+            continue;
+        }
+        occupancy.occupants.push(activator);
+        entered_events.send(TriggerEntered {
+            trigger,
+            activator,
+            occupants: occupancy.count(),
+        });
+    }
+}
+
+#[cfg(feature = "rapier")]
+pub fn rapier_trigger_occupancy_system(
+    rapier_context: Res<RapierContext>,
+    mut entered_events: EventWriter<TriggerEntered>,
+    mut exited_events: EventWriter<TriggerExited>,
+    mut q_triggers: Query<(Entity, &mut TriggerOccupancy)>,
+    q_instigators: Query<Entity, With<TriggerInstigator>>,
+) {
+    for (trigger, mut occupancy) in q_triggers.iter_mut() {
+        let inside = q_instigators
+            .iter()
+            .filter(|instigator| {
+                rapier_context.intersection_pair(*instigator, trigger) == Some(true)
+            })
+            .collect::<Vec<_>>();
+        if inside.is_empty() && !occupancy.is_occupied() {
+            continue;
+        }
+        update_trigger_occupancy(
+            trigger,
+            &mut occupancy,
+            &inside,
+            &mut entered_events,
+            &mut exited_events,
+        );
+    }
+}
+
+/// Fires [`TriggerLook`]s looked at by their occupants. Brush entities other
+/// than triggers and volumes block the view.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+pub fn trigger_look_system(
+    time: Res<Time>,
+    mut firing: TriggerFiring,
+    face_picker: crate::picking::FacePicker,
+    mut q_looks: Query<(Entity, &mut TriggerLook, &TriggerOccupancy), Without<TriggeredOnce>>,
+    q_targets: Query<(Entity, &TriggerTarget)>,
+    q_props: Query<&MapEntityProperties>,
+    q_transforms: Query<&GlobalTransform>,
+    q_parents: Query<&Parent>,
+    q_children: Query<&Children>,
+    q_eyes: Query<(), With<LookEye>>,
+) {
+    let is_occluder = |entity: Entity| {
+        q_props.get(entity).map_or(true, |props| {
+            !props.classname.starts_with("trigger_")
+                && !crate::build::is_volume_classname(&props.classname)
+        })
+    };
+    // point entities have no transform of their own, only their properties
+    let position = |entity: Entity| {
+        q_transforms
+            .get(entity)
+            .map(|transform| transform.translation())
+            .ok()
+            .or_else(|| {
+                let props = q_props.get(entity).ok()?;
+                let parent = q_transforms.get(q_parents.get(entity).ok()?.get()).ok()?;
+                Some(parent.transform_point(props.transform.translation))
+            })
+    };
+
+    for (entity, mut look, occupancy) in q_looks.iter_mut() {
+        if !occupancy.is_occupied() && look.looking.is_empty() {
+            continue;
+        }
+        let targets = q_targets
+            .iter()
+            .filter(|(_, target)| target.target_name == look.look_target)
+            .filter_map(|(target, _)| position(target))
+            .collect::<Vec<_>>();
+
+        let mut looking = Vec::new();
+        for occupant in occupancy.occupants.iter().copied() {
+            let eye = q_children
+                .iter_descendants(occupant)
+                .find(|descendant| q_eyes.contains(*descendant))
+                .unwrap_or(occupant);
+            let Ok(eye_transform) = q_transforms.get(eye) else {
+                continue;
+            };
+            let origin = eye_transform.translation();
+            let forward = eye_transform.forward();
+            let sees_target = targets.iter().any(|target| {
+                let to_target = *target - origin;
+                let distance = to_target.length();
+                if distance <= f32::EPSILON {
+                    return true;
+                }
+                if forward.angle_between(to_target) > look.angle {
+                    return false;
+                }
+                let ray = bevy::math::Ray3d::new(origin, to_target);
+                face_picker
+                    .pick_face_where(ray, is_occluder)
+                    .map_or(true, |hit| hit.distance >= distance - 0.01)
+            });
+            if !sees_target {
+                continue;
+            }
+            let previous = look
+                .looking
+                .iter()
+                .find(|(looker, _)| *looker == occupant)
+                .map(|(_, elapsed)| *elapsed);
+            let elapsed = previous.unwrap_or(0.0) + time.delta_seconds();
+            looking.push((occupant, elapsed));
+            if elapsed >= look.duration && previous.map_or(true, |p| p < look.duration) {
+                firing.fire(entity, &look.target, occupant, look.once);
+            }
+        }
+        look.looking = looking;
+    }
+}
+
 #[cfg(feature = "rapier")]
 pub fn rapier_trigger_system(
     rapier_context: Res<RapierContext>,
-    mut commands: Commands,
-    trigger_once: Query<(Entity, &TriggerOnce), Without<TriggeredOnce>>,
-    trigger_multiple: Query<(Entity, &TriggerMultiple)>,
+    mut firing: TriggerFiring,
+    trigger_once: Query<(Entity, &TriggerOnce), (Without<TriggeredOnce>, Without<RequiresUse>)>,
+    trigger_multiple: Query<(Entity, &TriggerMultiple), Without<RequiresUse>>,
     trigger_instigators: Query<Entity, (With<TriggerInstigator>,)>,
-    mut trigger_events: EventWriter<TriggeredEvent>,
 ) {
     for instigator_entity in trigger_instigators.iter() {
         for (trigger_entity, trigger) in trigger_multiple.iter() {
             if rapier_context.intersection_pair(instigator_entity, trigger_entity) == Some(true) {
-                trigger_events.send(TriggeredEvent {
-                    target: trigger.target.clone(),
-                    triggered_by: instigator_entity,
-                });
+                firing.fire(trigger_entity, &trigger.target, instigator_entity, false);
             }
         }
 
         for (trigger_entity, trigger) in trigger_once.iter() {
             if rapier_context.intersection_pair(instigator_entity, trigger_entity) == Some(true) {
-                trigger_events.send(TriggeredEvent {
-                    target: trigger.target.clone(),
-                    triggered_by: instigator_entity,
-                });
-                commands.entity(trigger_entity).insert(TriggeredOnce);
+                firing.fire(trigger_entity, &trigger.target, instigator_entity, true);
             }
         }
     }
 }
 
+#[cfg(feature = "xpbd")]
+use bevy::utils::HashSet;
 #[cfg(feature = "xpbd")]
 use bevy_xpbd_3d::prelude::*;
 
 #[cfg(feature = "xpbd")]
 pub fn xpbd_trigger_system(
     spatial_query: SpatialQuery,
-    mut commands: Commands,
-    map_entity: Query<Entity, With<Map>>,
+    mut firing: TriggerFiring,
+    map_entities: Query<Entity, With<Map>>,
     trigger_once: Query<
         (
             Entity,
@@ -51,65 +431,93 @@ pub fn xpbd_trigger_system(
             &Transform,
             &bevy_xpbd_3d::prelude::Collider,
         ),
-        Without<TriggeredOnce>,
+        (Without<TriggeredOnce>, Without<RequiresUse>),
+    >,
+    trigger_multiple: Query<
+        (
+            Entity,
+            &TriggerMultiple,
+            &GlobalTransform,
+            &Transform,
+            &bevy_xpbd_3d::prelude::Collider,
+        ),
+        Without<RequiresUse>,
     >,
-    trigger_multiple: Query<(
-        Entity,
-        &TriggerMultiple,
-        &GlobalTransform,
-        &Transform,
-        &bevy_xpbd_3d::prelude::Collider,
-    )>,
     trigger_instigators: Query<Entity, With<TriggerInstigator>>,
-    mut trigger_events: EventWriter<TriggeredEvent>,
 ) {
-    let map_entity = map_entity.get_single();
-
-    if let Ok(map_entity) = map_entity {
-        for instigator_entity in trigger_instigators.iter() {
-            for (trigger_entity, trigger, gtransform, transform, collider) in
-                trigger_multiple.iter()
-            {
-                let excluded = HashSet::from([map_entity]);
-                let intersections = spatial_query.shape_intersections(
-                    collider,
-                    gtransform.translation(),
-                    transform.rotation,
-                    SpatialQueryFilter {
-                        excluded_entities: excluded,
-                        ..default()
-                    },
-                );
-
-                for entity in intersections.iter() {
-                    if *entity == instigator_entity {
-                        trigger_events.send(TriggeredEvent {
-                            target: trigger.target.clone(),
-                            triggered_by: instigator_entity,
-                        });
-                        commands.entity(trigger_entity).insert(TriggeredOnce);
-                    }
+    // every map, including the ones spawned by `misc_external_map`
+    let excluded = map_entities.iter().collect::<HashSet<_>>();
+
+    for instigator_entity in trigger_instigators.iter() {
+        for (trigger_entity, trigger, gtransform, transform, collider) in trigger_multiple.iter() {
+            let intersections = spatial_query.shape_intersections(
+                collider,
+                gtransform.translation(),
+                transform.rotation,
+                SpatialQueryFilter {
+                    excluded_entities: excluded.clone(),
+                    ..default()
+                },
+            );
+
+            for entity in intersections.iter() {
+                if *entity == instigator_entity {
+                    firing.fire(trigger_entity, &trigger.target, instigator_entity, false);
                 }
             }
+        }
 
-            for (trigger_entity, trigger, gtransform, transform, collider) in trigger_once.iter() {
-                let intersections = spatial_query.shape_intersections(
-                    collider,
-                    gtransform.translation(),
-                    transform.rotation,
-                    SpatialQueryFilter::default(),
-                );
-
-                for entity in intersections.iter() {
-                    if *entity == instigator_entity {
-                        trigger_events.send(TriggeredEvent {
-                            target: trigger.target.clone(),
-                            triggered_by: instigator_entity,
-                        });
-                        commands.entity(trigger_entity).insert(TriggeredOnce);
-                    }
+        for (trigger_entity, trigger, gtransform, transform, collider) in trigger_once.iter() {
+            let intersections = spatial_query.shape_intersections(
+                collider,
+                gtransform.translation(),
+                transform.rotation,
+                SpatialQueryFilter::default(),
+            );
+
+            for entity in intersections.iter() {
+                if *entity == instigator_entity {
+                    firing.fire(trigger_entity, &trigger.target, instigator_entity, true);
                 }
             }
         }
     }
 }
+
+#[cfg(feature = "xpbd")]
+pub fn xpbd_trigger_occupancy_system(
+    spatial_query: SpatialQuery,
+    mut entered_events: EventWriter<TriggerEntered>,
+    mut exited_events: EventWriter<TriggerExited>,
+    mut q_triggers: Query<(
+        Entity,
+        &mut TriggerOccupancy,
+        &GlobalTransform,
+        &Transform,
+        &bevy_xpbd_3d::prelude::Collider,
+    )>,
+    q_instigators: Query<(), With<TriggerInstigator>>,
+) {
+    for (trigger, mut occupancy, gtransform, transform, collider) in q_triggers.iter_mut() {
+        let inside = spatial_query
+            .shape_intersections(
+                collider,
+                gtransform.translation(),
+                transform.rotation,
+                SpatialQueryFilter::default(),
+            )
+            .into_iter()
+            .filter(|entity| q_instigators.contains(*entity))
+            .collect::<Vec<_>>();
+        if inside.is_empty() && !occupancy.is_occupied() {
+            continue;
+        }
+        update_trigger_occupancy(
+            trigger,
+            &mut occupancy,
+            &inside,
+            &mut entered_events,
+            &mut exited_events,
+        );
+    }
+}
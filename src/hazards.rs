@@ -0,0 +1,181 @@
+//! Damage-over-time volumes, `trigger_hurt` and `func_liquid`s with a
+//! `damage` property.
+//!
+//! Every `1 / tick_rate` seconds (1 tick per second by default) an entity
+//! with a [`TriggerInstigator`] inside a [`HazardVolume`] takes `damage` of
+//! the volume's `damage_type`, sent as a [`HazardDamageEvent`] for the game
+//! to apply. The first tick happens on entering.
+//!
+//! A `team` property limits the hazard to entities with that [`Team`], or
+//! spares them when prefixed with `!`. Entities with a [`HazardImmunity`]
+//! listing the volume's `immunity` tag or its damage type, e.g. a hazard
+//! suit, take only the damage their protection lets through.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::components::{BrushVolume, MapEntityProperties, TriggerInstigator};
+use crate::intern::InternedStr;
+
+#[derive(Component, Debug, Clone)]
+pub struct HazardVolume {
+    pub damage_type: InternedStr,
+    /// damage per tick
+    pub damage: f32,
+    /// ticks per second
+    pub tick_rate: f32,
+    pub team_filter: HazardTeamFilter,
+    /// tag of the [`HazardImmunity`]s protecting from this hazard
+    pub immunity: Option<InternedStr>,
+}
+
+impl HazardVolume {
+    /// Damage dealt to an entity per tick, `None` when it's spared.
+    pub fn damage_to(&self, team: Option<&Team>, immunity: Option<&HazardImmunity>) -> Option<f32> {
+        if !self.team_filter.affects(team) {
+            return None;
+        }
+        let protection = immunity
+            .filter(|immunity| {
+                immunity.protects_from.iter().any(|tag| {
+                    *tag == self.damage_type.as_str()
+                        || self.immunity.as_ref().is_some_and(|i| *tag == i.as_str())
+                })
+            })
+            .map_or(0.0, |immunity| immunity.protection.clamp(0.0, 1.0));
+        let damage = self.damage * (1.0 - protection);
+        (damage > 0.0).then_some(damage)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum HazardTeamFilter {
+    #[default]
+    Everyone,
+    /// only hurts entities of this team
+    Only(String),
+    /// hurts everyone but entities of this team
+    Except(String),
+}
+
+impl HazardTeamFilter {
+    /// Parses a `team` property, `!` spares the team instead.
+    pub fn from_property(value: &str) -> Self {
+        let value = value.trim();
+        match value.strip_prefix('!') {
+            _ if value.is_empty() => Self::Everyone,
+            Some(team) => Self::Except(team.trim().to_owned()),
+            None => Self::Only(value.to_owned()),
+        }
+    }
+
+    pub fn affects(&self, team: Option<&Team>) -> bool {
+        let team = team.map(|team| team.0.as_str());
+        match self {
+            Self::Everyone => true,
+            Self::Only(only) => team == Some(only.as_str()),
+            Self::Except(except) => team != Some(except.as_str()),
+        }
+    }
+}
+
+/// The team of an entity, for the team filter of hazards.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Team(pub String);
+
+/// Protection from hazards, inserted by the game, e.g. while a hazard suit
+/// or power-up is active.
+#[derive(Component, Debug, Clone)]
+pub struct HazardImmunity {
+    /// immunity tags and damage types this protects from
+    pub protects_from: Vec<String>,
+    /// fraction of the damage absorbed, 1 for full immunity
+    pub protection: f32,
+}
+
+impl HazardImmunity {
+    /// Full immunity to the given tags and damage types.
+    pub fn full(protects_from: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            protects_from: protects_from.into_iter().map(Into::into).collect(),
+            protection: 1.0,
+        }
+    }
+}
+
+/// Damage dealt by a [`HazardVolume`], applied by the game.
+#[derive(Event, Debug, Clone)]
+pub struct HazardDamageEvent {
+    pub hazard: Entity,
+    pub entity: Entity,
+    pub damage_type: InternedStr,
+    pub amount: f32,
+}
+
+/// Inserts the [`HazardVolume`] component for a hazard entity.
+pub fn apply_hazard_properties(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    commands.entity(entity).insert(HazardVolume {
+        damage_type: props
+            .properties
+            .get("damage_type")
+            .cloned()
+            .unwrap_or_else(|| "generic".into()),
+        damage: props.get_property_as_f32("damage", 5.0).max(0.0),
+        tick_rate: props.get_property_as_f32("tick_rate", 1.0).max(0.0),
+        team_filter: HazardTeamFilter::from_property(
+            props.properties.get("team").map_or("", |v| v.as_str()),
+        ),
+        immunity: props.properties.get("immunity").cloned(),
+    });
+}
+
+pub fn hazard_system(
+    time: Res<Time>,
+    mut hazard_events: EventWriter<HazardDamageEvent>,
+    // time until the next tick of each (hazard, entity) pair inside a hazard
+    mut next_ticks: Local<HashMap<(Entity, Entity), f32>>,
+    q_hazards: Query<(Entity, &HazardVolume, &BrushVolume, &GlobalTransform)>,
+    q_instigators: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            Option<&Team>,
+            Option<&HazardImmunity>,
+        ),
+        With<TriggerInstigator>,
+    >,
+) {
+    let mut inside = HashMap::new();
+    for (hazard, volume, brushes, hazard_transform) in q_hazards.iter() {
+        for (entity, transform, team, immunity) in q_instigators.iter() {
+            if !brushes.contains_world(hazard_transform, transform.translation()) {
+                continue;
+            }
+            let next_tick =
+                next_ticks.get(&(hazard, entity)).copied().unwrap_or(0.0) - time.delta_seconds();
+            if next_tick > 0.0 {
+                inside.insert((hazard, entity), next_tick);
+                continue;
+            }
+            let interval = if volume.tick_rate > 0.0 {
+                1.0 / volume.tick_rate
+            } else {
+                f32::INFINITY
+            };
+            inside.insert((hazard, entity), next_tick + interval);
+            if let Some(amount) = volume.damage_to(team, immunity) {
+                hazard_events.send(HazardDamageEvent {
+                    hazard,
+                    entity,
+                    damage_type: volume.damage_type.clone(),
+                    amount,
+                });
+            }
+        }
+    }
+    *next_ticks = inside;
+}
@@ -0,0 +1,178 @@
+//! In-game map entity inspector, built on bevy-inspector-egui's egui integration.
+//!
+//! Lists map entities grouped by classname with their raw properties, draws
+//! target → targetname links, and lets you fire targets or teleport the
+//! camera to an entity. The recent [`MapEventLog`] can be shown in a second
+//! window.
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts, EguiPlugin};
+use std::collections::BTreeMap;
+
+use crate::components::{MapEntityProperties, TriggerTarget, TriggeredEvent};
+use crate::event_log::MapEventLog;
+
+#[derive(Default)]
+pub struct MapInspectorPlugin;
+
+impl Plugin for MapInspectorPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.init_resource::<MapInspectorSettings>().add_systems(
+            Update,
+            (
+                map_inspector_ui_system,
+                event_log_ui_system,
+                draw_target_links_system,
+            ),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct MapInspectorSettings {
+    pub open: bool,
+    pub draw_target_links: bool,
+    pub target_link_color: Color,
+    /// show the [`MapEventLog`], newest entries first
+    pub show_event_log: bool,
+}
+
+impl Default for MapInspectorSettings {
+    fn default() -> Self {
+        Self {
+            open: true,
+            draw_target_links: true,
+            target_link_color: Color::YELLOW,
+            show_event_log: false,
+        }
+    }
+}
+
+pub fn map_inspector_ui_system(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<MapInspectorSettings>,
+    q_map_entities: Query<(Entity, &MapEntityProperties, Option<&GlobalTransform>)>,
+    mut q_cameras: Query<&mut Transform, With<Camera3d>>,
+    mut trigger_events: EventWriter<TriggeredEvent>,
+) {
+    if !settings.open {
+        return;
+    }
+
+    let mut by_classname: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+    for item in q_map_entities.iter() {
+        by_classname
+            .entry(item.1.classname.as_str())
+            .or_default()
+            .push(item);
+    }
+
+    let mut open = settings.open;
+    egui::Window::new("Map entities")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (classname, entities) in by_classname.iter() {
+                    egui::CollapsingHeader::new(format!("{} ({})", classname, entities.len()))
+                        .id_source(classname)
+                        .show(ui, |ui| {
+                            for (entity, props, transform) in entities.iter() {
+                                egui::CollapsingHeader::new(format!("{:?}", entity))
+                                    .id_source(entity)
+                                    .show(ui, |ui| {
+                                        egui::Grid::new(entity).show(ui, |ui| {
+                                            for (key, value) in props.properties.iter() {
+                                                ui.label(key.as_str());
+                                                ui.label(value.as_str());
+                                                ui.end_row();
+                                            }
+                                        });
+
+                                        ui.horizontal(|ui| {
+                                            if let Some(target) = props.properties.get("target") {
+                                                if ui.button(format!("Fire → {}", target)).clicked()
+                                                {
+                                                    trigger_events.send(TriggeredEvent {
+                                                        target: target.clone(),
+                                                        triggered_by: *entity,
+                                                    });
+                                                }
+                                            }
+                                            if let Some(transform) = transform {
+                                                if ui.button("Teleport camera").clicked() {
+                                                    for mut camera in q_cameras.iter_mut() {
+                                                        camera.translation =
+                                                            transform.translation();
+                                                    }
+                                                }
+                                            }
+                                        });
+                                    });
+                            }
+                        });
+                }
+            });
+        });
+    settings.open = open;
+}
+
+pub fn event_log_ui_system(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<MapInspectorSettings>,
+    log: Option<ResMut<MapEventLog>>,
+) {
+    let Some(mut log) = log else {
+        return;
+    };
+    if !settings.show_event_log {
+        return;
+    }
+
+    let mut open = settings.show_event_log;
+    egui::Window::new("Map event log")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut log.paused, "Paused");
+                if ui.button("Clear").clicked() {
+                    log.clear();
+                }
+                ui.label(format!("{} / {}", log.len(), log.capacity));
+            });
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for entry in log.entries().rev() {
+                    ui.monospace(entry.to_string());
+                }
+            });
+        });
+    settings.show_event_log = open;
+}
+
+pub fn draw_target_links_system(
+    mut gizmos: Gizmos,
+    settings: Res<MapInspectorSettings>,
+    q_sources: Query<(&MapEntityProperties, &GlobalTransform)>,
+    q_targets: Query<(&TriggerTarget, &GlobalTransform)>,
+) {
+    if !settings.draw_target_links {
+        return;
+    }
+
+    for (props, source_transform) in q_sources.iter() {
+        let Some(target) = props.properties.get("target") else {
+            continue;
+        };
+        for (trigger_target, target_transform) in q_targets.iter() {
+            if trigger_target.target_name == *target {
+                gizmos.arrow(
+                    source_transform.translation(),
+                    target_transform.translation(),
+                    settings.target_link_color,
+                );
+            }
+        }
+    }
+}
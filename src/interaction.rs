@@ -0,0 +1,166 @@
+//! "Press to use" activation of doors and buttons.
+//!
+//! Movers and `trigger_once`/`trigger_multiple` volumes with spawnflag 128
+//! ([`REQUIRES_USE`]) get an [`Interactable`] and aren't activated by being
+//! touched. Instead, the game sends a [`UseEvent`] when a player presses its
+//! use key or button, and the nearest interactable within its `use_radius`
+//! (64 map units by default) of the user is used: a mover starts moving as if
+//! its `targetname` was fired, and a trigger fires its `target`. A
+//! [`UsedEvent`] is sent for each use, e.g. to play a sound.
+//!
+//! Doors that need a `key` are left to the game, as when they're triggered.
+//! The `use_prompt` property is exposed for the game's "press E to ..." UI.
+
+use bevy::prelude::*;
+
+use crate::components::{
+    Door, MapEntityProperties, MapUnits, MomentaryMover, Mover, TriggerMultiple, TriggerOnce,
+    TriggeredOnce,
+};
+use crate::conversions::to_bevy_distance;
+use crate::gameplay_systems::TriggerFiring;
+use crate::intern::InternedStr;
+
+/// Spawnflag of doors and buttons activated by [`UseEvent`]s rather than by
+/// touch.
+pub const REQUIRES_USE: i32 = 128;
+
+/// Marks trigger colliders that are only fired through a [`UseEvent`].
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct RequiresUse;
+
+/// An entity activated by [`UseEvent`]s.
+#[derive(Component, Debug, Clone)]
+pub struct Interactable {
+    /// largest distance between the user and the entity's origin
+    pub radius: f32,
+    /// text for the game to show while in range
+    pub prompt: Option<InternedStr>,
+}
+
+impl Interactable {
+    pub fn in_reach(&self, transform: &GlobalTransform, user: Vec3) -> bool {
+        transform.translation().distance_squared(user) <= self.radius * self.radius
+    }
+}
+
+/// Sent by the game when `user` tries to use what's in front of it.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct UseEvent {
+    pub user: Entity,
+}
+
+/// Sent when a [`UseEvent`] activates an [`Interactable`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct UsedEvent {
+    pub user: Entity,
+    pub used: Entity,
+}
+
+pub fn requires_use(props: &MapEntityProperties) -> bool {
+    props.get_property_as_i32("spawnflags", 0) & REQUIRES_USE != 0
+}
+
+/// Inserts the [`Interactable`] component of movers and triggers with the
+/// [`REQUIRES_USE`] spawnflag.
+pub fn apply_interaction_properties(
+    map_units: &MapUnits,
+    scale: f32,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let usable = matches!(
+        props.classname.as_str(),
+        "mover" | "trigger_once" | "trigger_multiple"
+    );
+    if !usable || !requires_use(props) {
+        return;
+    }
+    commands.entity(entity).insert(Interactable {
+        radius: to_bevy_distance(props.get_property_as_f32("use_radius", 64.0), map_units) * scale,
+        prompt: props.properties.get("use_prompt").cloned(),
+    });
+}
+
+/// Sends a [`UsedEvent`] for the nearest [`Interactable`] in reach of each
+/// user.
+pub fn use_system(
+    mut use_events: EventReader<UseEvent>,
+    mut used_events: EventWriter<UsedEvent>,
+    q_users: Query<&GlobalTransform>,
+    q_interactables: Query<(Entity, &Interactable, &GlobalTransform)>,
+) {
+    for ev in use_events.read() {
+        let Ok(user_transform) = q_users.get(ev.user) else {
+            continue;
+        };
+        let user = user_transform.translation();
+        let nearest = q_interactables
+            .iter()
+            .filter(|(entity, interactable, transform)| {
+                *entity != ev.user && interactable.in_reach(transform, user)
+            })
+            .min_by(|(_, _, a), (_, _, b)| {
+                let a = a.translation().distance_squared(user);
+                let b = b.translation().distance_squared(user);
+                a.total_cmp(&b)
+            });
+        if let Some((used, ..)) = nearest {
+            used_events.send(UsedEvent {
+                user: ev.user,
+                used,
+            });
+        }
+    }
+}
+
+/// Starts used movers.
+pub fn use_movers_system(
+    mut used_events: EventReader<UsedEvent>,
+    mut q_movers: Query<(&mut Mover, Option<&Door>), Without<MomentaryMover>>,
+) {
+    for ev in used_events.read() {
+        let Ok((mut mover, door)) = q_movers.get_mut(ev.used) else {
+            continue;
+        };
+        if door.is_some_and(|door| door.key.is_some()) {
+            continue;
+        }
+        crate::movers::start_mover(&mut mover);
+    }
+}
+
+/// Fires used triggers, through their colliders.
+pub fn use_triggers_system(
+    mut firing: TriggerFiring,
+    mut used_events: EventReader<UsedEvent>,
+    q_children: Query<&Children>,
+    q_triggers: Query<(
+        Option<&TriggerOnce>,
+        Option<&TriggerMultiple>,
+        Has<TriggeredOnce>,
+    )>,
+) {
+    for ev in used_events.read() {
+        let Ok(children) = q_children.get(ev.used) else {
+            continue;
+        };
+        // each brush of the trigger has its own collider, fire only one
+        for &collider in children.iter() {
+            let Ok((once, multiple, triggered_once)) = q_triggers.get(collider) else {
+                continue;
+            };
+            if let Some(trigger) = once {
+                if !triggered_once {
+                    firing.fire(collider, &trigger.target, ev.user, true);
+                }
+                break;
+            }
+            if let Some(trigger) = multiple {
+                firing.fire(collider, &trigger.target, ev.user, false);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Build warnings written to a log file, the way Quake map compilers report
+//! them.
+//!
+//! When [`MapBuildSettings::issue_log_dir`] is set, each build of a map
+//! writes `<dir>/<map file stem>.log` with one `WARNING:` line per degenerate
+//! face dropped by the geometry cleanup and per [`MapIssue`] found by
+//! [`crate::MapAsset::validate_with_definitions`] (missing textures, unmatched targets,
+//! degenerate brushes, ...). Entity, brush and face numbers are their indices
+//! in the .map file, followed by the line of the face when it's known. The
+//! file is rewritten on each build, so it can be kept open in an editor or
+//! tailed next to TrenchBroom.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::components::{DegenerateGeometry, Map};
+use crate::core::DegenerateFace;
+use crate::registry::MapEntityRegistry;
+use crate::settings::MapBuildSettings;
+use crate::validate::MapIssue;
+use crate::{MapAsset, PostBuildMapEvent};
+
+/// Formats the warnings of a build of `map_name`, one per line.
+pub fn format_issue_log(
+    map_name: &str,
+    dropped_faces: &[DegenerateFace],
+    issues: &[MapIssue],
+) -> String {
+    let mut log = String::new();
+    let _ = writeln!(log, "---- qevy: building {} ----", map_name);
+    for face in dropped_faces {
+        let _ = writeln!(log, "WARNING: {}", face);
+    }
+    for issue in issues {
+        let _ = writeln!(log, "WARNING: {}", issue);
+    }
+    let count = dropped_faces.len() + issues.len();
+    let _ = writeln!(
+        log,
+        "{} warning{}",
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    log
+}
+
+/// Writes the issue log of each built map, see the [module docs](self).
+pub fn write_issue_log_system(
+    build_settings: Res<MapBuildSettings>,
+    asset_server: Res<AssetServer>,
+    map_assets: Res<Assets<MapAsset>>,
+    registry: Res<MapEntityRegistry>,
+    mut post_build_events: EventReader<PostBuildMapEvent>,
+    mut degenerate_geometry_events: EventReader<DegenerateGeometry>,
+    q_maps: Query<&Map>,
+) {
+    let Some(dir) = build_settings.issue_log_dir.as_deref() else {
+        post_build_events.clear();
+        degenerate_geometry_events.clear();
+        return;
+    };
+
+    let mut dropped_faces: HashMap<Entity, Vec<DegenerateFace>> = HashMap::new();
+    for ev in degenerate_geometry_events.read() {
+        dropped_faces
+            .entry(ev.map)
+            .or_default()
+            .push(ev.face.clone());
+    }
+
+    for ev in post_build_events.read() {
+        let Ok(map) = q_maps.get(ev.map) else {
+            continue;
+        };
+        let Some(map_asset) = map_assets.get(&map.asset) else {
+            continue;
+        };
+        let Some(asset_path) = asset_server.get_path(map.asset.id()) else {
+            continue;
+        };
+        let map_path = asset_path.path();
+        let Some(stem) = map_path.file_stem() else {
+            continue;
+        };
+
+        let log = format_issue_log(
+            &map_path.display().to_string(),
+            dropped_faces.get(&ev.map).map_or(&[], Vec::as_slice),
+            &map_asset.validate_with_definitions(&registry.definitions),
+        );
+        let log_path = Path::new(dir).join(format!("{}.log", stem.to_string_lossy()));
+        match std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&log_path, log)) {
+            Ok(()) => debug!(path = %log_path.display(), "wrote map issue log"),
+            Err(e) => warn!(path = %log_path.display(), "failed to write map issue log: {}", e),
+        }
+    }
+}
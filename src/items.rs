@@ -0,0 +1,228 @@
+//! Generic `item_*` pickups.
+//!
+//! Every point entity whose classname starts with `item_` becomes an [`Item`]
+//! with a sensor sphere of `radius` (map units, default 16). A `model` property
+//! spawns a scene as its visual, which bobs and spins unless `bob` / `rotate`
+//! are set to 0. Touching an item with a [`TriggerInstigator`] sends a
+//! [`PickupEvent`]; items with a `respawn_delay` (seconds) come back after it,
+//! the others are despawned.
+
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::components::{MapEntityProperties, MapSpatialBundle, MapUnits};
+use crate::conversions::to_bevy_distance;
+use crate::intern::InternedStr;
+
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+use crate::components::TriggerInstigator;
+
+#[derive(Component, Debug, Clone)]
+pub struct Item {
+    pub classname: InternedStr,
+    /// pickup radius, in Bevy units
+    pub radius: f32,
+    pub respawn_delay: Option<Duration>,
+}
+
+/// The visual of an item, bobbing and rotating around its parent.
+#[derive(Component, Debug, Clone)]
+pub struct ItemVisual {
+    /// bob amplitude, in Bevy units
+    pub bob_height: f32,
+    /// rotation speed, in radians per second
+    pub rotate_speed: f32,
+    /// seconds the visual has been animated for
+    pub elapsed: f32,
+}
+
+/// Present on items that were picked up and are waiting to respawn.
+#[derive(Component, Debug, Clone)]
+pub struct ItemRespawning(pub Timer);
+
+#[derive(Event, Debug, Clone)]
+pub struct PickupEvent {
+    pub item: Entity,
+    pub classname: InternedStr,
+    pub collector: Entity,
+}
+
+pub fn is_item_classname(classname: &str) -> bool {
+    classname.starts_with("item_")
+}
+
+/// Inserts the [`Item`] component, transform and sensor collider for an `item_*` entity.
+pub fn apply_item_properties(
+    map_units: &MapUnits,
+    scale: f32,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let radius = to_bevy_distance(props.get_property_as_f32("radius", 16.0), map_units) * scale;
+    let respawn_delay = props.get_property_as_f32("respawn_delay", 0.0);
+
+    let mut item_entity = commands.entity(entity);
+    item_entity.insert((
+        Item {
+            classname: props.classname.clone(),
+            radius,
+            respawn_delay: (respawn_delay > 0.0).then(|| Duration::from_secs_f32(respawn_delay)),
+        },
+        MapSpatialBundle::from_transform(props.transform),
+    ));
+
+    #[cfg(feature = "xpbd")]
+    item_entity.insert((
+        bevy_xpbd_3d::prelude::Collider::sphere(radius),
+        bevy_xpbd_3d::prelude::RigidBody::Static,
+        bevy_xpbd_3d::prelude::Sensor,
+    ));
+
+    #[cfg(feature = "rapier")]
+    #[cfg(not(feature = "xpbd"))]
+    item_entity.insert((
+        bevy_rapier3d::prelude::Collider::ball(radius),
+        bevy_rapier3d::prelude::RigidBody::Fixed,
+        bevy_rapier3d::prelude::Sensor,
+        bevy_rapier3d::prelude::ActiveCollisionTypes::default()
+            | bevy_rapier3d::prelude::ActiveCollisionTypes::KINEMATIC_FIXED,
+    ));
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_item_visuals_system(
+    map_units: Res<MapUnits>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    q_items: Query<(Entity, &MapEntityProperties), Added<Item>>,
+) {
+    for (entity, props) in q_items.iter() {
+        let Some(model) = props.properties.get("model") else {
+            continue;
+        };
+        let visual = ItemVisual {
+            bob_height: to_bevy_distance(props.get_property_as_f32("bob", 4.0), &map_units),
+            rotate_speed: props.get_property_as_f32("rotate", 90.0).to_radians(),
+            elapsed: 0.0,
+        };
+        commands.entity(entity).with_children(|children| {
+            children.spawn((
+                SceneBundle {
+                    scene: asset_server.load(format!("{}#Scene0", model)),
+                    ..default()
+                },
+                visual,
+                Name::new(format!("item model ({})", model)),
+            ));
+        });
+    }
+}
+
+pub fn animate_item_visuals_system(
+    time: Res<Time>,
+    mut q_visuals: Query<(&mut ItemVisual, &mut Transform)>,
+) {
+    for (mut visual, mut transform) in q_visuals.iter_mut() {
+        // accumulated rather than read from `Time::elapsed` so pausing
+        // doesn't make visuals jump when resumed
+        visual.elapsed += time.delta_seconds();
+        let t = visual.elapsed;
+        transform.translation.y = visual.bob_height * (t * 2.0).sin();
+        transform.rotation = Quat::from_rotation_y(t * visual.rotate_speed);
+    }
+}
+
+/// Hides or despawns picked up items, depending on their respawn delay.
+pub fn item_pickup_system(
+    mut commands: Commands,
+    mut pickup_events: EventReader<PickupEvent>,
+    q_items: Query<&Item, Without<ItemRespawning>>,
+) {
+    for ev in pickup_events.read() {
+        let Ok(item) = q_items.get(ev.item) else {
+            continue;
+        };
+        match item.respawn_delay {
+            Some(delay) => {
+                commands
+                    .entity(ev.item)
+                    .insert(ItemRespawning(Timer::new(delay, TimerMode::Once)));
+                #[cfg(feature = "render")]
+                commands.entity(ev.item).insert(Visibility::Hidden);
+            }
+            None => commands.entity(ev.item).despawn_recursive(),
+        }
+    }
+}
+
+pub fn item_respawn_system(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut q_respawning: Query<(Entity, &mut ItemRespawning)>,
+    #[cfg(feature = "render")] mut q_visibilities: Query<&mut Visibility>,
+) {
+    for (entity, mut respawning) in q_respawning.iter_mut() {
+        if respawning.0.tick(time.delta()).just_finished() {
+            #[cfg(feature = "render")]
+            if let Ok(mut visibility) = q_visibilities.get_mut(entity) {
+                *visibility = Visibility::Inherited;
+            }
+            commands.entity(entity).remove::<ItemRespawning>();
+        }
+    }
+}
+
+#[cfg(feature = "rapier")]
+pub fn rapier_item_touch_system(
+    rapier_context: Res<bevy_rapier3d::prelude::RapierContext>,
+    q_items: Query<(Entity, &Item), Without<ItemRespawning>>,
+    q_instigators: Query<Entity, With<TriggerInstigator>>,
+    mut pickup_events: EventWriter<PickupEvent>,
+) {
+    for collector in q_instigators.iter() {
+        for (item_entity, item) in q_items.iter() {
+            if rapier_context.intersection_pair(collector, item_entity) == Some(true) {
+                pickup_events.send(PickupEvent {
+                    item: item_entity,
+                    classname: item.classname.clone(),
+                    collector,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(feature = "xpbd")]
+pub fn xpbd_item_touch_system(
+    spatial_query: bevy_xpbd_3d::prelude::SpatialQuery,
+    q_items: Query<
+        (
+            Entity,
+            &Item,
+            &GlobalTransform,
+            &bevy_xpbd_3d::prelude::Collider,
+        ),
+        Without<ItemRespawning>,
+    >,
+    q_instigators: Query<Entity, With<TriggerInstigator>>,
+    mut pickup_events: EventWriter<PickupEvent>,
+) {
+    for (item_entity, item, transform, collider) in q_items.iter() {
+        let intersections = spatial_query.shape_intersections(
+            collider,
+            transform.translation(),
+            Quat::IDENTITY,
+            bevy_xpbd_3d::prelude::SpatialQueryFilter::default(),
+        );
+        for collector in intersections {
+            if q_instigators.contains(collector) {
+                pickup_events.send(PickupEvent {
+                    item: item_entity,
+                    classname: item.classname.clone(),
+                    collector,
+                });
+            }
+        }
+    }
+}
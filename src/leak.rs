@@ -0,0 +1,242 @@
+//! Leak detection and point file (.pts) support.
+//!
+//! A map "leaks" when a point entity can reach the void outside of the
+//! worldspawn geometry. Quake compilers write the path of a leak to a .pts
+//! point file, which can be loaded and drawn in-game with [`LeakLine`].
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, BoxedFuture, LoadContext};
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use std::collections::VecDeque;
+
+use crate::components::MapUnits;
+use crate::conversions::to_bevy_position;
+use crate::core::{parse_vec3, CompiledMap};
+use crate::MapAssetLoaderError;
+
+/// Points of a .pts file, in map space.
+#[derive(Debug, Asset, TypePath)]
+pub struct PointFile {
+    pub points: Vec<Vec3>,
+}
+
+impl PointFile {
+    pub fn parse(text: &str) -> Self {
+        Self {
+            points: text.lines().filter_map(parse_vec3).collect(),
+        }
+    }
+
+    pub fn to_bevy_points(&self, map_units: &MapUnits) -> Vec<Vec3> {
+        self.points
+            .iter()
+            .map(|point| to_bevy_position(point, map_units))
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct PointFileLoader;
+
+impl AssetLoader for PointFileLoader {
+    type Asset = PointFile;
+    type Settings = ();
+    type Error = MapAssetLoaderError;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).await?;
+            Ok(PointFile::parse(&text))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pts"]
+    }
+}
+
+/// A leak path drawn with gizmos, relative to the entity's transform.
+/// Insert a `Handle<PointFile>` next to it to fill the points from a .pts file.
+#[cfg(feature = "render")]
+#[derive(Component, Clone)]
+pub struct LeakLine {
+    pub points: Vec<Vec3>,
+    pub color: Color,
+}
+
+#[cfg(feature = "render")]
+impl Default for LeakLine {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            color: Color::RED,
+        }
+    }
+}
+
+/// The result of [`find_leak`].
+#[derive(Debug, Default, Clone)]
+pub struct LeakReport {
+    /// index of the first point entity found outside of the world hull
+    pub leaking_entity: Option<usize>,
+    /// path from the leaking entity to the outside of the map, in Bevy space
+    pub path: Vec<Vec3>,
+}
+
+impl LeakReport {
+    pub fn is_sealed(&self) -> bool {
+        self.leaking_entity.is_none()
+    }
+}
+
+/// Checks whether the worldspawn brushes form a sealed hull around every
+/// point entity, by flood filling a grid of `cell_size` (in Bevy units)
+/// from outside of the map bounds.
+pub fn find_leak(map: &CompiledMap, cell_size: f32) -> LeakReport {
+    let world_brushes = map
+        .entities
+        .iter()
+        .filter(|entity| entity.classname == "worldspawn")
+        .flat_map(|entity| entity.brushes.iter())
+        .collect::<Vec<_>>();
+
+    // a brush is the intersection of its face planes
+    let brush_planes = world_brushes
+        .iter()
+        .map(|brush| {
+            brush
+                .faces
+                .iter()
+                .filter(|face| !face.vertices.is_empty() && !face.normals.is_empty())
+                .map(|face| (face.vertices[0], face.normals[0]))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for vertex in world_brushes.iter().flat_map(|brush| brush.vertices.iter()) {
+        min = min.min(*vertex);
+        max = max.max(*vertex);
+    }
+    if min.cmpgt(max).any() || cell_size <= 0.0 {
+        return LeakReport::default();
+    }
+
+    // pad by one cell so the outside is connected all around the map
+    let min = min - Vec3::splat(cell_size);
+    let max = max + Vec3::splat(cell_size);
+    let size = ((max - min) / cell_size).ceil().as_uvec3() + UVec3::ONE;
+    let index = |cell: UVec3| (cell.x + size.x * (cell.y + size.y * cell.z)) as usize;
+    let center = |cell: UVec3| min + (cell.as_vec3() + Vec3::splat(0.5)) * cell_size;
+
+    let is_solid = |point: Vec3| {
+        brush_planes.iter().any(|planes| {
+            !planes.is_empty()
+                && planes
+                    .iter()
+                    .all(|(origin, normal)| (point - *origin).dot(*normal) <= 0.0)
+        })
+    };
+
+    // flood fill from a corner, which is guaranteed to be outside
+    let cell_count = (size.x * size.y * size.z) as usize;
+    let mut parents: Vec<Option<UVec3>> = vec![None; cell_count];
+    let mut visited = vec![false; cell_count];
+    let mut queue = VecDeque::from([UVec3::ZERO]);
+    visited[0] = true;
+    while let Some(cell) = queue.pop_front() {
+        for offset in [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ] {
+            let neighbor = cell.as_ivec3() + offset;
+            if neighbor.cmplt(IVec3::ZERO).any() || neighbor.cmpge(size.as_ivec3()).any() {
+                continue;
+            }
+            let neighbor = neighbor.as_uvec3();
+            if visited[index(neighbor)] || is_solid(center(neighbor)) {
+                continue;
+            }
+            visited[index(neighbor)] = true;
+            parents[index(neighbor)] = Some(cell);
+            queue.push_back(neighbor);
+        }
+    }
+
+    for entity in map.entities.iter() {
+        if entity.is_brush_entity() || entity.classname == "worldspawn" {
+            continue;
+        }
+        let cell = ((entity.translation - min) / cell_size).floor();
+        if cell.cmplt(Vec3::ZERO).any() || cell.cmpge(size.as_vec3()).any() {
+            // outside of the world bounds entirely
+            return LeakReport {
+                leaking_entity: Some(entity.index),
+                path: vec![entity.translation],
+            };
+        }
+        let cell = cell.as_uvec3();
+        if visited[index(cell)] {
+            let mut path = vec![entity.translation];
+            let mut current = parents[index(cell)];
+            while let Some(parent) = current {
+                path.push(center(parent));
+                current = parents[index(parent)];
+            }
+            return LeakReport {
+                leaking_entity: Some(entity.index),
+                path,
+            };
+        }
+    }
+
+    LeakReport::default()
+}
+
+#[cfg(feature = "render")]
+pub fn point_file_leak_line_system(
+    map_units: Res<MapUnits>,
+    point_files: Res<Assets<PointFile>>,
+    mut ev_asset: EventReader<AssetEvent<PointFile>>,
+    mut q_leak_lines: Query<(&Handle<PointFile>, &mut LeakLine)>,
+) {
+    for ev in ev_asset.read() {
+        if let AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } = ev {
+            for (handle, mut leak_line) in q_leak_lines.iter_mut() {
+                if handle.id() != *id {
+                    continue;
+                }
+                if let Some(point_file) = point_files.get(*id) {
+                    leak_line.points = point_file.to_bevy_points(&map_units);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn draw_leak_lines_system(
+    mut gizmos: Gizmos,
+    q_leak_lines: Query<(&LeakLine, &GlobalTransform)>,
+) {
+    for (leak_line, transform) in q_leak_lines.iter() {
+        gizmos.linestrip(
+            leak_line
+                .points
+                .iter()
+                .map(|point| transform.transform_point(*point)),
+            leak_line.color,
+        );
+    }
+}
@@ -4,24 +4,138 @@ use bevy::ecs::system::SystemId;
 use bevy::prelude::*;
 use bevy::reflect::TypePath;
 use components::MapUnits;
-use std::collections::BTreeMap;
+use intern::{InternedStr, Interner};
+pub use plugins::{QevyGameplayState, QevyPlugins, QevySet};
+use std::collections::{BTreeMap, BTreeSet};
 use thiserror::Error;
 use tracing::info;
 
+#[cfg(feature = "render")]
+pub mod blend;
+pub mod brush_primitives;
 pub mod build;
+pub mod camera_paths;
+pub mod colliders;
 pub mod components;
+#[cfg(feature = "console")]
+pub mod console;
 pub mod conversions;
+pub mod core;
+pub mod cutscenes;
+pub mod damage;
+#[cfg(feature = "render")]
+pub mod detail;
+pub mod diagnostics;
+pub mod event_log;
+pub mod fgd;
+#[cfg(feature = "render")]
+pub mod fog;
 pub mod gameplay_systems;
+pub mod hazards;
+#[cfg(feature = "inspector")]
+pub mod inspector;
+pub mod interaction;
+pub mod intern;
+pub mod issue_log;
+pub mod items;
+pub mod leak;
+pub mod liquids;
+#[cfg(feature = "live_link")]
+pub mod live_link;
 pub mod load;
+pub mod materials;
+#[cfg(feature = "render")]
+pub mod minimap;
+pub mod movers;
+pub mod objectives;
+pub mod picking;
+pub mod plugins;
+pub mod process;
+#[cfg(feature = "render")]
+pub mod props;
+pub mod random;
+pub mod registry;
+#[cfg(feature = "render")]
+pub mod scatter;
+pub mod settings;
+pub mod snapshot;
+pub mod spawners;
+pub mod speakers;
+pub mod surfaces;
+#[cfg(feature = "test_utils")]
+pub mod test_utils;
+#[cfg(feature = "render")]
+pub mod thumbnail;
+pub mod timers;
+pub mod validate;
 
 #[derive(Debug, Asset, TypePath)]
 pub struct MapAsset {
     geomap: Option<shambler::GeoMap>,
-    texture_sizes: BTreeMap<String, (u32, u32)>,
-    material_handles: BTreeMap<String, Handle<StandardMaterial>>,
+    /// compiled by the asset processor, in place of `geomap`
+    processed: Option<core::ProcessedMap>,
+    brush_primitives: brush_primitives::BrushPrimitives,
+    texture_sizes: BTreeMap<InternedStr, (u32, u32)>,
+    #[cfg(feature = "render")]
+    material_handles: BTreeMap<InternedStr, Handle<StandardMaterial>>,
+    /// auxiliary textures by slot, see [`settings::MapLoaderSettings::texture_suffixes`]
+    #[cfg(feature = "render")]
+    auxiliary_textures: BTreeMap<InternedStr, BTreeMap<materials::TextureSlot, Handle<Image>>>,
+    /// from `textures/{name}.ron`
+    #[cfg(feature = "render")]
+    material_definitions: BTreeMap<InternedStr, materials::MaterialDefinition>,
+    #[cfg(feature = "render")]
+    material_quality: settings::MaterialQuality,
+    build_mode: settings::MapBuildMode,
+    geometry_tolerance: settings::GeometryTolerance,
+    interner: Interner,
+    textures_loaded: bool,
+    /// maps included through `misc_external_map`, keyed by entity index
+    external_maps: BTreeMap<usize, Handle<MapAsset>>,
+    /// textures replaced through [`MapAsset::replace_texture`], applied by
+    /// [`materials::texture_swap_system`]
+    #[cfg(feature = "render")]
+    pending_texture_swaps: Vec<(InternedStr, materials::TextureHandles)>,
+    /// faces retextured through [`MapAsset::retexture_face`], by face index
+    face_textures: BTreeMap<usize, InternedStr>,
+    /// size of the decoded textures, see [`components::MapMemoryUsage`]
+    texture_bytes: usize,
 }
 
 impl MapAsset {
+    /// Parses a map from memory, without going through the asset server.
+    /// No textures are loaded, so only colliders are built unless materials
+    /// are added with [`MapAsset::insert_material`], and `misc_external_map`
+    /// entities aren't included. Spawn it with [`load::spawn_map`].
+    pub fn from_bytes(
+        bytes: &[u8],
+        settings: &settings::MapLoaderSettings,
+    ) -> Result<Self, MapAssetLoaderError> {
+        let (geomap, brush_primitives) = core::parse_map(bytes)?;
+        Ok(Self {
+            geomap: Some(geomap),
+            brush_primitives,
+            ..load::new_map_asset(settings, true)
+        })
+    }
+
+    /// Uses `material` for the faces textured with `texture_name`, e.g. for
+    /// maps parsed with [`MapAsset::from_bytes`]. `size` is the texture size
+    /// in pixels, used to generate UVs.
+    #[cfg(feature = "render")]
+    pub fn insert_material(
+        &mut self,
+        texture_name: &str,
+        material: Handle<StandardMaterial>,
+        size: Option<(u32, u32)>,
+    ) {
+        let texture_name = self.interner.intern(texture_name);
+        if let Some(size) = size {
+            self.texture_sizes.insert(texture_name.clone(), size);
+        }
+        self.material_handles.insert(texture_name, material);
+    }
+
     pub fn get_texture_names_with_size(&self) -> BTreeMap<&str, (u32, u32)> {
         let mut names: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
         for (texture_name, (width, height)) in &self.texture_sizes {
@@ -29,12 +143,162 @@ impl MapAsset {
         }
         names
     }
+
+    /// An auxiliary texture loaded for `texture_name`, e.g. its normal map or a custom slot.
+    #[cfg(feature = "render")]
+    pub fn auxiliary_texture(
+        &self,
+        texture_name: &str,
+        slot: &materials::TextureSlot,
+    ) -> Option<&Handle<Image>> {
+        self.auxiliary_textures.get(texture_name)?.get(slot)
+    }
+
+    /// Swaps the textures of `texture_name` for `handles` on the next update,
+    /// e.g. to toggle an HD texture pack. Slots missing from `handles` keep
+    /// their current texture, and brush UVs are rescaled to the new size by
+    /// [`build::rescale_brush_uvs_system`].
+    /// Returns false if the map has no material for `texture_name`.
+    #[cfg(feature = "render")]
+    pub fn replace_texture(
+        &mut self,
+        texture_name: &str,
+        handles: materials::TextureHandles,
+    ) -> bool {
+        if !self.material_handles.contains_key(texture_name) {
+            return false;
+        }
+        let texture_name = self.interner.intern(texture_name);
+        if !handles.auxiliary.is_empty() {
+            self.auxiliary_textures
+                .entry(texture_name.clone())
+                .or_default()
+                .extend(handles.auxiliary.clone());
+        }
+        self.pending_texture_swaps.push((texture_name, handles));
+        true
+    }
+
+    /// Changes the texture of a face, by its index in the .map file. Kept
+    /// when the map is compiled again, see [`MapAsset::face_textures`].
+    /// Spawned maps are updated through [`picking::RetextureFaceEvent`].
+    pub fn retexture_face(&mut self, face_index: usize, texture_name: &str) {
+        let texture_name = self.interner.intern(texture_name);
+        self.face_textures.insert(face_index, texture_name);
+    }
+
+    /// The faces retextured at runtime, by face index, e.g. to write them
+    /// back to the .map file.
+    pub fn face_textures(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.face_textures
+            .iter()
+            .map(|(face_index, texture_name)| (*face_index, texture_name.as_str()))
+    }
+
+    /// Compiles the map's geometry and entities, see [`core::compile_geomap`].
+    /// Processed maps are only rescaled and have their UVs normalized, see
+    /// [`core::ProcessedMap::compile`].
+    pub fn compile(&self, units: &MapUnits) -> Option<core::CompiledMap> {
+        let settings = core::CompileSettings {
+            units: units.clone(),
+            texture_sizes: self
+                .texture_sizes
+                .iter()
+                .map(|(name, size)| (name.to_string(), *size))
+                .collect(),
+            face_textures: self
+                .face_textures
+                .iter()
+                .map(|(face_index, name)| (*face_index, name.to_string()))
+                .collect(),
+            tolerance: self.geometry_tolerance.clone(),
+        };
+        if let Some(processed) = &self.processed {
+            return Some(processed.compile(&settings));
+        }
+        Some(core::compile_parsed_map(
+            self.geomap.as_ref()?,
+            &self.brush_primitives,
+            &settings,
+        ))
+    }
+
+    /// Checks whether the worldspawn geometry seals every point entity in,
+    /// see [`leak::find_leak`].
+    pub fn find_leak(&self, units: &MapUnits, cell_size: f32) -> leak::LeakReport {
+        match self.compile(units) {
+            Some(compiled_map) => leak::find_leak(&compiled_map, cell_size),
+            None => leak::LeakReport::default(),
+        }
+    }
+
+    /// Hashes the map's geometry, see [`snapshot::GeometrySnapshot`].
+    pub fn geometry_snapshot(&self, units: &MapUnits) -> Option<snapshot::GeometrySnapshot> {
+        Some(snapshot::GeometrySnapshot::new(&self.compile(units)?))
+    }
+
+    /// Renders a top-down image of the map, see [`minimap::render_minimap`].
+    #[cfg(feature = "render")]
+    pub fn minimap(&self, units: &MapUnits, settings: &minimap::MinimapSettings) -> Option<Image> {
+        minimap::render_minimap(&self.compile(units)?, settings)
+    }
+
+    /// Lints the map for common authoring mistakes.
+    /// Missing textures are only reported if the textures were loaded.
+    pub fn validate(&self) -> Vec<validate::MapIssue> {
+        let Some(compiled_map) = self.compile(&MapUnits::default()) else {
+            return Vec::new();
+        };
+        #[cfg(feature = "render")]
+        let known_textures = self
+            .material_handles
+            .keys()
+            .map(|name| name.to_string())
+            .collect::<BTreeSet<_>>();
+        #[cfg(not(feature = "render"))]
+        let known_textures = BTreeSet::new();
+        validate::validate_compiled_map(
+            &compiled_map,
+            self.textures_loaded.then_some(&known_textures),
+        )
+    }
+
+    /// Lints the map like [`Self::validate`], and checks the properties of
+    /// entities against the FGD classes in `definitions`, see
+    /// [`validate::validate_definitions`].
+    pub fn validate_with_definitions(
+        &self,
+        definitions: &fgd::EntityDefinitions,
+    ) -> Vec<validate::MapIssue> {
+        let mut issues = self.validate();
+        if definitions.classes.is_empty() {
+            return issues;
+        }
+        if let Some(compiled_map) = self.compile(&MapUnits::default()) {
+            issues.extend(validate::validate_definitions(&compiled_map, definitions));
+        }
+        issues
+    }
+}
+
+/// Parses a map with the default [`settings::MapLoaderSettings`], e.g.
+/// `include_str!("level.map").parse::<MapAsset>()`, see [`MapAsset::from_bytes`].
+impl std::str::FromStr for MapAsset {
+    type Err = MapAssetLoaderError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(text.as_bytes(), &settings::MapLoaderSettings::default())
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum MapAssetLoaderError {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("compile error: {0}")]
+    Compile(#[from] core::CompileError),
+    #[error("invalid processed map: {0}")]
+    Processed(#[from] ron::error::SpannedError),
 }
 
 #[derive(Default)]
@@ -42,15 +306,15 @@ pub struct MapAssetLoader;
 
 impl AssetLoader for MapAssetLoader {
     type Asset = MapAsset;
-    type Settings = ();
+    type Settings = settings::MapLoaderSettings;
     type Error = MapAssetLoaderError;
     fn load<'a>(
         &'a self,
         reader: &'a mut Reader,
-        _settings: &'a Self::Settings,
+        settings: &'a Self::Settings,
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
-        load::load(reader, load_context, false)
+        load::load(reader, settings, load_context, false)
     }
 
     fn extensions(&self) -> &[&str] {
@@ -63,15 +327,15 @@ pub struct HeadlessMapAssetLoader;
 
 impl AssetLoader for HeadlessMapAssetLoader {
     type Asset = MapAsset;
-    type Settings = ();
+    type Settings = settings::MapLoaderSettings;
     type Error = MapAssetLoaderError;
     fn load<'a>(
         &'a self,
         reader: &'a mut Reader,
-        _settings: &'a Self::Settings,
+        settings: &'a Self::Settings,
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
-        load::load(reader, load_context, true)
+        load::load(reader, settings, load_context, true)
     }
 
     fn extensions(&self) -> &[&str] {
@@ -83,31 +347,67 @@ impl AssetLoader for HeadlessMapAssetLoader {
 pub struct PostBuildMapEvent {
     pub map: Entity,
 }
+
+/// Send after mutating an entity's `MapEntityProperties` to re-apply the
+/// built-in property → component mapping, without rebuilding the map.
+#[derive(Event)]
+pub struct ReapplyEntityPropertiesEvent(pub Entity);
+
+/// Loads .map files into [`MapAsset`]s. Add [`QevyPlugins`] to also build
+/// them, see [`plugins`].
 #[derive(Default)]
 pub struct MapAssetLoaderPlugin {
-    /// If true, the plugin will not add meshes, only colliders
+    /// If true, the plugin will not add meshes, only colliders. Always the
+    /// case without the `render` feature.
     pub headless: bool,
     pub units: MapUnits,
+    pub build_settings: settings::MapBuildSettings,
 }
 
 impl Plugin for MapAssetLoaderPlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<MapAsset>()
             .add_event::<components::TriggeredEvent>()
-            .add_event::<PostBuildMapEvent>()
-            .add_event::<build::SpawnMeshEvent>();
+            .add_event::<components::DegenerateGeometry>()
+            .init_resource::<registry::MapEntityRegistry>()
+            .init_resource::<plugins::QevyGameplayState>()
+            .configure_sets(
+                PreUpdate,
+                (
+                    plugins::QevySet::Parse,
+                    plugins::QevySet::BuildGeometry,
+                    plugins::QevySet::SpawnMeshes,
+                )
+                    .chain(),
+            )
+            .configure_sets(
+                Update,
+                (
+                    plugins::QevySet::PostBuild,
+                    plugins::QevySet::Gameplay
+                        .run_if(resource_equals(plugins::QevyGameplayState::Run)),
+                )
+                    .chain(),
+            )
+            .configure_sets(
+                FixedUpdate,
+                plugins::QevySet::Gameplay.run_if(resource_equals(plugins::QevyGameplayState::Run)),
+            );
 
         app.insert_resource(self.units.clone());
+        app.insert_resource(self.build_settings.clone());
+
+        let headless = self.headless || !cfg!(feature = "render");
+
+        // only used in `AssetMode::Processed`
+        app.register_asset_loader(process::ProcessedMapLoader { headless })
+            .register_asset_processor(process::MapProcessor)
+            .set_default_asset_processor::<process::MapProcessor>("map");
 
-        if self.headless {
+        if headless {
             info!("Using headless map loader. Only colliders will be added.");
-            app.add_systems(PreUpdate, load::handle_loaded_map_system);
             app.init_asset_loader::<HeadlessMapAssetLoader>();
         } else {
-            app.add_systems(
-                PreUpdate,
-                (load::handle_loaded_map_system, build::mesh_spawn_system).chain(),
-            );
             app.init_asset_loader::<MapAssetLoader>();
         }
     }
@@ -0,0 +1,376 @@
+//! `func_liquid` brush entities.
+//!
+//! Liquids are drawn but don't collide, and their `liquid` property sets
+//! their [`LiquidKind`]: `water` (the default), `slime`, `lava` or any name
+//! of your own. With the `render` feature, cameras with a `LiquidCamera` get
+//! a `CameraEnteredLiquid` event when they go under the surface and a
+//! `CameraExitedLiquid` one when they come back out. Unless `apply_effects`
+//! is off, the camera also gets the `UnderwaterEffects` of the kind from the
+//! `LiquidEffects` resource while submerged, for your post-processing and
+//! audio to read, and its `FogSettings` are tinted with them.
+//!
+//! Entities with a [`LiquidTracked`] marker, which dynamic physics bodies get
+//! automatically, are tagged with [`InLiquid`] while inside a liquid, for
+//! character controllers to implement swimming. Liquids with a
+//! `current_speed` (map units per second) along `current_direction` (a map
+//! space vector) make dynamic bodies drift, accelerating them towards the
+//! current's speed at a rate of `current_drag` per second.
+
+#[cfg(feature = "render")]
+use bevy::pbr::{FogFalloff, FogSettings};
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::utils::HashMap;
+
+use crate::components::{BrushVolume, MapEntityProperties, MapUnits};
+use crate::conversions::{to_bevy_distance, to_bevy_position};
+
+#[cfg(feature = "xpbd")]
+use bevy_xpbd_3d::prelude::{LinearVelocity, RigidBody};
+
+#[cfg(feature = "rapier")]
+#[cfg(not(feature = "xpbd"))]
+use bevy_rapier3d::prelude::{RigidBody, Velocity};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum LiquidKind {
+    #[default]
+    Water,
+    Slime,
+    Lava,
+    Custom(String),
+}
+
+impl LiquidKind {
+    pub fn from_property(value: &str) -> Self {
+        match value.trim() {
+            "" | "water" => Self::Water,
+            "slime" => Self::Slime,
+            "lava" => Self::Lava,
+            other => Self::Custom(other.to_owned()),
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct LiquidVolume {
+    pub kind: LiquidKind,
+    /// velocity of the current, in Bevy units per second
+    pub current: Vec3,
+    /// how fast bodies catch up with the current, per second
+    pub current_drag: f32,
+}
+
+/// Marks entities to tag with [`InLiquid`].
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct LiquidTracked;
+
+/// Inserted on [`LiquidTracked`] entities while they are inside a liquid.
+#[derive(Component, Debug, Clone)]
+pub struct InLiquid {
+    pub volume: Entity,
+    pub kind: LiquidKind,
+    /// distance below the surface, in Bevy units
+    pub depth: f32,
+    /// velocity of the current, in Bevy units per second
+    pub current: Vec3,
+    pub current_drag: f32,
+}
+
+impl InLiquid {
+    /// Velocity change pulling a body moving at `velocity` along the current.
+    pub fn current_drift(&self, velocity: Vec3, delta_seconds: f32) -> Vec3 {
+        let Some(direction) = self.current.try_normalize() else {
+            return Vec3::ZERO;
+        };
+        let missing = self.current.length() - velocity.dot(direction);
+        if missing <= 0.0 {
+            return Vec3::ZERO;
+        }
+        direction * missing * (self.current_drag * delta_seconds).min(1.0)
+    }
+}
+
+/// Effects of a camera under a liquid. Only the tint is applied by qevy, as
+/// fog, the rest is for the game's post-processing and audio.
+#[cfg(feature = "render")]
+#[derive(Component, Debug, Clone)]
+pub struct UnderwaterEffects {
+    /// fog color
+    pub tint: Color,
+    /// exponential fog density, per Bevy unit
+    pub fog_density: f32,
+    /// depth of field blur strength, 0 for none
+    pub blur: f32,
+    /// audio low-pass cutoff frequency in Hz, `None` for no filter
+    pub lowpass_cutoff: Option<f32>,
+}
+
+#[cfg(feature = "render")]
+impl UnderwaterEffects {
+    pub fn fog_settings(&self) -> FogSettings {
+        FogSettings {
+            color: self.tint,
+            falloff: FogFalloff::Exponential {
+                density: self.fog_density,
+            },
+            ..default()
+        }
+    }
+}
+
+/// The [`UnderwaterEffects`] of each liquid kind.
+#[cfg(feature = "render")]
+#[derive(Resource, Debug, Clone)]
+pub struct LiquidEffects {
+    pub effects: HashMap<LiquidKind, UnderwaterEffects>,
+    /// effects of kinds missing from `effects`
+    pub fallback: UnderwaterEffects,
+}
+
+#[cfg(feature = "render")]
+impl Default for LiquidEffects {
+    fn default() -> Self {
+        let water = UnderwaterEffects {
+            tint: Color::rgb(0.1, 0.25, 0.35),
+            fog_density: 0.15,
+            blur: 0.5,
+            lowpass_cutoff: Some(800.0),
+        };
+        let mut effects = HashMap::new();
+        effects.insert(
+            LiquidKind::Slime,
+            UnderwaterEffects {
+                tint: Color::rgb(0.2, 0.35, 0.05),
+                fog_density: 0.4,
+                ..water.clone()
+            },
+        );
+        effects.insert(
+            LiquidKind::Lava,
+            UnderwaterEffects {
+                tint: Color::rgb(0.9, 0.3, 0.0),
+                fog_density: 1.0,
+                ..water.clone()
+            },
+        );
+        effects.insert(LiquidKind::Water, water.clone());
+        Self {
+            effects,
+            fallback: water,
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl LiquidEffects {
+    pub fn get(&self, kind: &LiquidKind) -> &UnderwaterEffects {
+        self.effects.get(kind).unwrap_or(&self.fallback)
+    }
+}
+
+/// Tracks the liquid a camera is submerged in.
+#[cfg(feature = "render")]
+#[derive(Component, Debug, Clone)]
+pub struct LiquidCamera {
+    /// insert [`UnderwaterEffects`] and tint the fog while submerged
+    pub apply_effects: bool,
+    pub liquid: Option<(Entity, LiquidKind)>,
+    /// the camera's own fog, restored when it leaves the liquid
+    outside_fog: Option<FogSettings>,
+}
+
+#[cfg(feature = "render")]
+impl Default for LiquidCamera {
+    fn default() -> Self {
+        Self {
+            apply_effects: true,
+            liquid: None,
+            outside_fog: None,
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Event, Debug, Clone)]
+pub struct CameraEnteredLiquid {
+    pub camera: Entity,
+    pub volume: Entity,
+    pub kind: LiquidKind,
+}
+
+#[cfg(feature = "render")]
+#[derive(Event, Debug, Clone)]
+pub struct CameraExitedLiquid {
+    pub camera: Entity,
+    pub volume: Entity,
+    pub kind: LiquidKind,
+}
+
+/// Inserts the [`LiquidVolume`] component for a `func_liquid` entity.
+pub fn apply_liquid_properties(
+    map_units: &MapUnits,
+    scale: f32,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    // only the axes are swapped, the speed is converted on its own
+    let direction = to_bevy_position(
+        &props.get_property_as_vec3("current_direction", Vec3::ZERO),
+        &MapUnits::Trenchbroom,
+    )
+    .normalize_or_zero();
+    let speed =
+        to_bevy_distance(props.get_property_as_f32("current_speed", 0.0), map_units) * scale;
+    commands.entity(entity).insert(LiquidVolume {
+        kind: LiquidKind::from_property(props.properties.get("liquid").map_or("", |v| v.as_str())),
+        current: direction * speed,
+        current_drag: props.get_property_as_f32("current_drag", 1.0).max(0.0),
+    });
+}
+
+/// Inserts, updates and removes the [`InLiquid`] of [`LiquidTracked`] entities.
+pub fn in_liquid_system(
+    mut commands: Commands,
+    mut q_tracked: Query<(Entity, &GlobalTransform, Option<&mut InLiquid>), With<LiquidTracked>>,
+    q_liquids: Query<(Entity, &LiquidVolume, &BrushVolume, &GlobalTransform)>,
+) {
+    for (entity, transform, in_liquid) in q_tracked.iter_mut() {
+        let position = transform.translation();
+        let inside = q_liquids
+            .iter()
+            .find_map(|(volume, liquid, brushes, volume_transform)| {
+                let local = volume_transform
+                    .affine()
+                    .inverse()
+                    .transform_point3(position);
+                let surface = brushes.surface_height(local)?;
+                let surface =
+                    volume_transform.transform_point(Vec3::new(local.x, surface, local.z));
+                Some(InLiquid {
+                    volume,
+                    kind: liquid.kind.clone(),
+                    depth: surface.y - position.y,
+                    current: liquid.current,
+                    current_drag: liquid.current_drag,
+                })
+            });
+        match (inside, in_liquid) {
+            (Some(inside), Some(mut in_liquid)) => *in_liquid = inside,
+            (Some(inside), None) => {
+                commands.entity(entity).insert(inside);
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<InLiquid>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Marks new dynamic bodies as [`LiquidTracked`].
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+pub fn track_liquid_bodies_system(
+    mut commands: Commands,
+    q_bodies: Query<(Entity, &RigidBody), (Added<RigidBody>, Without<LiquidTracked>)>,
+) {
+    for (entity, body) in q_bodies.iter() {
+        if *body == RigidBody::Dynamic {
+            commands.entity(entity).insert(LiquidTracked);
+        }
+    }
+}
+
+#[cfg(feature = "xpbd")]
+pub fn xpbd_liquid_current_system(
+    time: Res<Time>,
+    mut q_bodies: Query<(&InLiquid, &RigidBody, &mut LinearVelocity)>,
+) {
+    for (in_liquid, body, mut velocity) in q_bodies.iter_mut() {
+        if *body == RigidBody::Dynamic {
+            let drift = in_liquid.current_drift(velocity.0, time.delta_seconds());
+            velocity.0 += drift;
+        }
+    }
+}
+
+#[cfg(feature = "rapier")]
+#[cfg(not(feature = "xpbd"))]
+pub fn rapier_liquid_current_system(
+    time: Res<Time>,
+    mut q_bodies: Query<(&InLiquid, &RigidBody, &mut Velocity)>,
+) {
+    for (in_liquid, body, mut velocity) in q_bodies.iter_mut() {
+        if *body == RigidBody::Dynamic {
+            let drift = in_liquid.current_drift(velocity.linvel, time.delta_seconds());
+            velocity.linvel += drift;
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn liquid_camera_system(
+    mut commands: Commands,
+    liquid_effects: Res<LiquidEffects>,
+    mut entered_events: EventWriter<CameraEnteredLiquid>,
+    mut exited_events: EventWriter<CameraExitedLiquid>,
+    mut q_cameras: Query<(
+        Entity,
+        &mut LiquidCamera,
+        &GlobalTransform,
+        Option<&FogSettings>,
+    )>,
+    q_liquids: Query<(Entity, &LiquidVolume, &BrushVolume, &GlobalTransform)>,
+) {
+    for (camera, mut tracker, camera_transform, fog) in q_cameras.iter_mut() {
+        let position = camera_transform.translation();
+        let inside = q_liquids
+            .iter()
+            .find(|(_, _, volume, transform)| volume.contains_world(transform, position))
+            .map(|(entity, liquid, ..)| (entity, liquid.kind.clone()));
+        if inside.as_ref().map(|(entity, _)| *entity)
+            == tracker.liquid.as_ref().map(|(entity, _)| *entity)
+        {
+            continue;
+        }
+
+        if let Some((volume, kind)) = tracker.liquid.clone() {
+            exited_events.send(CameraExitedLiquid {
+                camera,
+                volume,
+                kind,
+            });
+        }
+        if let Some((volume, kind)) = inside.clone() {
+            entered_events.send(CameraEnteredLiquid {
+                camera,
+                volume,
+                kind,
+            });
+        }
+
+        if tracker.apply_effects {
+            match &inside {
+                Some((_, kind)) => {
+                    if tracker.liquid.is_none() {
+                        tracker.outside_fog = fog.cloned();
+                    }
+                    let effects = liquid_effects.get(kind);
+                    commands
+                        .entity(camera)
+                        .insert((effects.clone(), effects.fog_settings()));
+                }
+                None => {
+                    let mut camera = commands.entity(camera);
+                    camera.remove::<UnderwaterEffects>();
+                    match tracker.outside_fog.take() {
+                        Some(outside_fog) => camera.insert(outside_fog),
+                        None => camera.remove::<FogSettings>(),
+                    };
+                }
+            }
+        }
+        tracker.liquid = inside;
+    }
+}
@@ -0,0 +1,306 @@
+//! Live link with the level editor, behind the `live_link` feature.
+//!
+//! The feature turns on Bevy's asset file watcher, so saving a map in
+//! TrenchBroom rebuilds it in the running game. [`MapLiveLinkPlugin`] also
+//! listens on a TCP port for text commands, one per line:
+//!
+//! - `reload [path]` reloads the map asset at `path`, or every map, e.g.
+//!   from a step of a TrenchBroom compile profile running
+//!   `echo reload | nc localhost 27960`
+//! - `camera <x> <y> <z> <pitch> <yaw>` moves the entities with
+//!   [`FollowEditorCamera`] to a position in map coordinates, looking the
+//!   way of Quake view angles (a positive pitch looks down)
+//!
+//! Commands are sent as [`LiveLinkCommand`] events, so games can react to
+//! them as well.
+//!
+//! Rebuilds aren't incremental: a reloaded map despawns and respawns all of
+//! its entities and meshes, the same as any other change of its asset, so
+//! game state on map entities is lost on each save.
+
+use bevy::prelude::*;
+use std::io::{ErrorKind, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+use crate::components::{Map, MapScale, MapUnits};
+use crate::conversions::to_bevy_position;
+
+pub const DEFAULT_LIVE_LINK_PORT: u16 = 27960;
+
+pub struct MapLiveLinkPlugin {
+    /// address to listen on, only the local machine by default
+    pub address: SocketAddr,
+}
+
+impl Default for MapLiveLinkPlugin {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([127, 0, 0, 1], DEFAULT_LIVE_LINK_PORT)),
+        }
+    }
+}
+
+impl Plugin for MapLiveLinkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LiveLinkCommand>();
+        match LiveLinkServer::bind(self.address) {
+            Ok(server) => {
+                info!(address = %self.address, "map live link listening");
+                app.insert_resource(server);
+            }
+            Err(e) => {
+                warn!(address = %self.address, "failed to start the map live link: {}", e);
+            }
+        }
+        app.add_systems(
+            PreUpdate,
+            (
+                receive_live_link_commands_system.run_if(resource_exists::<LiveLinkServer>),
+                live_link_reload_system,
+                follow_editor_camera_system,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Entities moved by `camera` commands, e.g. the game's camera.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct FollowEditorCamera;
+
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum LiveLinkCommand {
+    /// reload the map asset at the path, or every map, rebuilding them from scratch
+    Reload(Option<String>),
+    /// the editor camera moved
+    Camera {
+        /// in map coordinates
+        origin: Vec3,
+        /// degrees, positive down
+        pitch: f32,
+        /// degrees, counterclockwise from the map's X axis
+        yaw: f32,
+    },
+}
+
+impl LiveLinkCommand {
+    /// Parses a line received through the live link.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        match command {
+            "reload" => Some(Self::Reload((!args.is_empty()).then(|| args.to_string()))),
+            "camera" => {
+                let numbers = args
+                    .split_whitespace()
+                    .map(|arg| arg.parse::<f32>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                let [x, y, z, pitch, yaw] = numbers[..] else {
+                    return None;
+                };
+                Some(Self::Camera {
+                    origin: Vec3::new(x, y, z),
+                    pitch,
+                    yaw,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The live link's listener and the editors connected to it.
+#[derive(Resource)]
+pub struct LiveLinkServer {
+    listener: TcpListener,
+    clients: Vec<LiveLinkClient>,
+}
+
+struct LiveLinkClient {
+    stream: TcpStream,
+    /// received bytes not ending a line yet
+    pending: Vec<u8>,
+}
+
+impl LiveLinkServer {
+    pub fn bind(address: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Accepts new connections and returns the complete lines received since
+    /// the last call, without blocking.
+    fn receive_lines(&mut self) -> Vec<String> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        warn!(%address, "failed to accept live link connection: {}", e);
+                        continue;
+                    }
+                    debug!(%address, "live link connected");
+                    self.clients.push(LiveLinkClient {
+                        stream,
+                        pending: Vec::new(),
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("live link error: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        let mut buffer = [0; 1024];
+        self.clients.retain_mut(|client| {
+            let open = loop {
+                match client.stream.read(&mut buffer) {
+                    Ok(0) => break false,
+                    Ok(read) => client.pending.extend_from_slice(&buffer[..read]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break true,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break false,
+                }
+            };
+            while let Some(end) = client.pending.iter().position(|&byte| byte == b'\n') {
+                let line = client.pending.drain(..=end).collect::<Vec<_>>();
+                lines.push(String::from_utf8_lossy(&line).trim().to_string());
+            }
+            // a last line without a newline before the editor disconnected
+            if !open && !client.pending.is_empty() {
+                lines.push(String::from_utf8_lossy(&client.pending).trim().to_string());
+            }
+            open
+        });
+        lines.retain(|line| !line.is_empty());
+        lines
+    }
+}
+
+pub fn receive_live_link_commands_system(
+    mut server: ResMut<LiveLinkServer>,
+    mut commands: EventWriter<LiveLinkCommand>,
+) {
+    for line in server.receive_lines() {
+        match LiveLinkCommand::parse(&line) {
+            Some(command) => {
+                commands.send(command);
+            }
+            None => warn!(%line, "unknown live link command"),
+        }
+    }
+}
+
+/// Reloads map assets, which rebuilds their maps from scratch once loaded.
+pub fn live_link_reload_system(
+    asset_server: Res<AssetServer>,
+    mut commands: EventReader<LiveLinkCommand>,
+    q_maps: Query<&Map>,
+) {
+    for command in commands.read() {
+        let LiveLinkCommand::Reload(path) = command else {
+            continue;
+        };
+        match path {
+            Some(path) => asset_server.reload(path.clone()),
+            None => {
+                for map in q_maps.iter() {
+                    if let Some(path) = asset_server.get_path(map.asset.id()) {
+                        asset_server.reload(path.into_owned());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Moves the [`FollowEditorCamera`] entities to the editor camera, relative
+/// to the first map.
+pub fn follow_editor_camera_system(
+    map_units: Res<MapUnits>,
+    mut commands: EventReader<LiveLinkCommand>,
+    q_maps: Query<(&GlobalTransform, Option<&MapScale>), With<Map>>,
+    mut q_followers: Query<&mut Transform, With<FollowEditorCamera>>,
+) {
+    let Some((origin, pitch, yaw)) = commands
+        .read()
+        .filter_map(|command| match command {
+            LiveLinkCommand::Camera { origin, pitch, yaw } => Some((*origin, *pitch, *yaw)),
+            _ => None,
+        })
+        .last()
+    else {
+        return;
+    };
+    let (map_transform, scale) = q_maps
+        .iter()
+        .next()
+        .map_or((GlobalTransform::IDENTITY, 1.0), |(transform, scale)| {
+            (*transform, scale.map_or(1.0, |scale| scale.0))
+        });
+
+    let (pitch, yaw) = (pitch.to_radians(), yaw.to_radians());
+    let direction = Vec3::new(
+        yaw.cos() * pitch.cos(),
+        yaw.sin() * pitch.cos(),
+        -pitch.sin(),
+    );
+    // map directions swizzle like positions, without the unit conversion
+    let direction =
+        map_transform
+            .affine()
+            .transform_vector3(Vec3::new(direction.y, direction.z, direction.x));
+    let translation = map_transform.transform_point(to_bevy_position(&origin, &map_units) * scale);
+    for mut transform in q_followers.iter_mut() {
+        transform.translation = translation;
+        if direction.length_squared() > 0.0 {
+            transform.look_to(direction, Vec3::Y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_reload() {
+        assert_eq!(
+            LiveLinkCommand::parse("reload"),
+            Some(LiveLinkCommand::Reload(None))
+        );
+        assert_eq!(
+            LiveLinkCommand::parse("  reload   maps/e1m1.map \r"),
+            Some(LiveLinkCommand::Reload(Some("maps/e1m1.map".to_string())))
+        );
+    }
+
+    #[test]
+    fn parses_camera() {
+        assert_eq!(
+            LiveLinkCommand::parse("camera 64 -128 32.5 15 90"),
+            Some(LiveLinkCommand::Camera {
+                origin: Vec3::new(64.0, -128.0, 32.5),
+                pitch: 15.0,
+                yaw: 90.0,
+            })
+        );
+        assert_eq!(LiveLinkCommand::parse("camera 64 -128 32.5 15"), None);
+        assert_eq!(LiveLinkCommand::parse("camera 64 -128 32.5 15 90 0"), None);
+        assert_eq!(LiveLinkCommand::parse("camera 64 -128 up 15 90"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert_eq!(LiveLinkCommand::parse(""), None);
+        assert_eq!(LiveLinkCommand::parse("reloadall"), None);
+        assert_eq!(LiveLinkCommand::parse("quit"), None);
+    }
+}
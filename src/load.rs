@@ -1,18 +1,31 @@
+#[cfg(feature = "render")]
 use crate::build::SpawnMeshEvent;
+use crate::intern::Interner;
+#[cfg(feature = "render")]
+use crate::materials::{MaterialDefinition, TextureSlot};
+use crate::registry::MapEntityRegistry;
+use crate::settings::{MapBuildSettings, MapLoaderSettings};
 use crate::{components::*, MapAssetLoaderError};
 use crate::{MapAsset, PostBuildMapEvent};
 use bevy::asset::io::Reader;
 use bevy::asset::AsyncReadExt;
+#[cfg(feature = "render")]
 use bevy::asset::LoadedAsset;
 use bevy::asset::{BoxedFuture, LoadContext};
 use bevy::prelude::*;
+#[cfg(feature = "render")]
 use bevy::render::render_asset::RenderAssetUsages;
-use bevy::render::texture::CompressedImageFormats;
-use bevy::render::texture::ImageAddressMode;
-use bevy::render::texture::ImageSampler;
-use bevy::render::texture::ImageSamplerDescriptor;
-use bevy::render::texture::ImageType;
+#[cfg(feature = "render")]
+use bevy::render::texture::{
+    CompressedImageFormats, ImageSampler, ImageSamplerDescriptor, ImageType,
+};
+#[cfg(feature = "render")]
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy::utils::Instant;
 use std::collections::BTreeMap;
+#[cfg(feature = "render")]
+use std::collections::BTreeSet;
+use tracing::Instrument;
 
 pub(crate) fn extensions() -> &'static [&'static str] {
     &["map"]
@@ -20,115 +33,462 @@ pub(crate) fn extensions() -> &'static [&'static str] {
 
 pub(crate) fn load<'a>(
     reader: &'a mut Reader,
+    settings: &'a MapLoaderSettings,
     load_context: &'a mut LoadContext,
     headless: bool,
 ) -> BoxedFuture<'a, Result<MapAsset, MapAssetLoaderError>> {
-    Box::pin(async move {
-        let mut bytes = Vec::new();
-        reader.read_to_end(&mut bytes).await?;
-        if let Ok(map) = std::str::from_utf8(&bytes)
-            .expect("invalid utf8")
-            .parse::<shalrath::repr::Map>()
-        {
-            let geomap = Some(shambler::GeoMap::new(map.clone()));
-            let mut map = MapAsset {
-                geomap: geomap,
-                texture_sizes: BTreeMap::new(),
-                material_handles: BTreeMap::new(),
+    let span = debug_span!("load_map", path = %load_context.path().display());
+    Box::pin(
+        async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let parse_start = Instant::now();
+            let (geomap, brush_primitives) = crate::core::parse_map(&bytes)?;
+            debug!(elapsed = ?parse_start.elapsed(), bytes = bytes.len(), "parsed map");
+            let map = MapAsset {
+                geomap: Some(geomap),
+                brush_primitives,
+                ..new_map_asset(settings, headless)
             };
+            finish_loading(map, settings, load_context).await
+        }
+        .instrument(span),
+    )
+}
 
-            if !headless {
-                load_map_textures(&mut map, load_context).await;
-            }
-            return Ok(map);
+/// Loads a map compiled by [`crate::process::MapProcessor`].
+pub(crate) fn load_processed<'a>(
+    reader: &'a mut Reader,
+    settings: &'a MapLoaderSettings,
+    load_context: &'a mut LoadContext,
+    headless: bool,
+) -> BoxedFuture<'a, Result<MapAsset, MapAssetLoaderError>> {
+    let span = debug_span!("load_processed_map", path = %load_context.path().display());
+    Box::pin(
+        async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let map = MapAsset {
+                processed: Some(ron::de::from_bytes(&bytes)?),
+                ..new_map_asset(settings, headless)
+            };
+            finish_loading(map, settings, load_context).await
         }
-        Err(MapAssetLoaderError::Io(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "invalid map",
-        )))
-    })
+        .instrument(span),
+    )
+}
+
+pub(crate) fn new_map_asset(settings: &MapLoaderSettings, headless: bool) -> MapAsset {
+    MapAsset {
+        geomap: None,
+        processed: None,
+        brush_primitives: Default::default(),
+        texture_sizes: BTreeMap::new(),
+        #[cfg(feature = "render")]
+        material_handles: BTreeMap::new(),
+        #[cfg(feature = "render")]
+        auxiliary_textures: BTreeMap::new(),
+        #[cfg(feature = "render")]
+        material_definitions: BTreeMap::new(),
+        #[cfg(feature = "render")]
+        material_quality: settings.material_quality,
+        build_mode: settings.build_mode,
+        geometry_tolerance: settings.geometry_tolerance.clone(),
+        interner: Interner::default(),
+        textures_loaded: cfg!(feature = "render")
+            && !headless
+            && settings.build_mode.spawns_geometry(),
+        external_maps: BTreeMap::new(),
+        #[cfg(feature = "render")]
+        pending_texture_swaps: Vec::new(),
+        face_textures: BTreeMap::new(),
+        texture_bytes: 0,
+    }
+}
+
+#[cfg_attr(not(feature = "render"), allow(unused_variables))]
+async fn finish_loading(
+    mut map: MapAsset,
+    settings: &MapLoaderSettings,
+    load_context: &mut LoadContext<'_>,
+) -> Result<MapAsset, MapAssetLoaderError> {
+    load_external_maps(&mut map, settings, load_context);
+
+    #[cfg(feature = "render")]
+    if map.textures_loaded {
+        load_map_textures(&mut map, settings, load_context).await;
+    }
+    Ok(map)
+}
+
+/// Spawns a map from an in-memory [`MapAsset`], e.g. one parsed with
+/// [`MapAsset::from_bytes`], returning the map entity. The map is built on
+/// the next update, like maps loaded through the asset server.
+pub fn spawn_map(
+    commands: &mut Commands,
+    map_assets: &mut Assets<MapAsset>,
+    map_asset: MapAsset,
+) -> Entity {
+    commands
+        .spawn(MapBundle {
+            map: Map {
+                asset: map_assets.add(map_asset),
+                ..default()
+            },
+            ..default()
+        })
+        .id()
 }
 
 pub(crate) fn handle_loaded_map_system(
     map_units: Res<MapUnits>,
+    build_settings: Res<MapBuildSettings>,
+    registry: Res<MapEntityRegistry>,
     mut commands: Commands,
     mut map_assets: ResMut<Assets<MapAsset>>,
     mut ev_asset: EventReader<AssetEvent<MapAsset>>,
-    mut q_maps: Query<Entity, With<Map>>,
+    mut q_maps: Query<(
+        Entity,
+        &mut Map,
+        Option<&MapScale>,
+        Option<&MapMirror>,
+        Has<MapRebuildPending>,
+    )>,
+    q_children: Query<&Children>,
+    mut despawning_event: EventWriter<MapDescendantDespawning>,
     mut post_build_event: EventWriter<PostBuildMapEvent>,
-    mut spawn_mesh_event: EventWriter<SpawnMeshEvent>,
+    #[cfg(feature = "render")] mut spawn_mesh_event: EventWriter<SpawnMeshEvent>,
+    mut degenerate_geometry_event: EventWriter<DegenerateGeometry>,
 ) {
+    let mut loaded = Vec::new();
     for ev in ev_asset.read() {
         match ev {
             AssetEvent::LoadedWithDependencies { id } => {
-                for map_entity in q_maps.iter_mut() {
-                    commands.entity(map_entity).despawn_descendants();
-                    let map_asset = map_assets.get_mut(*id).unwrap();
-                    crate::build::build_map(
-                        &map_units,
-                        map_entity,
-                        map_asset,
-                        &mut commands,
-                        &mut spawn_mesh_event,
-                        &mut post_build_event,
-                    );
-                }
+                loaded.push(*id);
             }
             _ => {}
         }
     }
+
+    for (map_entity, mut map, scale, mirror, rebuild_pending) in q_maps.iter_mut() {
+        // maps spawned after their asset finished loading (e.g. included
+        // maps) are built right away
+        let id = map.asset.id();
+        if !rebuild_pending && !loaded.contains(&id) && !(map.is_added() && map_assets.contains(id))
+        {
+            continue;
+        }
+        if !map_assets.contains(id) {
+            continue;
+        }
+
+        // rebuilt maps give games a frame to handle their old entities
+        if !rebuild_pending && q_children.get(map_entity).is_ok() {
+            for entity in q_children.iter_descendants(map_entity) {
+                despawning_event.send(MapDescendantDespawning {
+                    map: map_entity,
+                    entity,
+                });
+            }
+            commands.entity(map_entity).insert(MapRebuildPending);
+            continue;
+        }
+        let Some(map_asset) = map_assets.get_mut(id) else {
+            continue;
+        };
+
+        commands
+            .entity(map_entity)
+            .remove::<MapRebuildPending>()
+            .despawn_descendants();
+        match crate::build::build_map(
+            &map_units,
+            &build_settings,
+            map_entity,
+            map_asset,
+            &mut commands,
+            #[cfg(feature = "render")]
+            &mut spawn_mesh_event,
+            &mut post_build_event,
+            &mut degenerate_geometry_event,
+            &registry,
+            scale.map_or(1.0, |scale| scale.0),
+            mirror.copied(),
+        ) {
+            Ok(metadata) => map.metadata = metadata,
+            Err(e) => error!("map {:?} wasn't built: {}", map_entity, e),
+        }
+    }
 }
 
+/// Starts loading the maps referenced by `misc_external_map` entities, with
+/// either a `_external_map` or `file` property relative to the including map.
+/// Maps that include themselves, directly or through other maps, are skipped.
+fn load_external_maps(
+    map_asset: &mut MapAsset,
+    settings: &MapLoaderSettings,
+    load_context: &mut LoadContext,
+) {
+    let mut including_maps = settings.including_maps.clone();
+    including_maps.push(load_context.asset_path().to_string());
+
+    let entity_properties: Vec<BTreeMap<String, String>> =
+        match (&map_asset.geomap, &map_asset.processed) {
+            (Some(geomap), _) => geomap
+                .entity_properties
+                .values()
+                .map(|props| {
+                    props
+                        .iter()
+                        .map(|p| (p.key.clone(), p.value.clone()))
+                        .collect()
+                })
+                .collect(),
+            (None, Some(processed)) => processed
+                .compiled
+                .entities
+                .iter()
+                .map(|entity| entity.properties.clone())
+                .collect(),
+            (None, None) => Vec::new(),
+        };
+    for (entity_index, props) in entity_properties.iter().enumerate() {
+        if props.get("classname").map(String::as_str) != Some("misc_external_map") {
+            continue;
+        }
+        let Some(file) = props.get("_external_map").or(props.get("file")) else {
+            warn!("misc_external_map {} has no file", entity_index);
+            continue;
+        };
+
+        let path = match load_context.asset_path().resolve_embed(file) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!(
+                    "misc_external_map {} has an invalid file: {}",
+                    entity_index, e
+                );
+                continue;
+            }
+        };
+        if including_maps.contains(&path.to_string()) {
+            warn!(
+                "misc_external_map {} includes {}, which includes this map",
+                entity_index, path
+            );
+            continue;
+        }
+        let including_maps = including_maps.clone();
+        let handle = load_context
+            .load_with_settings::<MapAsset, MapLoaderSettings>(path, move |settings| {
+                settings.including_maps = including_maps.clone()
+            });
+        map_asset.external_maps.insert(entity_index, handle);
+    }
+}
+
+#[cfg(feature = "render")]
 pub(crate) async fn load_map_textures<'a>(
     map_asset: &mut MapAsset,
+    settings: &MapLoaderSettings,
     load_context: &mut LoadContext<'a>,
 ) {
-    let geomap = map_asset.geomap.as_mut().unwrap();
+    let quality = map_asset.material_quality;
+    let texture_names = match (&map_asset.geomap, &map_asset.processed) {
+        (Some(geomap), _) => geomap
+            .textures
+            .values()
+            .chain(
+                map_asset
+                    .brush_primitives
+                    .patches
+                    .iter()
+                    .map(|patch| &patch.texture),
+            )
+            .map(|texture_name| map_asset.interner.intern(texture_name))
+            .collect::<BTreeSet<_>>(),
+        (None, Some(processed)) => processed
+            .texture_names()
+            .into_iter()
+            .map(|texture_name| map_asset.interner.intern(texture_name))
+            .collect(),
+        (None, None) => BTreeSet::new(),
+    };
 
-    // for each texture, load it into the asset server
-    for texture_info in geomap.textures.iter() {
-        let texture_name = texture_info.1;
+    // files are read one at a time: `read_asset_bytes` takes the load context
+    // mutably, and it's the only way to record the files as loader
+    // dependencies, which hot reloading relies on. Decoding is the costly
+    // part, so the bytes are gathered first and every texture is decoded
+    // concurrently afterwards
+    let read_start = Instant::now();
+    let mut textures = Vec::new();
+    let mut files = Vec::new();
+    for texture_name in texture_names {
         let file = format!("textures/{}.png", texture_name);
+        let color = load_context.read_asset_bytes(file).await.ok();
+        if color.is_none() && settings.skip_auxiliary_without_base_color {
+            debug!(texture = %texture_name, "no base color texture");
+            continue;
+        }
 
-        let bytes = load_context.read_asset_bytes(&file).await;
-
-        if let Ok(bytes) = bytes {
-            let texture = Image::from_buffer(
-                &bytes,
-                ImageType::Extension("png"),
-                CompressedImageFormats::all(),
-                false,
-                ImageSampler::Descriptor(ImageSamplerDescriptor {
-                    address_mode_u: ImageAddressMode::Repeat,
-                    address_mode_v: ImageAddressMode::Repeat,
-                    ..default()
-                }),
-                RenderAssetUsages::RENDER_WORLD,
+        let definition_file = format!("textures/{}.ron", texture_name);
+        let definition = load_context
+            .read_asset_bytes(definition_file)
+            .await
+            .ok()
+            .and_then(
+                |bytes| match ron::de::from_bytes::<MaterialDefinition>(&bytes) {
+                    Ok(definition) => Some(definition),
+                    Err(e) => {
+                        warn!(texture = %texture_name, "invalid material definition: {}", e);
+                        None
+                    }
+                },
             );
+        let is_srgb = |slot: Option<&TextureSlot>| match (slot, definition.as_ref()) {
+            (None, Some(definition)) => definition.base_color_srgb.unwrap_or(false),
+            (None, None) => false,
+            (Some(slot), definition) => definition
+                .and_then(|definition| definition.srgb.get(slot).copied())
+                .unwrap_or(slot.is_srgb()),
+        };
 
-            if texture.is_ok() {
-                let texture = texture.unwrap();
-                let texture_handle = load_context.add_loaded_labeled_asset(
-                    format!("textures/{}", texture_name),
-                    LoadedAsset::from(texture.clone()),
-                );
-                let mat = StandardMaterial {
-                    base_color_texture: Some(texture_handle),
-                    perceptual_roughness: 0.55,
-                    metallic: 0.5,
-                    ..default()
-                };
-                let mat_handle = load_context.add_loaded_labeled_asset::<StandardMaterial>(
-                    format!("materials/{}", texture_name),
-                    LoadedAsset::from(mat),
-                );
-                map_asset
-                    .material_handles
-                    .insert(texture_name.clone(), mat_handle);
-                map_asset
-                    .texture_sizes
-                    .insert(texture_name.clone(), (texture.width(), texture.height()));
+        let mut auxiliary = Vec::new();
+        if quality.loads_auxiliary_textures() {
+            for (suffix, slot) in settings.texture_suffixes.iter() {
+                let file = format!("textures/{}{}.png", texture_name, suffix);
+                if let Ok(bytes) = load_context.read_asset_bytes(file).await {
+                    auxiliary.push((slot.clone(), bytes, is_srgb(Some(slot))));
+                }
             }
         }
+        if color.is_none() && auxiliary.is_empty() && definition.is_none() {
+            continue;
+        }
+        files.push(TextureFiles {
+            color: color.map(|bytes| (bytes, is_srgb(None))),
+            auxiliary,
+            sampler: settings.sampler.descriptor(&texture_name, quality),
+        });
+        textures.push((texture_name, definition));
+    }
+
+    debug!(elapsed = ?read_start.elapsed(), textures = files.len(), "read texture files");
+    let decode_start = Instant::now();
+    let decoded = decode_textures(files).await;
+    debug!(elapsed = ?decode_start.elapsed(), "decoded textures");
+
+    for ((texture_name, definition), (texture, auxiliary)) in textures.into_iter().zip(decoded) {
+        map_asset.texture_bytes += texture.as_ref().map_or(0, |texture| texture.data.len())
+            + auxiliary
+                .iter()
+                .map(|(_, image)| image.data.len())
+                .sum::<usize>();
+        let texture_handle = texture.as_ref().map(|texture| {
+            load_context.add_loaded_labeled_asset(
+                format!("textures/{}", texture_name),
+                LoadedAsset::from(texture.clone()),
+            )
+        });
+
+        let mut mat = StandardMaterial {
+            base_color_texture: texture_handle,
+            perceptual_roughness: 0.55,
+            metallic: 0.5,
+            ..default()
+        };
+
+        let mut slots = BTreeMap::new();
+        for (slot, image) in auxiliary {
+            let handle = load_context.add_loaded_labeled_asset(
+                format!("textures/{}/{}", texture_name, slot),
+                LoadedAsset::from(image),
+            );
+            slot.apply(&mut mat, &handle);
+            slots.insert(slot, handle);
+        }
+        if !slots.is_empty() {
+            map_asset
+                .auxiliary_textures
+                .insert(texture_name.clone(), slots);
+        }
+
+        if let Some(definition) = definition {
+            map_asset
+                .material_definitions
+                .insert(texture_name.clone(), definition);
+        }
+
+        let mat_handle = load_context.add_loaded_labeled_asset::<StandardMaterial>(
+            format!("materials/{}", texture_name),
+            LoadedAsset::from(mat),
+        );
+        map_asset
+            .material_handles
+            .insert(texture_name.clone(), mat_handle);
+        if let Some(texture) = texture {
+            map_asset
+                .texture_sizes
+                .insert(texture_name.clone(), (texture.width(), texture.height()));
+        }
     }
 }
+
+/// Base color and auxiliary texture bytes of a texture, with whether they're sRGB.
+#[cfg(feature = "render")]
+struct TextureFiles {
+    color: Option<(Vec<u8>, bool)>,
+    auxiliary: Vec<(TextureSlot, Vec<u8>, bool)>,
+    sampler: ImageSamplerDescriptor,
+}
+
+/// Decodes base color and auxiliary textures, in parallel on the async
+/// compute task pool when available.
+#[cfg(feature = "render")]
+async fn decode_textures(
+    files: Vec<TextureFiles>,
+) -> Vec<(Option<Image>, Vec<(TextureSlot, Image)>)> {
+    let decode = |files: TextureFiles| {
+        let sampler = files.sampler;
+        (
+            files
+                .color
+                .and_then(|(bytes, is_srgb)| decode_texture(&bytes, is_srgb, sampler.clone())),
+            files
+                .auxiliary
+                .into_iter()
+                .filter_map(|(slot, bytes, is_srgb)| {
+                    let image = decode_texture(&bytes, is_srgb, sampler.clone())?;
+                    Some((slot, image))
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    let Some(task_pool) = AsyncComputeTaskPool::try_get() else {
+        return files.into_iter().map(decode).collect();
+    };
+    let tasks = files
+        .into_iter()
+        .map(|file| task_pool.spawn(async move { decode(file) }))
+        .collect::<Vec<_>>();
+    let mut decoded = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        decoded.push(task.await);
+    }
+    decoded
+}
+
+/// Decodes a png texture, `None` if it's invalid.
+#[cfg(feature = "render")]
+fn decode_texture(bytes: &[u8], is_srgb: bool, sampler: ImageSamplerDescriptor) -> Option<Image> {
+    Image::from_buffer(
+        bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::all(),
+        is_srgb,
+        ImageSampler::Descriptor(sampler),
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .ok()
+}
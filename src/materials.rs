@@ -0,0 +1,294 @@
+//! Per-texture material definitions.
+//!
+//! Next to `textures/{name}.png`, a map texture can have a
+//! `textures/{name}.ron` definition file, e.g.
+//!
+//! ```ron
+//! (
+//!     parallax: Some((depth_scale: Some(0.08), method: Some(Occlusion))),
+//!     base_color_srgb: Some(true),
+//! )
+//! ```
+//!
+//! and auxiliary textures such as a `textures/{name}_depth.png` depth map used
+//! for parallax mapping, see
+//! [`crate::settings::MapLoaderSettings::texture_suffixes`]. Parallax
+//! can be tuned or turned off globally with [`ParallaxSettings`].
+
+#[cfg(feature = "render")]
+use bevy::pbr::ParallaxMappingMethod;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "render")]
+use crate::settings::MaterialQuality;
+#[cfg(feature = "render")]
+use crate::MapAsset;
+
+/// The material slot an auxiliary texture is used for.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TextureSlot {
+    /// parallax depth map, applied through [`ParallaxSettings`]
+    Depth,
+    Normal,
+    MetallicRoughness,
+    Emissive,
+    Occlusion,
+    /// packed occlusion (R), roughness (G) and metallic (B)
+    OcclusionRoughnessMetallic,
+    /// blended over the base color up close, see [`crate::detail`]
+    Detail,
+    /// not used by the built-in material, see [`MapAsset::auxiliary_texture`]
+    Custom(String),
+}
+
+impl TextureSlot {
+    pub fn is_srgb(&self) -> bool {
+        matches!(self, TextureSlot::Emissive)
+    }
+
+    /// Sets the texture on the matching [`StandardMaterial`] field.
+    #[cfg(feature = "render")]
+    pub fn apply(&self, material: &mut StandardMaterial, texture: &Handle<Image>) {
+        match self {
+            TextureSlot::Normal => material.normal_map_texture = Some(texture.clone()),
+            TextureSlot::MetallicRoughness => {
+                material.metallic_roughness_texture = Some(texture.clone());
+                material.metallic = 1.0;
+                material.perceptual_roughness = 1.0;
+            }
+            TextureSlot::Emissive => {
+                material.emissive_texture = Some(texture.clone());
+                material.emissive = Color::WHITE;
+            }
+            TextureSlot::Occlusion => material.occlusion_texture = Some(texture.clone()),
+            // bevy reads occlusion from R and roughness/metallic from G/B,
+            // so a packed texture can be bound to both slots as is
+            TextureSlot::OcclusionRoughnessMetallic => {
+                material.occlusion_texture = Some(texture.clone());
+                material.metallic_roughness_texture = Some(texture.clone());
+                material.metallic = 1.0;
+                material.perceptual_roughness = 1.0;
+            }
+            // depth maps are toggled by the parallax settings, detail
+            // textures need a `DetailMaterial`
+            TextureSlot::Depth | TextureSlot::Detail | TextureSlot::Custom(_) => {}
+        }
+    }
+}
+
+impl fmt::Display for TextureSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureSlot::Depth => write!(f, "depth"),
+            TextureSlot::Normal => write!(f, "normal"),
+            TextureSlot::MetallicRoughness => write!(f, "metallic_roughness"),
+            TextureSlot::Emissive => write!(f, "emissive"),
+            TextureSlot::Occlusion => write!(f, "occlusion"),
+            TextureSlot::OcclusionRoughnessMetallic => write!(f, "orm"),
+            TextureSlot::Detail => write!(f, "detail"),
+            TextureSlot::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct MaterialDefinition {
+    pub parallax: Option<ParallaxDefinition>,
+    #[cfg(feature = "render")]
+    pub detail: Option<crate::detail::DetailDefinition>,
+    #[cfg(feature = "render")]
+    pub scatter: Option<crate::scatter::ScatterDefinition>,
+    /// whether the base color texture is sRGB, by default it's loaded as linear
+    pub base_color_srgb: Option<bool>,
+    /// color space overrides of the auxiliary textures, e.g. `{ Emissive: false }`
+    pub srgb: BTreeMap<TextureSlot, bool>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ParallaxMethod {
+    Occlusion,
+    #[default]
+    Relief,
+}
+
+/// Parallax parameters, unset fields fall back to [`ParallaxSettings`].
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct ParallaxDefinition {
+    pub depth_scale: Option<f32>,
+    pub method: Option<ParallaxMethod>,
+    /// steps of the relief method
+    pub max_steps: Option<u32>,
+    pub max_layer_count: Option<f32>,
+}
+
+/// Global parallax mapping settings for map textures with a depth map.
+#[derive(Resource, Debug, Clone)]
+pub struct ParallaxSettings {
+    /// turn off to drop depth maps, e.g. on low-end targets
+    pub enabled: bool,
+    pub depth_scale: f32,
+    pub method: ParallaxMethod,
+    pub max_steps: u32,
+    pub max_layer_count: f32,
+    /// per-texture overrides, taking precedence over definition files
+    pub overrides: BTreeMap<String, ParallaxDefinition>,
+}
+
+impl Default for ParallaxSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            depth_scale: 0.04,
+            method: ParallaxMethod::Relief,
+            max_steps: 20,
+            max_layer_count: 16.0,
+            overrides: BTreeMap::new(),
+        }
+    }
+}
+
+impl ParallaxSettings {
+    #[cfg(feature = "render")]
+    fn apply(
+        &self,
+        texture_name: &str,
+        definition: Option<&ParallaxDefinition>,
+        quality: MaterialQuality,
+        material: &mut StandardMaterial,
+    ) {
+        let overrides = self.overrides.get(texture_name);
+        let pick = |get: &dyn Fn(&ParallaxDefinition) -> Option<f32>| {
+            overrides.and_then(get).or_else(|| definition.and_then(get))
+        };
+
+        material.parallax_depth_scale = pick(&|d| d.depth_scale).unwrap_or(self.depth_scale);
+        material.max_parallax_layer_count =
+            pick(&|d| d.max_layer_count).unwrap_or(self.max_layer_count);
+        let max_steps = overrides
+            .and_then(|d| d.max_steps)
+            .or_else(|| definition.and_then(|d| d.max_steps))
+            .unwrap_or(self.max_steps);
+        let method = overrides
+            .and_then(|d| d.method)
+            .or_else(|| definition.and_then(|d| d.method))
+            .unwrap_or(match quality {
+                MaterialQuality::High => self.method,
+                _ => ParallaxMethod::Occlusion,
+            });
+        material.parallax_mapping_method = match method {
+            ParallaxMethod::Occlusion => ParallaxMappingMethod::Occlusion,
+            ParallaxMethod::Relief => ParallaxMappingMethod::Relief { max_steps },
+        };
+    }
+}
+
+/// Replacement textures for [`MapAsset::replace_texture`].
+#[cfg(feature = "render")]
+#[derive(Debug, Default, Clone)]
+pub struct TextureHandles {
+    pub base_color: Option<Handle<Image>>,
+    pub auxiliary: BTreeMap<TextureSlot, Handle<Image>>,
+}
+
+/// Applies the textures replaced through [`MapAsset::replace_texture`] to
+/// the map's materials.
+#[cfg(feature = "render")]
+pub fn texture_swap_system(
+    mut map_assets: ResMut<Assets<MapAsset>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ev_asset: EventReader<AssetEvent<MapAsset>>,
+) {
+    let modified = ev_asset
+        .read()
+        .filter_map(|ev| match ev {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    for id in modified {
+        // only borrow mutably when needed, as that sends another event
+        if map_assets
+            .get(id)
+            .map_or(true, |map_asset| map_asset.pending_texture_swaps.is_empty())
+        {
+            continue;
+        }
+        let Some(map_asset) = map_assets.get_mut(id) else {
+            continue;
+        };
+        for (texture_name, handles) in std::mem::take(&mut map_asset.pending_texture_swaps) {
+            let Some(material) = map_asset
+                .material_handles
+                .get(&texture_name)
+                .and_then(|handle| materials.get_mut(handle))
+            else {
+                continue;
+            };
+            if let Some(base_color) = handles.base_color {
+                material.base_color_texture = Some(base_color);
+            }
+            for (slot, texture) in handles.auxiliary.iter() {
+                slot.apply(material, texture);
+            }
+        }
+    }
+}
+
+/// Applies [`ParallaxSettings`] to the materials of loaded maps, when either changes.
+#[cfg(feature = "render")]
+pub fn apply_parallax_settings_system(
+    settings: Res<ParallaxSettings>,
+    map_assets: Res<Assets<MapAsset>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ev_asset: EventReader<AssetEvent<MapAsset>>,
+) {
+    let loaded = ev_asset
+        .read()
+        .filter_map(|ev| match ev {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if loaded.is_empty() && !settings.is_changed() {
+        return;
+    }
+
+    for (id, map_asset) in map_assets.iter() {
+        if !settings.is_changed() && !loaded.contains(&id) {
+            continue;
+        }
+        for (texture_name, slots) in map_asset.auxiliary_textures.iter() {
+            let Some(depth_map) = slots.get(&TextureSlot::Depth) else {
+                continue;
+            };
+            let Some(material) = map_asset
+                .material_handles
+                .get(texture_name)
+                .and_then(|handle| materials.get_mut(handle))
+            else {
+                continue;
+            };
+            if !settings.enabled {
+                material.depth_map = None;
+                continue;
+            }
+            material.depth_map = Some(depth_map.clone());
+            let definition = map_asset
+                .material_definitions
+                .get(texture_name)
+                .and_then(|definition| definition.parallax.as_ref());
+            settings.apply(
+                texture_name,
+                definition,
+                map_asset.material_quality,
+                material,
+            );
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Top-down minimap images, computed from brush footprints.
+//!
+//! Walls are drawn as the outline of the vertical brush faces, triggers as
+//! translucent footprints and spawn points (`info_player_*`) as dots. Each
+//! layer can be turned off or recolored through [`MinimapSettings`].
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::core::{is_nodraw_texture, CompiledMap};
+
+#[derive(Debug, Clone)]
+pub struct MinimapLayer {
+    pub enabled: bool,
+    pub color: Color,
+}
+
+impl MinimapLayer {
+    fn new(color: Color) -> Self {
+        Self {
+            enabled: true,
+            color,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MinimapSettings {
+    /// size of the longest side of the image, in pixels
+    pub size: u32,
+    /// padding around the map bounds, in pixels
+    pub padding: u32,
+    /// only geometry within this vertical range (Bevy space) is drawn
+    pub height_range: Option<(f32, f32)>,
+    pub background: Color,
+    pub walls: MinimapLayer,
+    pub triggers: MinimapLayer,
+    pub spawn_points: MinimapLayer,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        Self {
+            size: 512,
+            padding: 8,
+            height_range: None,
+            background: Color::NONE,
+            walls: MinimapLayer::new(Color::WHITE),
+            triggers: MinimapLayer::new(Color::rgba(1.0, 0.6, 0.0, 0.35)),
+            spawn_points: MinimapLayer::new(Color::GREEN),
+        }
+    }
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    min: Vec2,
+    scale: f32,
+    padding: f32,
+}
+
+impl Canvas {
+    /// Maps a Bevy space point to pixel coordinates, looking down the Y axis.
+    fn to_pixel(&self, point: Vec3) -> Vec2 {
+        (Vec2::new(point.x, point.z) - self.min) * self.scale + Vec2::splat(self.padding)
+    }
+
+    fn blend(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let index = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let src = color.as_rgba_f32();
+        let alpha = src[3];
+        for channel in 0..3 {
+            let dst = self.data[index + channel] as f32 / 255.0;
+            let value = src[channel] * alpha + dst * (1.0 - alpha);
+            self.data[index + channel] = (value * 255.0).round() as u8;
+        }
+        let dst_alpha = self.data[index + 3] as f32 / 255.0;
+        self.data[index + 3] = ((alpha + dst_alpha * (1.0 - alpha)) * 255.0).round() as u8;
+    }
+
+    fn line(&mut self, from: Vec2, to: Vec2, color: Color) {
+        let steps = (to - from).abs().max_element().ceil().max(1.0) as i32;
+        for step in 0..=steps {
+            let point = from.lerp(to, step as f32 / steps as f32);
+            self.blend(point.x as i32, point.y as i32, color);
+        }
+    }
+
+    fn triangle(&mut self, a: Vec2, b: Vec2, c: Vec2, color: Color) {
+        let area = (b - a).perp_dot(c - a);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+        let min = a.min(b).min(c).floor();
+        let max = a.max(b).max(c).ceil();
+        for y in min.y as i32..max.y as i32 {
+            for x in min.x as i32..max.x as i32 {
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = (c - b).perp_dot(p - b) / area;
+                let w1 = (a - c).perp_dot(p - c) / area;
+                let w2 = 1.0 - w0 - w1;
+                if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+    fn dot(&mut self, center: Vec2, radius: f32, color: Color) {
+        let r = radius.ceil() as i32;
+        for y in -r..=r {
+            for x in -r..=r {
+                if ((x * x + y * y) as f32) <= radius * radius {
+                    self.blend(center.x as i32 + x, center.y as i32 + y, color);
+                }
+            }
+        }
+    }
+}
+
+/// Renders a top-down image of a compiled map. Returns `None` for maps without brushes.
+pub fn render_minimap(map: &CompiledMap, settings: &MinimapSettings) -> Option<Image> {
+    let in_range = |point: &Vec3| match settings.height_range {
+        Some((min, max)) => point.y >= min && point.y <= max,
+        None => true,
+    };
+
+    let mut min = Vec2::splat(f32::MAX);
+    let mut max = Vec2::splat(f32::MIN);
+    for vertex in map
+        .entities
+        .iter()
+        .flat_map(|entity| entity.brushes.iter())
+        .flat_map(|brush| brush.vertices.iter())
+        .filter(|vertex| in_range(vertex))
+    {
+        min = min.min(Vec2::new(vertex.x, vertex.z));
+        max = max.max(Vec2::new(vertex.x, vertex.z));
+    }
+    let extent = max - min;
+    if extent.cmple(Vec2::ZERO).any() {
+        return None;
+    }
+
+    let padding = settings.padding as f32;
+    let scale = (settings.size as f32 - padding * 2.0).max(1.0) / extent.max_element();
+    let size = (extent * scale).ceil().as_uvec2() + UVec2::splat(settings.padding * 2);
+    let background = settings.background.as_rgba_u8();
+    let mut canvas = Canvas {
+        width: size.x,
+        height: size.y,
+        data: background.repeat((size.x * size.y) as usize),
+        min,
+        scale,
+        padding,
+    };
+
+    for entity in map.entities.iter() {
+        let is_trigger = entity.classname.starts_with("trigger_");
+        let layer = if is_trigger {
+            &settings.triggers
+        } else {
+            &settings.walls
+        };
+        if !layer.enabled {
+            continue;
+        }
+
+        for face in entity.brushes.iter().flat_map(|brush| brush.faces.iter()) {
+            if !is_trigger && is_nodraw_texture(&face.texture) {
+                continue;
+            }
+            for triangle in face.indices.chunks_exact(3) {
+                let points = [0, 1, 2].map(|i| face.vertices[triangle[i] as usize]);
+                if !points.iter().any(|point| in_range(point)) {
+                    continue;
+                }
+                let [a, b, c] = points.map(|point| canvas.to_pixel(point));
+                if is_trigger {
+                    // faces of a convex brush overlap twice from above, only fill the upward ones
+                    if face.normals.first().map_or(false, |normal| normal.y > 0.0) {
+                        canvas.triangle(a, b, c, layer.color);
+                    }
+                } else if face
+                    .normals
+                    .first()
+                    .map_or(false, |normal| normal.y.abs() < 0.7)
+                {
+                    canvas.line(a, b, layer.color);
+                    canvas.line(b, c, layer.color);
+                    canvas.line(c, a, layer.color);
+                }
+            }
+        }
+    }
+
+    if settings.spawn_points.enabled {
+        for entity in map.entities.iter() {
+            if entity.classname.starts_with("info_player") && in_range(&entity.translation) {
+                let center = canvas.to_pixel(entity.translation);
+                canvas.dot(center, 3.0, settings.spawn_points.color);
+            }
+        }
+    }
+
+    Some(Image::new(
+        Extent3d {
+            width: canvas.width,
+            height: canvas.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        canvas.data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ))
+}
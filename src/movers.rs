@@ -0,0 +1,611 @@
+//! Built-in [`Mover`] motion.
+//!
+//! Movers start moving to their destination when their `targetname` is
+//! triggered, wait there for their `destination_time` and move back, unless
+//! they're a [`Door`] that opens once. Doors that need a `key` are left for
+//! the game to open, by setting their [`MoverState`]. [`MomentaryMover`]s
+//! aren't triggered, they follow the progress set by [`SetMoverProgress`].
+//!
+//! With [`MoverTimestep::Fixed`], positions are stepped in `FixedUpdate`
+//! ahead of the physics step, and each mover's [`MoverInterpolation`] smooths
+//! its rendered transform in between steps.
+//!
+//! Doors wait `wait` seconds (an alias of `destination_time`, negative to
+//! stay open) before closing, and reopen when a [`TriggerInstigator`] is in
+//! the way unless `reopen_on_block` is 0. Doors with an `open_radius` open
+//! on their own when an instigator comes that close, and stay open while
+//! it's around. See [`Door`].
+//!
+//! Linked doors, see [`door_groups`], move as one: opening any of them opens
+//! them all, the others following the state of the group's first door, and
+//! locking or unlocking one with a `key` does the same to the others.
+//!
+//! With a physics backend, [`MoverPassenger`]s and [`TriggerInstigator`]s
+//! standing on a mover, found with a short ray cast down, are moved along
+//! with it so lifts and platforms carry them instead of sliding away
+//! underneath. Passengers are expected to be top-level entities.
+//!
+//! Every mover's [`MoverSync`] mirrors its state for networking crates to
+//! replicate, and replicated changes are applied back before movers move.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::time::Duration;
+
+use crate::components::*;
+use crate::core::{CompiledEntity, CompiledMap};
+use crate::settings::MoverTimestep;
+
+pub fn activate_movers_system(
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut q_movers: Query<(&TriggerTarget, &mut Mover, Option<&Door>), Without<MomentaryMover>>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (trigger_target, mut mover, door) in q_movers.iter_mut() {
+            if trigger_target.target_name != trigger_event.target {
+                continue;
+            }
+            if door.is_some_and(|door| door.key.is_some()) {
+                continue;
+            }
+            start_mover(&mut mover);
+        }
+    }
+}
+
+/// Starts moving a mover to its destination, if it's at its start.
+pub(crate) fn start_mover(mover: &mut Mover) {
+    if let MoverState::AtStart = mover.state {
+        mover.state =
+            MoverState::MovingToDestination(Timer::new(mover.moving_time, TimerMode::Once));
+    }
+}
+
+/// Advances the state of a mover by `delta`.
+fn step_mover(mover: &mut Mover, open_once: bool, delta: Duration) {
+    let next = match &mut mover.state {
+        MoverState::AtStart => None,
+        MoverState::MovingToDestination(timer) => timer.tick(delta).finished().then(|| {
+            MoverState::AtDestination(Timer::new(mover.destination_time, TimerMode::Once))
+        }),
+        // doors that open once stay at their destination
+        MoverState::AtDestination(_) if open_once => None,
+        MoverState::AtDestination(timer) => timer
+            .tick(delta)
+            .finished()
+            .then(|| MoverState::MovingToStart(Timer::new(mover.moving_time, TimerMode::Once))),
+        MoverState::MovingToStart(timer) => {
+            timer.tick(delta).finished().then_some(MoverState::AtStart)
+        }
+    };
+    if let Some(next) = next {
+        mover.state = next;
+    }
+}
+
+pub fn set_mover_progress_system(
+    mut progress_events: EventReader<SetMoverProgress>,
+    mut q_movers: Query<&mut MomentaryMover>,
+) {
+    for ev in progress_events.read() {
+        if let Ok(mut momentary) = q_movers.get_mut(ev.mover) {
+            momentary.target_progress = ev.progress.clamp(0.0, 1.0);
+        }
+    }
+}
+
+/// Moves a momentary mover towards its target progress by `delta`, keeping
+/// its state in step so [`Mover::progress`] and [`MoverSync`] follow it.
+fn step_momentary_mover(mover: &mut Mover, target_progress: f32, delta: Duration) {
+    let progress = mover.progress();
+    let step = match mover.moving_time.is_zero() {
+        true => 1.0,
+        false => delta.as_secs_f32() / mover.moving_time.as_secs_f32(),
+    };
+    let next = if target_progress > progress {
+        (progress + step).min(target_progress)
+    } else {
+        (progress - step).max(target_progress)
+    };
+    let timer = |elapsed: f32| {
+        let mut timer = Timer::new(mover.moving_time, TimerMode::Once);
+        timer.set_elapsed(mover.moving_time.mul_f32(elapsed));
+        timer
+    };
+    let opening = match mover.state {
+        MoverState::MovingToStart(_) => next > progress,
+        _ => next >= progress,
+    };
+    mover.state = if next <= 0.0 {
+        MoverState::AtStart
+    } else if next >= 1.0 {
+        MoverState::AtDestination(Timer::new(mover.destination_time, TimerMode::Once))
+    } else if opening {
+        MoverState::MovingToDestination(timer(next))
+    } else {
+        MoverState::MovingToStart(timer(1.0 - next))
+    };
+}
+
+/// Moves movers between their start, from their [`MapEntityProperties`]
+/// transform, and their destination.
+pub fn mover_system(
+    time: Res<Time>,
+    mut q_movers: Query<(
+        &mut Mover,
+        &MapEntityProperties,
+        &mut Transform,
+        Option<&Door>,
+        Option<&MomentaryMover>,
+        Option<&mut MoverInterpolation>,
+    )>,
+) {
+    for (mut mover, props, mut transform, door, momentary, interpolation) in q_movers.iter_mut() {
+        match momentary {
+            Some(momentary) => {
+                step_momentary_mover(&mut mover, momentary.target_progress, time.delta())
+            }
+            None => step_mover(
+                &mut mover,
+                door.is_some_and(|door| door.open_once),
+                time.delta(),
+            ),
+        }
+        let translation = props.transform.translation + mover.destination_offset * mover.progress();
+        if let Some(mut interpolation) = interpolation {
+            interpolation.previous = interpolation.current;
+            interpolation.current = translation;
+        }
+        transform.translation = translation;
+    }
+}
+
+/// Applies replicated [`MoverSync`] changes that don't match the mover's
+/// own state, e.g. received from a server.
+pub fn apply_mover_sync_system(mut q_movers: Query<(&mut Mover, &MoverSync), Changed<MoverSync>>) {
+    for (mut mover, sync) in q_movers.iter_mut() {
+        if MoverSync::from_mover(&mover) != *sync {
+            mover.state = sync.to_state(&mover);
+        }
+    }
+}
+
+pub(crate) fn is_door(entity: &CompiledEntity) -> bool {
+    entity.classname == "mover"
+        && entity
+            .properties
+            .get("mover_kind")
+            .map(|kind| kind.as_str())
+            == Some("door")
+}
+
+/// Groups the doors of a map that are linked: doors with the same `team` or
+/// `linked` property, and doors touching each other, unless either has the
+/// [`DOOR_DONT_LINK`] spawnflag. Maps the index of each linked door to the
+/// index of the first door of its group.
+pub fn door_groups(map: &CompiledMap) -> HashMap<usize, usize> {
+    let doors = map
+        .entities
+        .iter()
+        .filter(|entity| is_door(entity))
+        .map(|entity| {
+            let property = |key: &str| {
+                entity
+                    .properties
+                    .get(key)
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+            };
+            let spawnflags = property("spawnflags")
+                .and_then(|flags| flags.parse::<i32>().ok())
+                .unwrap_or(0);
+            let touches = spawnflags & DOOR_DONT_LINK == 0;
+            let bounds = entity
+                .brushes
+                .iter()
+                .flat_map(|brush| brush.vertices.iter())
+                .fold(None, |bounds: Option<(Vec3, Vec3)>, vertex| {
+                    Some(bounds.map_or((*vertex, *vertex), |(min, max)| {
+                        (min.min(*vertex), max.max(*vertex))
+                    }))
+                })
+                .filter(|_| touches);
+            (entity.index, property("team"), property("linked"), bounds)
+        })
+        .collect::<Vec<_>>();
+
+    // union-find over the doors, each group rooted at its first door
+    let mut roots = (0..doors.len()).collect::<Vec<_>>();
+    fn root(roots: &mut [usize], mut i: usize) -> usize {
+        while roots[i] != i {
+            roots[i] = roots[roots[i]];
+            i = roots[i];
+        }
+        i
+    }
+    const TOUCH_DISTANCE: f32 = 0.001;
+    for a in 0..doors.len() {
+        for b in a + 1..doors.len() {
+            let (_, team_a, linked_a, bounds_a) = doors[a];
+            let (_, team_b, linked_b, bounds_b) = doors[b];
+            let same_team = team_a.is_some() && team_a == team_b;
+            let same_link = linked_a.is_some() && linked_a == linked_b;
+            let touching = match (bounds_a, bounds_b) {
+                (Some((min_a, max_a)), Some((min_b, max_b))) => {
+                    (min_a - TOUCH_DISTANCE).cmple(max_b).all()
+                        && (min_b - TOUCH_DISTANCE).cmple(max_a).all()
+                }
+                _ => false,
+            };
+            if same_team || same_link || touching {
+                let (root_a, root_b) = (root(&mut roots, a), root(&mut roots, b));
+                roots[root_a.max(root_b)] = root_a.min(root_b);
+            }
+        }
+    }
+
+    let mut sizes = HashMap::new();
+    for i in 0..doors.len() {
+        *sizes.entry(root(&mut roots, i)).or_insert(0) += 1;
+    }
+    (0..doors.len())
+        .filter_map(|i| {
+            let root = root(&mut roots, i);
+            (sizes[&root] > 1).then(|| (doors[i].0, doors[root].0))
+        })
+        .collect()
+}
+
+/// Keeps the doors of each [`DoorGroup`] in the same state and lock.
+pub fn link_doors_system(mut q_doors: Query<(Entity, &DoorGroup, &mut Mover, &mut Door)>) {
+    let mut groups: HashMap<(Entity, usize), Vec<Entity>> = HashMap::new();
+    for (entity, group, ..) in q_doors.iter() {
+        groups
+            .entry((group.map, group.id))
+            .or_default()
+            .push(entity);
+    }
+
+    for members in groups.values() {
+        // a door locked or unlocked since the last run locks or unlocks the
+        // others, locked doors winning when they're spawned
+        let mut key = None;
+        let mut iter = q_doors.iter_many_mut(members);
+        while let Some((.., door)) = iter.fetch_next() {
+            if door.is_changed() && key.as_ref().map_or(true, Option::is_none) {
+                key = Some(door.key.clone());
+            }
+        }
+        if let Some(key) = key {
+            let mut iter = q_doors.iter_many_mut(members);
+            while let Some((.., mut door)) = iter.fetch_next() {
+                if door.key != key {
+                    door.key = key.clone();
+                }
+            }
+        }
+
+        // the leader follows a door opened on its own, e.g. when triggered
+        // or reopened when blocked
+        let Some(leader) = q_doors
+            .iter_many(members)
+            .find(|(_, group, ..)| group.leader)
+            .map(|(entity, ..)| entity)
+        else {
+            continue;
+        };
+        let Ok((.., leader_mover, _)) = q_doors.get(leader) else {
+            continue;
+        };
+        let mut sync = MoverSync::from_mover(&leader_mover);
+        if matches!(sync.phase, MoverPhase::AtStart | MoverPhase::MovingToStart) {
+            if let Some(opened) = q_doors
+                .iter_many(members)
+                .map(|(_, _, mover, _)| MoverSync::from_mover(&mover))
+                .find(|member| member.phase == MoverPhase::MovingToDestination)
+            {
+                sync = opened;
+            }
+        }
+        let mut iter = q_doors.iter_many_mut(members);
+        while let Some((_, _, mut mover, _)) = iter.fetch_next() {
+            let current = MoverSync::from_mover(&mover);
+            if current.phase != sync.phase || (current.elapsed - sync.elapsed).abs() > 1e-4 {
+                mover.state = sync.to_state(&mover);
+            }
+        }
+    }
+}
+
+/// Opens proximity doors, holds them open while instigators are around, and
+/// reopens blocked doors.
+pub fn door_system(
+    mut q_doors: Query<(&mut Mover, &Door, &GlobalTransform, Option<&BrushVolume>)>,
+    q_instigators: Query<&GlobalTransform, With<TriggerInstigator>>,
+) {
+    for (mut mover, door, transform, volume) in q_doors.iter_mut() {
+        if let Some(radius) = door.open_radius.filter(|_| door.key.is_none()) {
+            let near = q_instigators.iter().any(|instigator| {
+                instigator
+                    .translation()
+                    .distance_squared(transform.translation())
+                    <= radius * radius
+            });
+            if near {
+                match &mut mover.state {
+                    MoverState::AtStart => start_mover(&mut mover),
+                    MoverState::AtDestination(timer) => timer.reset(),
+                    _ => {}
+                }
+            }
+        }
+
+        let MoverState::MovingToStart(timer) = &mover.state else {
+            continue;
+        };
+        let blocked = door.reopen_on_block
+            && volume.is_some_and(|volume| {
+                q_instigators.iter().any(|instigator| {
+                    volume.contains_world_within(
+                        transform,
+                        instigator.translation(),
+                        door.block_margin,
+                    )
+                })
+            });
+        if blocked {
+            // move back from where it is
+            let mut reopening = Timer::new(mover.moving_time, TimerMode::Once);
+            reopening.set_elapsed(mover.moving_time.saturating_sub(timer.elapsed()));
+            mover.state = MoverState::MovingToDestination(reopening);
+        }
+    }
+}
+
+/// World space movement of each mover since the last call, keeping track
+/// of their translations in `previous`.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn mover_deltas(
+    previous: &mut HashMap<Entity, Vec3>,
+    q_movers: &Query<(Entity, &Mover, &MapEntityProperties, Option<&Parent>)>,
+    q_global_transforms: &Query<&GlobalTransform>,
+) -> HashMap<Entity, Vec3> {
+    let mut deltas = HashMap::new();
+    let mut current = HashMap::new();
+    for (entity, mover, props, parent) in q_movers.iter() {
+        // rather than the transform, which may be interpolated
+        let translation = props.transform.translation + mover.destination_offset * mover.progress();
+        current.insert(entity, translation);
+        let Some(last) = previous.get(&entity) else {
+            continue;
+        };
+        let mut delta = translation - *last;
+        if let Some(parent) = parent.and_then(|p| q_global_transforms.get(p.get()).ok()) {
+            delta = parent.affine().transform_vector3(delta);
+        }
+        if delta != Vec3::ZERO {
+            deltas.insert(entity, delta);
+        }
+    }
+    *previous = current;
+    deltas
+}
+
+/// The moving mover a ray cast down from a passenger hit, either itself or
+/// through one of its colliders.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+fn carrier(
+    deltas: &HashMap<Entity, Vec3>,
+    q_parents: &Query<&Parent>,
+    hit: Entity,
+) -> Option<Entity> {
+    std::iter::once(hit)
+        .chain(q_parents.get(hit).map(|parent| parent.get()))
+        .find(|entity| deltas.contains_key(entity))
+}
+
+#[cfg(feature = "rapier")]
+#[cfg(not(feature = "xpbd"))]
+pub fn rapier_carry_passengers_system(
+    rapier_context: Res<bevy_rapier3d::prelude::RapierContext>,
+    mut previous: Local<HashMap<Entity, Vec3>>,
+    q_movers: Query<(Entity, &Mover, &MapEntityProperties, Option<&Parent>)>,
+    q_global_transforms: Query<&GlobalTransform>,
+    q_parents: Query<&Parent>,
+    mut q_passengers: Query<
+        (Entity, &mut Transform, Option<&MoverPassenger>),
+        (
+            Or<(With<MoverPassenger>, With<TriggerInstigator>)>,
+            Without<Mover>,
+        ),
+    >,
+) {
+    use bevy_rapier3d::prelude::QueryFilter;
+
+    let deltas = mover_deltas(&mut previous, &q_movers, &q_global_transforms);
+    if deltas.is_empty() {
+        return;
+    }
+    for (entity, mut transform, passenger) in q_passengers.iter_mut() {
+        let ground_distance = passenger.copied().unwrap_or_default().ground_distance;
+        let Some((hit, _)) = rapier_context.cast_ray(
+            transform.translation,
+            Vec3::NEG_Y,
+            ground_distance,
+            true,
+            QueryFilter::new()
+                .exclude_collider(entity)
+                .exclude_sensors(),
+        ) else {
+            continue;
+        };
+        if let Some(mover) = carrier(&deltas, &q_parents, hit) {
+            transform.translation += deltas[&mover];
+        }
+    }
+}
+
+#[cfg(feature = "xpbd")]
+pub fn xpbd_carry_passengers_system(
+    spatial_query: bevy_xpbd_3d::prelude::SpatialQuery,
+    mut previous: Local<HashMap<Entity, Vec3>>,
+    q_movers: Query<(Entity, &Mover, &MapEntityProperties, Option<&Parent>)>,
+    q_global_transforms: Query<&GlobalTransform>,
+    q_parents: Query<&Parent>,
+    mut q_passengers: Query<
+        (Entity, &mut Transform, Option<&MoverPassenger>),
+        (
+            Or<(With<MoverPassenger>, With<TriggerInstigator>)>,
+            Without<Mover>,
+        ),
+    >,
+) {
+    use bevy_xpbd_3d::prelude::SpatialQueryFilter;
+
+    let deltas = mover_deltas(&mut previous, &q_movers, &q_global_transforms);
+    if deltas.is_empty() {
+        return;
+    }
+    for (entity, mut transform, passenger) in q_passengers.iter_mut() {
+        let ground_distance = passenger.copied().unwrap_or_default().ground_distance;
+        let Some(hit) = spatial_query.cast_ray(
+            transform.translation,
+            Direction3d::NEG_Y,
+            ground_distance,
+            true,
+            SpatialQueryFilter {
+                excluded_entities: [entity].into_iter().collect(),
+                ..default()
+            },
+        ) else {
+            continue;
+        };
+        if let Some(mover) = carrier(&deltas, &q_parents, hit.entity) {
+            transform.translation += deltas[&mover];
+        }
+    }
+}
+
+/// Mirrors the state of movers into their [`MoverSync`].
+pub fn sync_mover_state_system(mut q_movers: Query<(&Mover, &mut MoverSync)>) {
+    for (mover, mut sync) in q_movers.iter_mut() {
+        sync.set_if_neq(MoverSync::from_mover(mover));
+    }
+}
+
+/// Gives movers a [`MoverInterpolation`] when they're stepped in `FixedUpdate`.
+pub fn insert_mover_interpolation_system(
+    mut commands: Commands,
+    q_movers: Query<(Entity, &Transform), (With<Mover>, Without<MoverInterpolation>)>,
+) {
+    for (entity, transform) in q_movers.iter() {
+        commands.entity(entity).insert(MoverInterpolation {
+            previous: transform.translation,
+            current: transform.translation,
+        });
+    }
+}
+
+/// Places movers between their last two fixed timestep positions.
+pub fn interpolate_movers_system(
+    fixed_time: Res<Time<Fixed>>,
+    mut q_movers: Query<(&MoverInterpolation, &mut Transform)>,
+) {
+    let t = fixed_time.overstep_fraction();
+    for (interpolation, mut transform) in q_movers.iter_mut() {
+        transform.translation = interpolation.previous.lerp(interpolation.current, t);
+    }
+}
+
+/// Adds the mover systems to the schedule of `timestep`.
+pub(crate) fn add_mover_systems(app: &mut App, timestep: MoverTimestep) {
+    use crate::plugins::QevySet;
+
+    app.add_systems(
+        Update,
+        (activate_movers_system, set_mover_progress_system).in_set(QevySet::Gameplay),
+    );
+    match timestep {
+        MoverTimestep::Variable => {
+            app.add_systems(
+                Update,
+                (
+                    apply_mover_sync_system,
+                    door_system,
+                    link_doors_system,
+                    mover_system,
+                    sync_mover_state_system,
+                )
+                    .chain()
+                    .after(activate_movers_system)
+                    .after(set_mover_progress_system)
+                    .in_set(QevySet::Gameplay),
+            );
+        }
+        MoverTimestep::Fixed => {
+            let fixed_mover_system = (
+                insert_mover_interpolation_system,
+                apply_mover_sync_system,
+                door_system,
+                link_doors_system,
+                mover_system,
+                sync_mover_state_system,
+            )
+                .chain()
+                .in_set(QevySet::Gameplay);
+
+            // only takes effect with the backend in `FixedUpdate`, see
+            // `MoverTimestep::Fixed`
+            #[cfg(feature = "xpbd")]
+            let fixed_mover_system =
+                fixed_mover_system.before(bevy_xpbd_3d::prelude::PhysicsSet::Prepare);
+
+            #[cfg(feature = "rapier")]
+            #[cfg(not(feature = "xpbd"))]
+            let fixed_mover_system =
+                fixed_mover_system.before(bevy_rapier3d::prelude::PhysicsSet::SyncBackend);
+
+            app.add_systems(FixedUpdate, fixed_mover_system);
+            app.add_systems(
+                Update,
+                interpolate_movers_system
+                    .after(activate_movers_system)
+                    .in_set(QevySet::Gameplay),
+            );
+        }
+    }
+
+    #[cfg(feature = "xpbd")]
+    let carry_passengers_system = xpbd_carry_passengers_system
+        .after(mover_system)
+        .before(sync_mover_state_system);
+
+    #[cfg(feature = "rapier")]
+    #[cfg(not(feature = "xpbd"))]
+    let carry_passengers_system = rapier_carry_passengers_system
+        .after(mover_system)
+        .before(sync_mover_state_system);
+
+    #[cfg(any(feature = "xpbd", feature = "rapier"))]
+    match timestep {
+        // `Update` always runs before the backend steps in `PostUpdate`
+        MoverTimestep::Variable => {
+            app.add_systems(Update, carry_passengers_system.in_set(QevySet::Gameplay));
+        }
+        MoverTimestep::Fixed => {
+            #[cfg(feature = "xpbd")]
+            let carry_passengers_system =
+                carry_passengers_system.before(bevy_xpbd_3d::prelude::PhysicsSet::Prepare);
+
+            #[cfg(feature = "rapier")]
+            #[cfg(not(feature = "xpbd"))]
+            let carry_passengers_system =
+                carry_passengers_system.before(bevy_rapier3d::prelude::PhysicsSet::SyncBackend);
+
+            app.add_systems(
+                FixedUpdate,
+                carry_passengers_system.in_set(QevySet::Gameplay),
+            );
+        }
+    }
+}
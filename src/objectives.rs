@@ -0,0 +1,153 @@
+//! Mission objectives driven by map data.
+//!
+//! `info_objective` point entities describe an objective with `title`,
+//! `description` and `order` properties. An objective is completed when it's
+//! targeted through its `targetname`, usually by a `trigger_objective_complete`
+//! volume. Progress is tracked in the [`MapObjectives`] resource and reported
+//! with [`ObjectiveEvent`]s.
+
+use bevy::prelude::*;
+
+use crate::components::{MapEntityProperties, TriggerTarget, TriggeredEvent};
+
+#[derive(Component, Debug, Clone, Default)]
+pub struct Objective {
+    pub title: String,
+    pub description: String,
+    pub order: i32,
+    pub completed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectiveEntry {
+    pub entity: Entity,
+    pub title: String,
+    pub description: String,
+    pub order: i32,
+    pub completed: bool,
+}
+
+/// All objectives of the loaded maps, sorted by their `order`.
+#[derive(Resource, Debug, Default)]
+pub struct MapObjectives {
+    pub objectives: Vec<ObjectiveEntry>,
+}
+
+impl MapObjectives {
+    /// The first objective that isn't completed yet.
+    pub fn current(&self) -> Option<&ObjectiveEntry> {
+        self.objectives
+            .iter()
+            .find(|objective| !objective.completed)
+    }
+
+    pub fn completed_count(&self) -> usize {
+        self.objectives
+            .iter()
+            .filter(|objective| objective.completed)
+            .count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        !self.objectives.is_empty() && self.current().is_none()
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectiveEvent {
+    /// the objective became the current one
+    Activated(Entity),
+    Completed(Entity),
+    /// every objective has been completed
+    AllCompleted,
+}
+
+/// Inserts the [`Objective`] component for an `info_objective` entity.
+pub fn apply_objective_properties(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    commands.entity(entity).insert(Objective {
+        title: props
+            .get_property_as_string("title", None)
+            .unwrap_or_default(),
+        description: props
+            .get_property_as_string("description", None)
+            .unwrap_or_default(),
+        order: props.get_property_as_i32("order", 0),
+        completed: false,
+    });
+}
+
+pub fn register_objectives_system(
+    mut objectives: ResMut<MapObjectives>,
+    mut objective_events: EventWriter<ObjectiveEvent>,
+    q_added: Query<(Entity, &Objective), Added<Objective>>,
+    mut removed: RemovedComponents<Objective>,
+) {
+    let previous = objectives.current().map(|objective| objective.entity);
+    let mut changed = false;
+
+    for entity in removed.read() {
+        objectives
+            .objectives
+            .retain(|objective| objective.entity != entity);
+        changed = true;
+    }
+
+    for (entity, objective) in q_added.iter() {
+        objectives
+            .objectives
+            .retain(|objective| objective.entity != entity);
+        objectives.objectives.push(ObjectiveEntry {
+            entity,
+            title: objective.title.clone(),
+            description: objective.description.clone(),
+            order: objective.order,
+            completed: objective.completed,
+        });
+        changed = true;
+    }
+
+    if changed {
+        objectives
+            .objectives
+            .sort_by_key(|objective| objective.order);
+        let current = objectives.current().map(|objective| objective.entity);
+        if let Some(current) = current.filter(|current| Some(*current) != previous) {
+            objective_events.send(ObjectiveEvent::Activated(current));
+        }
+    }
+}
+
+pub fn complete_objectives_system(
+    mut objectives: ResMut<MapObjectives>,
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut objective_events: EventWriter<ObjectiveEvent>,
+    mut q_objectives: Query<(Entity, &TriggerTarget, &mut Objective)>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (entity, trigger_target, mut objective) in q_objectives.iter_mut() {
+            if objective.completed || trigger_target.target_name != trigger_event.target {
+                continue;
+            }
+            objective.completed = true;
+            objective_events.send(ObjectiveEvent::Completed(entity));
+
+            let Some(entry) = objectives
+                .objectives
+                .iter_mut()
+                .find(|objective| objective.entity == entity)
+            else {
+                continue;
+            };
+            entry.completed = true;
+
+            match objectives.current() {
+                Some(current) => objective_events.send(ObjectiveEvent::Activated(current.entity)),
+                None => objective_events.send(ObjectiveEvent::AllCompleted),
+            };
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Brush face picking and retexturing, for in-game level editing tools.
+//!
+//! Brush entities keep the geometry of their faces in a [`BrushFaces`]
+//! component, which [`FacePicker::pick_face`] casts rays against. Send a
+//! [`RetextureFaceEvent`] to change the texture of a picked face.
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::render::mesh::Indices;
+#[cfg(feature = "render")]
+use bevy::render::render_asset::RenderAssetUsages;
+#[cfg(feature = "render")]
+use bevy::render::render_resource::PrimitiveTopology;
+
+#[cfg(feature = "render")]
+use crate::components::{Brush, Map};
+use crate::core::{is_nodraw_texture, CompiledEntity};
+use crate::intern::{InternedStr, Interner};
+#[cfg(feature = "render")]
+use crate::MapAsset;
+
+/// The faces of a brush entity, relative to its transform.
+#[derive(Component, Debug, Default, Clone)]
+pub struct BrushFaces {
+    pub faces: Vec<BrushFace>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BrushFace {
+    /// index of the brush in the .map file
+    pub brush_index: usize,
+    /// index of the face in the .map file
+    pub face_index: usize,
+    pub texture: InternedStr,
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<u32>,
+}
+
+impl BrushFaces {
+    /// Collects the drawable faces of a compiled brush entity, relative to `origin`.
+    pub fn from_compiled(entity: &CompiledEntity, origin: Vec3, interner: &mut Interner) -> Self {
+        let faces = entity
+            .brushes
+            .iter()
+            .flat_map(|brush| brush.faces.iter().map(move |face| (brush.index, face)))
+            .filter(|(_, face)| !is_nodraw_texture(&face.texture))
+            .map(|(brush_index, face)| BrushFace {
+                brush_index,
+                face_index: face.index,
+                texture: interner.intern(&face.texture),
+                vertices: face
+                    .vertices
+                    .iter()
+                    .map(|vertex| *vertex - origin)
+                    .collect(),
+                normals: face.normals.clone(),
+                uvs: face.uvs.clone(),
+                indices: face.indices.clone(),
+            })
+            .collect();
+        Self { faces }
+    }
+
+    /// Builds a mesh out of the faces with these indices in the .map file.
+    #[cfg(feature = "render")]
+    pub fn mesh(&self, face_indices: &[usize]) -> Mesh {
+        let mut vertices = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+        for face in self
+            .faces
+            .iter()
+            .filter(|face| face_indices.contains(&face.face_index))
+        {
+            let base = vertices.len() as u32;
+            vertices.extend_from_slice(&face.vertices);
+            normals.extend_from_slice(&face.normals);
+            if face.uvs.len() == face.vertices.len() {
+                uvs.extend_from_slice(&face.uvs);
+            } else {
+                uvs.resize(vertices.len(), Vec2::ZERO);
+            }
+            indices.extend(face.indices.iter().map(|index| index + base));
+        }
+
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_indices(Indices::U32(indices));
+        if let Err(e) = mesh.generate_tangents() {
+            warn!(faces = ?face_indices, "error generating tangents: {:?}", e);
+        }
+        mesh
+    }
+
+    /// The closest face hit by a ray in local space, with its distance along the ray.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<(usize, f32, Vec2)> {
+        let mut closest: Option<(usize, f32, Vec2)> = None;
+        for (face_index, face) in self.faces.iter().enumerate() {
+            for triangle in face.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+                let Some((t, u, v)) = intersect_triangle(
+                    origin,
+                    direction,
+                    [face.vertices[a], face.vertices[b], face.vertices[c]],
+                ) else {
+                    continue;
+                };
+                if closest.map_or(false, |(_, closest_t, _)| closest_t <= t) {
+                    continue;
+                }
+                let uv = match (face.uvs.get(a), face.uvs.get(b), face.uvs.get(c)) {
+                    (Some(uv_a), Some(uv_b), Some(uv_c)) => {
+                        *uv_a * (1.0 - u - v) + *uv_b * u + *uv_c * v
+                    }
+                    _ => Vec2::ZERO,
+                };
+                closest = Some((face_index, t, uv));
+            }
+        }
+        closest
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection, returning the distance along
+/// the ray and the barycentric coordinates of the hit.
+fn intersect_triangle(
+    origin: Vec3,
+    direction: Vec3,
+    [a, b, c]: [Vec3; 3],
+) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = origin - a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(q) * inverse;
+    (t > 0.0).then_some((t, u, v))
+}
+
+/// A brush face hit by [`FacePicker::pick_face`].
+#[derive(Debug, Clone)]
+pub struct FaceHit {
+    pub brush_entity: Entity,
+    /// index of the brush in the .map file
+    pub brush_index: usize,
+    /// index of the face in the .map file
+    pub face_index: usize,
+    pub texture_name: InternedStr,
+    /// texture coordinates at the hit point
+    pub uv: Vec2,
+    pub point: Vec3,
+    pub distance: f32,
+}
+
+/// Casts rays against the faces of every brush entity.
+#[derive(SystemParam)]
+pub struct FacePicker<'w, 's> {
+    q_brushes: Query<'w, 's, (Entity, &'static BrushFaces, &'static GlobalTransform)>,
+}
+
+impl<'w, 's> FacePicker<'w, 's> {
+    /// The closest brush face along `ray`, in world space.
+    pub fn pick_face(&self, ray: Ray3d) -> Option<FaceHit> {
+        self.pick_face_where(ray, |_| true)
+    }
+
+    /// The closest face along `ray` of the brush entities `filter` accepts,
+    /// e.g. to trace a line of sight through trigger brushes.
+    pub fn pick_face_where(&self, ray: Ray3d, filter: impl Fn(Entity) -> bool) -> Option<FaceHit> {
+        let mut closest: Option<FaceHit> = None;
+        for (entity, brush_faces, transform) in self.q_brushes.iter() {
+            if !filter(entity) {
+                continue;
+            }
+            let to_local = transform.affine().inverse();
+            let origin = to_local.transform_point3(ray.origin);
+            let direction = to_local.transform_vector3(*ray.direction);
+            let Some((index, t, uv)) = brush_faces.raycast(origin, direction) else {
+                continue;
+            };
+            let point = transform.transform_point(origin + direction * t);
+            let distance = ray.origin.distance(point);
+            if closest
+                .as_ref()
+                .map_or(false, |hit| hit.distance <= distance)
+            {
+                continue;
+            }
+            let face = &brush_faces.faces[index];
+            closest = Some(FaceHit {
+                brush_entity: entity,
+                brush_index: face.brush_index,
+                face_index: face.face_index,
+                texture_name: face.texture.clone(),
+                uv,
+                point,
+                distance,
+            });
+        }
+        closest
+    }
+}
+
+/// The brush faces a mesh draws, by index in the .map file.
+#[derive(Component, Debug, Clone)]
+pub struct BrushFaceMesh {
+    pub brush_entity: Entity,
+    pub faces: Vec<usize>,
+}
+
+/// Changes the texture of a face of a spawned brush entity, e.g. one hit by
+/// [`FacePicker::pick_face`]. The change is recorded in the map asset, see
+/// [`crate::MapAsset::retexture_face`].
+#[derive(Event, Debug, Clone)]
+pub struct RetextureFaceEvent {
+    pub brush_entity: Entity,
+    /// index of the face in the .map file
+    pub face_index: usize,
+    pub texture_name: String,
+}
+
+/// Applies [`RetextureFaceEvent`]s. A mesh drawing several faces is split,
+/// so the retextured face gets a mesh of its own.
+#[cfg(feature = "render")]
+pub fn retexture_face_system(
+    mut commands: Commands,
+    mut events: EventReader<RetextureFaceEvent>,
+    mut map_assets: ResMut<Assets<MapAsset>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_maps: Query<&Map>,
+    q_parents: Query<&Parent>,
+    mut q_brush_faces: Query<&mut BrushFaces>,
+    mut q_face_meshes: Query<(
+        &mut BrushFaceMesh,
+        &Handle<Mesh>,
+        &mut Handle<StandardMaterial>,
+        Option<&mut Brush>,
+        Option<&mut Name>,
+        &Parent,
+    )>,
+) {
+    for ev in events.read() {
+        let Ok(mut brush_faces) = q_brush_faces.get_mut(ev.brush_entity) else {
+            continue;
+        };
+        let Some(face) = brush_faces
+            .faces
+            .iter_mut()
+            .find(|face| face.face_index == ev.face_index)
+        else {
+            continue;
+        };
+        let Some(map_asset) = q_parents
+            .iter_ancestors(ev.brush_entity)
+            .find_map(|ancestor| q_maps.get(ancestor).ok())
+            .and_then(|map| map_assets.get_mut(&map.asset))
+        else {
+            continue;
+        };
+        let Some(material) = map_asset.material_handles.get(ev.texture_name.as_str()) else {
+            warn!(
+                "can't retexture face {}, no material for {}",
+                ev.face_index, ev.texture_name
+            );
+            continue;
+        };
+        let material = material.clone();
+        // the face's UVs were computed against the size of its original texture
+        let texture_size = map_asset
+            .texture_sizes
+            .get(&face.texture)
+            .map_or(UVec2::ONE, |(w, h)| UVec2::new(*w, *h));
+        map_asset.retexture_face(ev.face_index, &ev.texture_name);
+        face.texture = map_asset.interner.intern(&ev.texture_name);
+
+        let Some((mut face_mesh, mesh, mut mesh_material, brush, name, parent)) =
+            q_face_meshes.iter_mut().find(|(face_mesh, ..)| {
+                face_mesh.brush_entity == ev.brush_entity
+                    && face_mesh.faces.contains(&ev.face_index)
+            })
+        else {
+            continue;
+        };
+        if face_mesh.faces.len() == 1 {
+            // brush UVs get rescaled to the new texture by `rescale_brush_uvs_system`
+            *mesh_material = material;
+            if let Some(mut name) = name {
+                *name = crate::build::mesh_name(&face_mesh.faces, &ev.texture_name);
+            }
+            continue;
+        }
+
+        face_mesh
+            .faces
+            .retain(|face_index| *face_index != ev.face_index);
+        // inserted rather than edited in place, which also works for meshes
+        // that were only kept in the render world
+        meshes.insert(mesh.id(), brush_faces.mesh(&face_mesh.faces));
+        if let Some(mut brush) = brush {
+            brush.texture_size = texture_size;
+            brush.authored_texture_size = texture_size;
+        }
+
+        let split_mesh = commands
+            .spawn((
+                PbrBundle {
+                    mesh: meshes.add(brush_faces.mesh(&[ev.face_index])),
+                    material,
+                    ..default()
+                },
+                Brush {
+                    texture_size,
+                    authored_texture_size: texture_size,
+                },
+                crate::build::mesh_name(&[ev.face_index], &ev.texture_name),
+                BrushFaceMesh {
+                    brush_entity: ev.brush_entity,
+                    faces: vec![ev.face_index],
+                },
+            ))
+            .id();
+        commands.entity(parent.get()).add_child(split_mesh);
+    }
+}
@@ -0,0 +1,436 @@
+//! The plugins making up [`QevyPlugins`].
+//!
+//! Each plugin can be disabled on its own to supply your own systems, e.g.
+//! `QevyPlugins::default().build().disable::<MapGameplayPlugin>()` keeps the
+//! loader and meshing but leaves movers, triggers and the other gameplay
+//! entities to the game. [`crate::MapAssetLoaderPlugin`] is required by all of
+//! the others.
+
+use bevy::app::PluginGroupBuilder;
+use bevy::prelude::*;
+
+use crate::components::MapUnits;
+use crate::settings::{MapBuildSettings, MoverTimestep};
+use crate::{
+    build, camera_paths, colliders, components, cutscenes, damage, diagnostics, event_log,
+    gameplay_systems, hazards, interaction, issue_log, items, leak, liquids, load, materials,
+    movers, objectives, picking, registry, spawners, speakers, surfaces, timers,
+    MapAssetLoaderPlugin, PostBuildMapEvent, ReapplyEntityPropertiesEvent,
+};
+#[cfg(feature = "render")]
+use crate::{fog, props, scatter};
+
+/// The phases of building a map, for ordering your own systems around them.
+/// The first three run in `PreUpdate` and the last two in `Update`, each
+/// after the one before it.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QevySet {
+    /// Before loaded maps are built, e.g. to edit a [`crate::MapAsset`] or
+    /// the [`crate::registry::MapEntityRegistry`] first
+    Parse,
+    /// Entities and colliders of loaded maps are spawned
+    BuildGeometry,
+    /// Brush meshes are spawned
+    SpawnMeshes,
+    /// Built-in components are inserted from entity properties
+    PostBuild,
+    /// Triggers, movers and the other gameplay entities run, unless
+    /// [`QevyGameplayState::Paused`]
+    Gameplay,
+}
+
+/// Whether map gameplay runs. Pausing freezes triggers, movers, camera
+/// paths, cutscenes, item animations and every other system in
+/// [`QevySet::Gameplay`], including your own.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QevyGameplayState {
+    #[default]
+    Run,
+    Paused,
+}
+
+/// Every qevy plugin, configured from a single place.
+#[derive(Default)]
+pub struct QevyPlugins {
+    /// If true, maps get colliders but no meshes or materials. Always the
+    /// case without the `render` feature.
+    pub headless: bool,
+    pub units: MapUnits,
+    pub build_settings: MapBuildSettings,
+    pub mover_timestep: MoverTimestep,
+}
+
+impl PluginGroup for QevyPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let headless = self.headless || !cfg!(feature = "render");
+        let group = PluginGroupBuilder::start::<Self>()
+            .add(MapAssetLoaderPlugin {
+                headless,
+                units: self.units,
+                build_settings: self.build_settings,
+            })
+            .add(MapBuilderPlugin { headless })
+            .add(MapMaterialsPlugin)
+            .add(MapPhysicsPlugin)
+            .add(MapGameplayPlugin {
+                headless,
+                mover_timestep: self.mover_timestep,
+            })
+            .add(MapAutoConfigPlugin)
+            .add(MapDebugPlugin { headless })
+            .add(diagnostics::MapDiagnosticsPlugin);
+        if headless {
+            group.disable::<MapMaterialsPlugin>()
+        } else {
+            group
+        }
+    }
+}
+
+/// Spawns the entities, meshes and colliders of loaded maps, and rebuilds
+/// them when their asset changes.
+#[derive(Default)]
+pub struct MapBuilderPlugin {
+    /// If true, only colliders are spawned
+    pub headless: bool,
+}
+
+impl Plugin for MapBuilderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<components::MapDescendantDespawning>()
+            .add_event::<PostBuildMapEvent>()
+            .add_event::<picking::RetextureFaceEvent>();
+        #[cfg(feature = "render")]
+        app.add_event::<build::SpawnMeshEvent>();
+
+        app.add_systems(
+            PreUpdate,
+            load::handle_loaded_map_system.in_set(QevySet::BuildGeometry),
+        );
+        app.add_systems(
+            Update,
+            issue_log::write_issue_log_system.in_set(QevySet::PostBuild),
+        );
+        #[cfg(feature = "render")]
+        if !self.headless {
+            app.add_systems(
+                PreUpdate,
+                build::mesh_spawn_system.in_set(QevySet::SpawnMeshes),
+            );
+            app.add_systems(
+                Update,
+                picking::retexture_face_system.in_set(QevySet::Gameplay),
+            );
+            app.add_systems(Update, scatter::scatter_system.in_set(QevySet::PostBuild));
+            app.add_systems(
+                Update,
+                (
+                    props::spawn_static_props_system,
+                    props::bake_static_props_system,
+                )
+                    .chain()
+                    .in_set(QevySet::PostBuild),
+            );
+            app.add_systems(
+                PostUpdate,
+                (
+                    build::rescale_brush_uvs_system,
+                    build::world_locked_uvs_system,
+                )
+                    .chain()
+                    .after(bevy::transform::TransformSystem::TransformPropagate),
+            );
+        }
+    }
+}
+
+/// Texture swaps and parallax settings of map materials.
+#[derive(Default)]
+pub struct MapMaterialsPlugin;
+
+impl Plugin for MapMaterialsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<materials::ParallaxSettings>();
+        #[cfg(feature = "render")]
+        app.add_systems(
+            Update,
+            (
+                materials::texture_swap_system,
+                materials::apply_parallax_settings_system,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Deferred collider generation and the trigger systems of the enabled
+/// physics backend.
+#[derive(Default)]
+pub struct MapPhysicsPlugin;
+
+impl Plugin for MapPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<components::ColliderSource>()
+            .init_resource::<components::TriggerAuthority>()
+            .add_event::<components::WouldFireTrigger>()
+            .add_event::<components::TriggerEntered>()
+            .add_event::<components::TriggerExited>()
+            .add_event::<components::ConfirmTriggerEvent>()
+            .add_event::<interaction::UsedEvent>()
+            .add_systems(
+                Update,
+                (
+                    gameplay_systems::confirm_triggers_system,
+                    interaction::use_triggers_system.after(interaction::use_system),
+                )
+                    .in_set(QevySet::Gameplay),
+            );
+
+        app.add_systems(
+            PreUpdate,
+            colliders::deferred_collider_system
+                .in_set(QevySet::BuildGeometry)
+                .after(load::handle_loaded_map_system),
+        );
+
+        #[cfg(any(feature = "xpbd", feature = "rapier"))]
+        app.add_systems(Update, liquids::track_liquid_bodies_system);
+
+        #[cfg(feature = "xpbd")]
+        app.add_systems(
+            Update,
+            (
+                gameplay_systems::xpbd_trigger_system,
+                (
+                    gameplay_systems::xpbd_trigger_occupancy_system,
+                    gameplay_systems::trigger_pressure_system,
+                    gameplay_systems::trigger_look_system,
+                )
+                    .chain(),
+                liquids::xpbd_liquid_current_system.after(liquids::in_liquid_system),
+            )
+                .in_set(QevySet::Gameplay),
+        );
+
+        #[cfg(feature = "rapier")]
+        #[cfg(not(feature = "xpbd"))]
+        app.add_systems(
+            Update,
+            (
+                gameplay_systems::rapier_trigger_system,
+                (
+                    gameplay_systems::rapier_trigger_occupancy_system,
+                    gameplay_systems::trigger_pressure_system,
+                    gameplay_systems::trigger_look_system,
+                )
+                    .chain(),
+                liquids::rapier_liquid_current_system.after(liquids::in_liquid_system),
+            )
+                .in_set(QevySet::Gameplay),
+        );
+    }
+}
+
+/// Movers, use-key interaction, toggleable brushes, damageable entities, speakers, fog volumes, liquids, secrets, items, objectives,
+/// spawners, camera paths and cutscenes.
+#[derive(Default)]
+pub struct MapGameplayPlugin {
+    /// If true, item visuals aren't spawned
+    pub headless: bool,
+    pub mover_timestep: MoverTimestep,
+}
+
+impl Plugin for MapGameplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<components::MoverSync>()
+            .register_type::<components::MoverPhase>()
+            .register_type::<components::Toggleable>()
+            .register_type::<components::TriggeredOnce>()
+            .register_type::<damage::MapDamageable>()
+            .add_event::<components::SetMoverProgress>()
+            .add_event::<damage::DamageEvent>()
+            .add_event::<damage::DeathEvent>()
+            .add_event::<components::SecretFoundEvent>()
+            .init_resource::<components::SecretsFound>()
+            .add_event::<items::PickupEvent>()
+            .add_event::<objectives::ObjectiveEvent>()
+            .init_resource::<objectives::MapObjectives>()
+            .add_event::<spawners::SpawnRequested>()
+            .init_resource::<spawners::SpawnerRegistry>()
+            .add_event::<speakers::SpeakerEvent>()
+            .init_resource::<surfaces::SurfaceTable>()
+            .add_event::<hazards::HazardDamageEvent>()
+            .add_event::<camera_paths::PlayCameraPathEvent>()
+            .add_event::<camera_paths::CameraPathFinishedEvent>()
+            .add_event::<cutscenes::CutsceneRequested>()
+            .add_systems(
+                Update,
+                (
+                    gameplay_systems::toggle_brush_entities_system,
+                    gameplay_systems::apply_brush_toggle_system,
+                )
+                    .chain()
+                    .in_set(QevySet::Gameplay),
+            )
+            .add_systems(
+                Update,
+                gameplay_systems::secret_found_system.in_set(QevySet::Gameplay),
+            )
+            .add_systems(Update, damage::damage_system.in_set(QevySet::Gameplay))
+            .add_systems(
+                Update,
+                (items::item_pickup_system, items::item_respawn_system).in_set(QevySet::Gameplay),
+            )
+            .add_systems(
+                Update,
+                (
+                    objectives::register_objectives_system,
+                    objectives::complete_objectives_system,
+                )
+                    .chain()
+                    .in_set(QevySet::Gameplay),
+            )
+            .add_systems(
+                Update,
+                (spawners::activate_spawners_system, spawners::spawner_system)
+                    .chain()
+                    .in_set(QevySet::Gameplay),
+            )
+            .add_systems(
+                Update,
+                (
+                    camera_paths::play_camera_path_system,
+                    camera_paths::camera_path_player_system,
+                )
+                    .chain()
+                    .in_set(QevySet::Gameplay),
+            )
+            .add_systems(
+                Update,
+                (
+                    cutscenes::cutscene_trigger_system,
+                    cutscenes::activate_sequences_system,
+                    cutscenes::sequence_playback_system,
+                )
+                    .chain()
+                    .in_set(QevySet::Gameplay),
+            );
+
+        movers::add_mover_systems(app, self.mover_timestep);
+
+        app.add_event::<interaction::UseEvent>()
+            .add_event::<interaction::UsedEvent>()
+            .add_systems(
+                Update,
+                (interaction::use_system, interaction::use_movers_system)
+                    .chain()
+                    .in_set(QevySet::Gameplay),
+            );
+        app.add_systems(Update, speakers::speaker_system.in_set(QevySet::Gameplay));
+        app.add_systems(
+            Update,
+            (timers::toggle_timers_system, timers::timer_system)
+                .chain()
+                .in_set(QevySet::Gameplay),
+        );
+        app.add_systems(Update, hazards::hazard_system.in_set(QevySet::Gameplay));
+        app.add_systems(Update, liquids::in_liquid_system.in_set(QevySet::Gameplay));
+        #[cfg(feature = "render")]
+        {
+            app.add_event::<fog::FogVolumeEntered>()
+                .add_event::<fog::FogVolumeExited>()
+                .init_resource::<liquids::LiquidEffects>()
+                .add_event::<liquids::CameraEnteredLiquid>()
+                .add_event::<liquids::CameraExitedLiquid>()
+                .add_systems(
+                    Update,
+                    (
+                        gameplay_systems::toggle_texture_frames_system,
+                        fog::fog_volume_system,
+                        liquids::liquid_camera_system,
+                    )
+                        .in_set(QevySet::Gameplay),
+                );
+        }
+        #[cfg(feature = "audio")]
+        if !self.headless {
+            app.add_systems(
+                Update,
+                speakers::play_speaker_sounds_system
+                    .after(speakers::speaker_system)
+                    .in_set(QevySet::Gameplay),
+            );
+        }
+
+        #[cfg(feature = "render")]
+        if !self.headless {
+            app.add_systems(
+                Update,
+                (
+                    items::spawn_item_visuals_system,
+                    items::animate_item_visuals_system,
+                )
+                    .in_set(QevySet::Gameplay),
+            );
+        }
+    }
+}
+
+/// The built-in property → component mapping of lights, movers and the
+/// other entities handled by [`build::apply_entity_properties`], followed by
+/// the bundles registered with [`registry::RegisterClassnameBundle`].
+#[derive(Default)]
+pub struct MapAutoConfigPlugin;
+
+impl Plugin for MapAutoConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReapplyEntityPropertiesEvent>().add_systems(
+            Update,
+            (
+                build::post_build_map_system,
+                build::reapply_entity_properties_system,
+                registry::insert_classname_bundles_system.after(build::post_build_map_system),
+            )
+                .in_set(QevySet::PostBuild),
+        );
+    }
+}
+
+/// Loads .pts point files and draws their [`leak::LeakLine`]s, and records
+/// the [`event_log::MapEventLog`].
+#[derive(Default)]
+pub struct MapDebugPlugin {
+    /// If true, leak lines aren't drawn
+    pub headless: bool,
+}
+
+impl Plugin for MapDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<leak::PointFile>()
+            .init_asset_loader::<leak::PointFileLoader>();
+
+        // the logged events are registered by other plugins, which may be
+        // left out
+        app.add_event::<components::TriggeredEvent>()
+            .add_event::<components::TriggerEntered>()
+            .add_event::<components::TriggerExited>()
+            .add_event::<components::WouldFireTrigger>()
+            .init_resource::<event_log::MapEventLog>()
+            .add_systems(
+                Update,
+                (
+                    event_log::record_trigger_events_system,
+                    event_log::record_mover_phases_system,
+                )
+                    .after(QevySet::Gameplay),
+            );
+
+        #[cfg(feature = "render")]
+        {
+            app.add_systems(Update, leak::point_file_leak_line_system);
+            if !self.headless {
+                app.add_systems(Update, leak::draw_leak_lines_system);
+            }
+        }
+    }
+}
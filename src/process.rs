@@ -0,0 +1,87 @@
+//! Map processing for Bevy's `AssetMode::Processed`.
+//!
+//! [`MapProcessor`] is the default processor of .map files. It parses and
+//! compiles maps when assets are processed, so games load the resulting
+//! [`ProcessedMap`] with [`ProcessedMapLoader`] instead of parsing and
+//! meshing every brush at load time. Textures are still loaded at runtime.
+
+use bevy::asset::io::{Reader, Writer};
+use bevy::asset::meta::{AssetAction, AssetMeta};
+use bevy::asset::processor::{Process, ProcessContext, ProcessError};
+use bevy::asset::{AssetLoader, AsyncWriteExt, BoxedFuture, LoadContext};
+use serde::{Deserialize, Serialize};
+
+use crate::components::MapUnits;
+use crate::core::ProcessedMap;
+use crate::settings::MapLoaderSettings;
+use crate::{load, MapAsset, MapAssetLoaderError};
+
+/// Settings of [`MapProcessor`], in the `.meta` file of each map.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MapProcessorSettings {
+    /// units maps are compiled with, processed maps are rescaled when loaded
+    /// with other [`MapUnits`]
+    pub units: MapUnits,
+    /// settings of the [`ProcessedMapLoader`] loading the processed map
+    pub loader: MapLoaderSettings,
+}
+
+/// Compiles .map files into [`ProcessedMap`]s.
+pub struct MapProcessor;
+
+impl Process for MapProcessor {
+    type Settings = MapProcessorSettings;
+    type OutputLoader = ProcessedMapLoader;
+
+    fn process<'a>(
+        &'a self,
+        context: &'a mut ProcessContext,
+        meta: AssetMeta<(), Self>,
+        writer: &'a mut Writer,
+    ) -> BoxedFuture<'a, Result<MapLoaderSettings, ProcessError>> {
+        Box::pin(async move {
+            let AssetAction::Process { settings, .. } = meta.asset else {
+                return Err(ProcessError::WrongMetaType);
+            };
+            let processed = ProcessedMap::new(
+                context.asset_bytes(),
+                settings.units,
+                settings.loader.geometry_tolerance.clone(),
+            )
+            .map_err(|e| ProcessError::AssetTransformError(e.into()))?;
+            let bytes = ron::ser::to_string(&processed)
+                .map_err(|e| ProcessError::AssetSaveError(e.into()))?;
+            writer
+                .write_all(bytes.as_bytes())
+                .await
+                .map_err(|e| ProcessError::AssetSaveError(e.into()))?;
+            Ok(settings.loader)
+        })
+    }
+}
+
+/// Loads maps written by [`MapProcessor`].
+#[derive(Default)]
+pub struct ProcessedMapLoader {
+    /// If true, the textures of the map aren't loaded
+    pub headless: bool,
+}
+
+impl AssetLoader for ProcessedMapLoader {
+    type Asset = MapAsset;
+    type Settings = MapLoaderSettings;
+    type Error = MapAssetLoaderError;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        load::load_processed(reader, settings, load_context, self.headless)
+    }
+
+    // picked through the processed .meta files, never by extension
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+}
@@ -0,0 +1,287 @@
+//! `prop_static` point entities, decorative models that never move.
+//!
+//! A prop draws its glTF `model`, either a file (`models/crate.glb`) or one
+//! of its meshes (`models/crate.glb#Mesh1`). By default the model's scene is
+//! spawned under the prop.
+//!
+//! Props with `_bake 1`, or every prop when
+//! [`MapBuildSettings::bake_static_props`] is set, are baked instead: the
+//! triangles of their mesh (the first one of the file unless labeled) are
+//! merged with those of the map's other baked props into one mesh per
+//! material, parented to the map's [`WorldGeometry`]. A room full of clutter
+//! then costs one entity and draw call per material. Baked props keep their
+//! entity, without visuals, and are listed in the [`BakedStaticProps`] of
+//! the meshes they were merged into. Meshes that can't be merged, e.g.
+//! skinned ones or ones that aren't triangle lists, fall back to the scene.
+
+use bevy::gltf::GltfMesh;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::utils::HashMap;
+
+use crate::components::{Map, MapEntityProperties, WorldGeometry};
+use crate::intern::InternedStr;
+use crate::settings::MapBuildSettings;
+
+#[derive(Component, Debug, Clone)]
+pub struct StaticProp {
+    /// path of the glTF file, optionally labeled with the mesh to draw
+    pub model: InternedStr,
+    /// merge the mesh into the map's baked props rather than spawning the scene
+    pub bake: bool,
+}
+
+impl StaticProp {
+    fn scene_path(&self) -> String {
+        let file = self.model.split('#').next().unwrap_or_default();
+        format!("{}#Scene0", file)
+    }
+
+    fn mesh_path(&self) -> String {
+        match self.model.contains('#') {
+            true => self.model.to_string(),
+            false => format!("{}#Mesh0", self.model),
+        }
+    }
+}
+
+/// A mesh of baked [`StaticProp`]s sharing a material.
+#[derive(Component, Debug, Clone)]
+pub struct BakedStaticProps {
+    /// the prop entities merged into the mesh
+    pub props: Vec<Entity>,
+}
+
+/// The mesh of a prop waiting to be baked.
+#[derive(Component, Debug, Clone)]
+pub struct PendingPropBake(pub Handle<GltfMesh>);
+
+/// Inserts the [`StaticProp`] component for a `prop_static` entity.
+pub fn apply_static_prop_properties(
+    build_settings: &MapBuildSettings,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let Some(model) = props.properties.get("model").cloned() else {
+        warn!("prop_static {:?} has no model", entity);
+        return;
+    };
+    commands.entity(entity).insert((
+        StaticProp {
+            model,
+            bake: props.get_property_as_bool("_bake", build_settings.bake_static_props),
+        },
+        SpatialBundle::from_transform(props.transform),
+    ));
+}
+
+/// Spawns the scene of new props, or queues them for baking.
+pub fn spawn_static_props_system(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    q_props: Query<(Entity, &StaticProp), Added<StaticProp>>,
+) {
+    for (entity, prop) in q_props.iter() {
+        if prop.bake {
+            commands
+                .entity(entity)
+                .insert(PendingPropBake(asset_server.load(prop.mesh_path())));
+        } else {
+            spawn_prop_scene(&asset_server, &mut commands, entity, prop);
+        }
+    }
+}
+
+fn spawn_prop_scene(
+    asset_server: &AssetServer,
+    commands: &mut Commands,
+    entity: Entity,
+    prop: &StaticProp,
+) {
+    commands.entity(entity).with_children(|children| {
+        children.spawn((
+            SceneBundle {
+                scene: asset_server.load(prop.scene_path()),
+                ..default()
+            },
+            Name::new(format!("prop model ({})", prop.model)),
+        ));
+    });
+}
+
+/// Vertices of the baked props of a material, in the space of the map.
+#[derive(Default)]
+struct BakedMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+    props: Vec<Entity>,
+}
+
+impl BakedMesh {
+    /// Whether a mesh is a static triangle list with positions and normals.
+    fn can_merge(mesh: &Mesh) -> bool {
+        mesh.primitive_topology() == PrimitiveTopology::TriangleList
+            && mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX).is_none()
+            && matches!(
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+                Some(VertexAttributeValues::Float32x3(_))
+            )
+            && matches!(
+                mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+                Some(VertexAttributeValues::Float32x3(_))
+            )
+    }
+
+    fn append(&mut self, mesh: &Mesh, transform: &Transform, prop: Entity) {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+        )
+        else {
+            return;
+        };
+        let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uvs)) => Some(uvs),
+            _ => None,
+        };
+
+        let matrix = transform.compute_matrix();
+        let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+        let start = self.positions.len() as u32;
+        self.positions.extend(
+            positions
+                .iter()
+                .map(|position| matrix.transform_point3(Vec3::from(*position)).to_array()),
+        );
+        self.normals.extend(normals.iter().map(|normal| {
+            (normal_matrix * Vec3::from(*normal))
+                .normalize_or_zero()
+                .to_array()
+        }));
+        match uvs {
+            Some(uvs) => self.uvs.extend(uvs.iter().copied()),
+            None => self.uvs.extend(positions.iter().map(|_| [0.0, 0.0])),
+        }
+        match mesh.indices() {
+            Some(indices) => self
+                .indices
+                .extend(indices.iter().map(|index| start + index as u32)),
+            None => self
+                .indices
+                .extend((0..positions.len() as u32).map(|index| start + index)),
+        }
+        if self.props.last() != Some(&prop) {
+            self.props.push(prop);
+        }
+    }
+
+    fn into_mesh(self) -> (Mesh, Vec<Entity>) {
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_indices(Indices::U32(self.indices));
+        if let Err(e) = mesh.generate_tangents() {
+            warn!("error generating tangents of baked props: {:?}", e);
+        }
+        (mesh, self.props)
+    }
+}
+
+/// Merges the meshes of the props queued for baking, one map at a time once
+/// all of its props are loaded.
+pub fn bake_static_props_system(
+    asset_server: Res<AssetServer>,
+    gltf_meshes: Res<Assets<GltfMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut commands: Commands,
+    q_pending: Query<(Entity, &StaticProp, &PendingPropBake, &Transform)>,
+    q_parents: Query<&Parent>,
+    q_maps: Query<(), With<Map>>,
+    q_world_geometry: Query<(Entity, &Parent), With<WorldGeometry>>,
+) {
+    let mut pending_by_map: HashMap<Entity, Vec<_>> = HashMap::new();
+    for pending in q_pending.iter() {
+        let Some(map) = q_parents
+            .iter_ancestors(pending.0)
+            .find(|ancestor| q_maps.contains(*ancestor))
+        else {
+            continue;
+        };
+        pending_by_map.entry(map).or_default().push(pending);
+    }
+
+    for (map, pending) in pending_by_map {
+        let mut ready = true;
+        for (entity, prop, PendingPropBake(handle), _) in pending.iter() {
+            if gltf_meshes.contains(handle) {
+                continue;
+            }
+            if asset_server.load_state(handle) == bevy::asset::LoadState::Failed {
+                warn!(model = %prop.model, "failed to load the mesh of prop_static {:?}", entity);
+                commands.entity(*entity).remove::<PendingPropBake>();
+            }
+            ready = false;
+        }
+        if !ready {
+            continue;
+        }
+
+        let mut baked: HashMap<Handle<StandardMaterial>, BakedMesh> = HashMap::new();
+        for (entity, prop, PendingPropBake(handle), transform) in pending {
+            commands.entity(entity).remove::<PendingPropBake>();
+            let Some(gltf_mesh) = gltf_meshes.get(handle) else {
+                continue;
+            };
+            let mergeable = gltf_mesh.primitives.iter().all(|primitive| {
+                meshes
+                    .get(&primitive.mesh)
+                    .is_some_and(BakedMesh::can_merge)
+            });
+            if !mergeable {
+                debug!(model = %prop.model, "prop can't be baked, spawning its scene");
+                spawn_prop_scene(&asset_server, &mut commands, entity, prop);
+                continue;
+            }
+            for primitive in gltf_mesh.primitives.iter() {
+                let Some(mesh) = meshes.get(&primitive.mesh) else {
+                    continue;
+                };
+                baked
+                    .entry(primitive.material.clone().unwrap_or_default())
+                    .or_default()
+                    .append(mesh, transform, entity);
+            }
+        }
+
+        let parent = q_world_geometry
+            .iter()
+            .find(|(_, parent)| parent.get() == map)
+            .map_or(map, |(world_geometry, _)| world_geometry);
+        for (material, baked_mesh) in baked {
+            let (mesh, props) = baked_mesh.into_mesh();
+            debug!(props = props.len(), "baked static props");
+            commands
+                .spawn((
+                    PbrBundle {
+                        mesh: meshes.add(mesh),
+                        material,
+                        ..default()
+                    },
+                    BakedStaticProps { props },
+                    Name::new("baked props"),
+                ))
+                .set_parent(parent);
+        }
+    }
+}
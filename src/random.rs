@@ -0,0 +1,27 @@
+//! Deterministic randomness.
+//!
+//! Every map has a [`crate::components::MapSeed`], from its worldspawn
+//! `_seed` property or [`crate::settings::MapBuildSettings::seed`], and each
+//! of its entities a seed derived from it and the entity's index in the .map
+//! file, see [`crate::components::MapEntityProperties::seed`]. Randomized
+//! features start from these seeds so replays and networked clients see the
+//! same results.
+
+/// A random number in `0..1`, advancing `state` (splitmix64).
+pub fn next_random(state: &mut u64) -> f32 {
+    (next_u64(state) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn next_u64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// The seed of the entity at `entity_index` in a map seeded with `map_seed`.
+pub fn entity_seed(map_seed: u64, entity_index: usize) -> u64 {
+    let mut state = map_seed ^ (entity_index as u64).wrapping_mul(0x2545f4914f6cdd1d);
+    next_u64(&mut state)
+}
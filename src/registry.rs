@@ -0,0 +1,263 @@
+//! Classname aliases, property defaults and bundles applied when a map is
+//! built.
+//!
+//! An alias makes an entity handled as another classname, with preset
+//! property defaults, e.g. Quake's `light_fluoro` is a `light`. Classnames
+//! can also declare defaults for properties maps leave out, so handlers
+//! don't each need their own fallbacks. Register both on the
+//! [`MapEntityRegistry`] resource:
+//!
+//! ```ignore
+//! app.world
+//!     .resource_mut::<MapEntityRegistry>()
+//!     .register_alias("light_lava", "light", &[("light", "400"), ("_color", "1 0.5 0")])
+//!     .register_defaults("func_door", &[("speed", "100"), ("wait", "3")]);
+//! ```
+//!
+//! Games can also have a [`Bundle`] built from the properties of every entity
+//! of a classname and inserted for them, instead of matching classnames in
+//! their own post-build systems:
+//!
+//! ```ignore
+//! impl FromMapProps for GruntBundle {
+//!     fn from_map_props(props: &MapEntityProperties) -> Self {
+//!         Self {
+//!             health: Health(props.get_property_as_f32("health", 50.0)),
+//!             ..default()
+//!         }
+//!     }
+//! }
+//!
+//! app.register_classname_bundle::<GruntBundle>("monster_grunt");
+//! ```
+//!
+//! Bundles are inserted after the built-in components, in
+//! [`crate::QevySet::PostBuild`], so they're there for systems running after
+//! it.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::collections::BTreeMap;
+
+use crate::components::MapEntityProperties;
+use crate::core::CompiledEntity;
+use crate::fgd::{EntityDefinition, EntityDefinitions};
+use crate::intern::InternedStr;
+use crate::settings::MapBuildMode;
+use crate::PostBuildMapEvent;
+
+/// A bundle built from the properties of a map entity, see
+/// [`RegisterClassnameBundle`].
+pub trait FromMapProps {
+    fn from_map_props(props: &MapEntityProperties) -> Self;
+}
+
+/// Inserts a bundle registered for a classname on one of its entities.
+pub type BundleInserter = fn(&mut Commands, Entity, &MapEntityProperties);
+
+fn insert_bundle<B: Bundle + FromMapProps>(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    commands.entity(entity).insert(B::from_map_props(props));
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassnameAlias {
+    /// the classname the alias is handled as
+    pub classname: InternedStr,
+    /// properties set on entities that don't have them
+    pub defaults: Vec<(String, String)>,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct MapEntityRegistry {
+    pub aliases: HashMap<InternedStr, ClassnameAlias>,
+    /// properties set on entities of a classname that don't have them
+    pub defaults: HashMap<InternedStr, Vec<(String, String)>>,
+    /// bundles inserted on entities of a classname
+    pub bundles: HashMap<InternedStr, Vec<BundleInserter>>,
+    /// classes imported from an FGD, see [`Self::register_definitions`]
+    pub definitions: EntityDefinitions,
+}
+
+impl Default for MapEntityRegistry {
+    /// Registers the light variants of Quake as `light`
+    fn default() -> Self {
+        let mut registry = Self {
+            aliases: HashMap::default(),
+            defaults: HashMap::default(),
+            bundles: HashMap::default(),
+            definitions: EntityDefinitions::default(),
+        };
+        for alias in [
+            "light_fluoro",
+            "light_fluorospark",
+            "light_globe",
+            "light_torch_small_walltorch",
+            "light_flame_large_yellow",
+            "light_flame_small_yellow",
+            "light_flame_small_white",
+        ] {
+            registry.register_alias(alias, "light", &[("light", "300")]);
+        }
+        registry
+    }
+}
+
+impl MapEntityRegistry {
+    pub fn register_alias(
+        &mut self,
+        alias: &str,
+        classname: &str,
+        defaults: &[(&str, &str)],
+    ) -> &mut Self {
+        self.aliases.insert(
+            alias.into(),
+            ClassnameAlias {
+                classname: classname.into(),
+                defaults: defaults
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            },
+        );
+        self
+    }
+
+    /// Adds defaults for the properties of `classname`, replacing the ones
+    /// already registered for the same keys.
+    pub fn register_defaults(&mut self, classname: &str, defaults: &[(&str, &str)]) -> &mut Self {
+        let registered = self.defaults.entry(classname.into()).or_default();
+        for (key, value) in defaults {
+            registered.retain(|(registered_key, _)| registered_key != key);
+            registered.push((key.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    /// Inserts `B`, built from the entity's properties, on every entity of
+    /// `classname` (after resolving aliases) when its map is built.
+    pub fn register_bundle<B: Bundle + FromMapProps>(&mut self, classname: &str) -> &mut Self {
+        self.bundles
+            .entry(classname.into())
+            .or_default()
+            .push(insert_bundle::<B>);
+        self
+    }
+
+    /// Imports the classes of an FGD: their property defaults are added
+    /// for the properties that have none registered yet, and their
+    /// properties are checked by [`crate::MapAsset::validate_with_definitions`].
+    ///
+    /// Defaults are only added for classnames qevy or the game have no types
+    /// for, so an FGD doesn't change how built-in entities, aliases or
+    /// classnames with a registered bundle behave. Use
+    /// [`Self::register_defaults`] to set those explicitly.
+    pub fn register_definitions(&mut self, definitions: &EntityDefinitions) -> &mut Self {
+        for (classname, class) in definitions.classes.iter() {
+            self.definitions
+                .classes
+                .insert(classname.clone(), class.clone());
+        }
+        for (classname, class) in definitions.classes.iter() {
+            if class.kind == crate::fgd::ClassKind::Base
+                || crate::build::is_built_in_classname(classname)
+                || self.aliases.contains_key(classname.as_str())
+                || self.bundles.contains_key(classname.as_str())
+            {
+                continue;
+            }
+            let registered = self.defaults.entry(classname.as_str().into()).or_default();
+            for (key, value) in self.definitions.defaults(classname) {
+                if !registered
+                    .iter()
+                    .any(|(registered_key, _)| *registered_key == key)
+                {
+                    registered.push((key, value));
+                }
+            }
+        }
+        self
+    }
+
+    /// The imported FGD class of `classname`, if any.
+    pub fn definition(&self, classname: &str) -> Option<&EntityDefinition> {
+        self.definitions.classes.get(classname)
+    }
+
+    /// Resolves the classname of an entity and injects the defaults of its
+    /// alias, then of its classname. The `classname` property keeps the
+    /// name used in the map.
+    pub fn apply(&self, entity: &mut CompiledEntity) {
+        if let Some(alias) = self.aliases.get(entity.classname.as_str()) {
+            entity.classname = alias.classname.to_string();
+            inject_defaults(&mut entity.properties, &alias.defaults);
+        }
+        if let Some(defaults) = self.defaults.get(entity.classname.as_str()) {
+            inject_defaults(&mut entity.properties, defaults);
+        }
+    }
+}
+
+/// Sets the properties missing from `properties` to their default.
+pub fn inject_defaults(properties: &mut BTreeMap<String, String>, defaults: &[(String, String)]) {
+    for (key, value) in defaults {
+        properties
+            .entry(key.clone())
+            .or_insert_with(|| value.clone());
+    }
+}
+
+/// Registers bundles on the [`MapEntityRegistry`] of an app, see
+/// [`MapEntityRegistry::register_bundle`].
+pub trait RegisterClassnameBundle {
+    fn register_classname_bundle<B: Bundle + FromMapProps>(&mut self, classname: &str)
+        -> &mut Self;
+}
+
+impl RegisterClassnameBundle for App {
+    fn register_classname_bundle<B: Bundle + FromMapProps>(
+        &mut self,
+        classname: &str,
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(MapEntityRegistry::default)
+            .register_bundle::<B>(classname);
+        self
+    }
+}
+
+/// Inserts the registered bundles on the entities of built maps.
+pub fn insert_classname_bundles_system(
+    registry: Res<MapEntityRegistry>,
+    mut commands: Commands,
+    mut event_reader: EventReader<PostBuildMapEvent>,
+    q_map_entities: Query<(Entity, &MapEntityProperties)>,
+    q_parents: Query<&Parent>,
+    q_build_modes: Query<&MapBuildMode>,
+) {
+    for ev in event_reader.read() {
+        if registry.bundles.is_empty() {
+            continue;
+        }
+        for (entity, props) in q_map_entities.iter() {
+            let Some(inserters) = registry.bundles.get(props.classname.as_str()) else {
+                continue;
+            };
+            if !q_parents
+                .iter_ancestors(entity)
+                .any(|ancestor| ancestor == ev.map)
+            {
+                continue;
+            }
+            if !crate::build::map_build_mode_of(entity, &q_parents, &q_build_modes).spawns_logic() {
+                continue;
+            }
+            for insert in inserters {
+                insert(&mut commands, entity, props);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Foliage scattering over brush faces.
+//!
+//! Faces scatter copies of glTF scenes over their area when their texture's
+//! material definition has a `scatter` section:
+//!
+//! ```ron
+//! (
+//!     scatter: Some((
+//!         models: ["models/grass.glb#Scene0", "models/rock.glb#Scene0"],
+//!         density: Some(4.0),
+//!         scale: Some((0.8, 1.2)),
+//!     )),
+//! )
+//! ```
+//!
+//! or when their brush entity has a `_scatter` property listing the scenes,
+//! with optional `_scatter_density`, `_scatter_scale` (`min max`) and
+//! `_scatter_align` (`0` keeps instances upright) properties. Placement
+//! derives from the entity's seed, so it's the same on every client.
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::components::{Map, MapEntityProperties};
+use crate::picking::{BrushFace, BrushFaces};
+use crate::random::next_random;
+use crate::{MapAsset, PostBuildMapEvent};
+
+/// Scatter parameters of a material definition, unset fields use
+/// [`ScatterSettings::default`].
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScatterDefinition {
+    /// paths of the scenes to scatter, picked at random
+    pub models: Vec<String>,
+    pub density: Option<f32>,
+    pub scale: Option<(f32, f32)>,
+    pub align_to_normal: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScatterSettings {
+    pub models: Vec<String>,
+    /// instances per square Bevy unit of face area
+    pub density: f32,
+    /// range of the random uniform scale of the instances
+    pub scale: (f32, f32),
+    /// whether instances are tilted to the face normal or stay upright
+    pub align_to_normal: bool,
+}
+
+impl Default for ScatterSettings {
+    fn default() -> Self {
+        Self {
+            models: Vec::new(),
+            density: 1.0,
+            scale: (1.0, 1.0),
+            align_to_normal: true,
+        }
+    }
+}
+
+impl ScatterDefinition {
+    pub fn settings(&self) -> ScatterSettings {
+        let default = ScatterSettings::default();
+        ScatterSettings {
+            models: self.models.clone(),
+            density: self.density.unwrap_or(default.density),
+            scale: self.scale.unwrap_or(default.scale),
+            align_to_normal: self.align_to_normal.unwrap_or(default.align_to_normal),
+        }
+    }
+}
+
+impl ScatterSettings {
+    /// The settings of an entity's `_scatter` properties, if it has any.
+    pub fn from_properties(props: &MapEntityProperties) -> Option<Self> {
+        let models = props
+            .properties
+            .get("_scatter")?
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|model| !model.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        let default = Self::default();
+        let scale = props
+            .get_property_as_string("_scatter_scale", None)
+            .and_then(|value| {
+                let values = value
+                    .split_whitespace()
+                    .filter_map(|v| v.parse::<f32>().ok())
+                    .collect::<Vec<_>>();
+                match values[..] {
+                    [min, max] => Some((min, max)),
+                    [scale] => Some((scale, scale)),
+                    _ => None,
+                }
+            });
+        Some(Self {
+            models,
+            density: props.get_property_as_f32("_scatter_density", default.density),
+            scale: scale.unwrap_or(default.scale),
+            align_to_normal: props.get_property_as_bool("_scatter_align", default.align_to_normal),
+        })
+    }
+
+    /// Transforms of the instances scattered over a face, relative to its
+    /// brush entity, with the scene picked for each.
+    pub fn scatter(&self, face: &BrushFace, seed: u64) -> Vec<(usize, Transform)> {
+        let mut instances = Vec::new();
+        if self.models.is_empty() || self.density <= 0.0 {
+            return instances;
+        }
+        let mut rng = seed ^ (face.face_index as u64).wrapping_mul(0x9e3779b97f4a7c15);
+        for triangle in face.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| face.vertices[triangle[i] as usize]);
+            let area = (b - a).cross(c - a).length() * 0.5;
+            let normal = face
+                .normals
+                .get(triangle[0] as usize)
+                .copied()
+                .unwrap_or(Vec3::Y);
+            let count = (area * self.density + next_random(&mut rng)) as usize;
+            for _ in 0..count {
+                let (r1, r2) = (next_random(&mut rng).sqrt(), next_random(&mut rng));
+                let position = a * (1.0 - r1) + b * (r1 * (1.0 - r2)) + c * (r1 * r2);
+                let up = if self.align_to_normal {
+                    normal
+                } else {
+                    Vec3::Y
+                };
+                let yaw = next_random(&mut rng) * std::f32::consts::TAU;
+                let (min, max) = self.scale;
+                let scale = min + (max - min) * next_random(&mut rng);
+                let model = ((next_random(&mut rng) * self.models.len() as f32) as usize)
+                    .min(self.models.len() - 1);
+                instances.push((
+                    model,
+                    Transform::from_translation(position)
+                        .with_rotation(
+                            Quat::from_rotation_arc(Vec3::Y, up) * Quat::from_rotation_y(yaw),
+                        )
+                        .with_scale(Vec3::splat(scale)),
+                ));
+            }
+        }
+        instances
+    }
+}
+
+/// Marks the scenes scattered over brush faces.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ScatteredFoliage;
+
+pub fn scatter_system(
+    asset_server: Res<AssetServer>,
+    map_assets: Res<Assets<MapAsset>>,
+    mut commands: Commands,
+    mut post_build_events: EventReader<PostBuildMapEvent>,
+    q_maps: Query<&Map>,
+    q_parents: Query<&Parent>,
+    q_brushes: Query<(Entity, &BrushFaces, &MapEntityProperties)>,
+) {
+    for ev in post_build_events.read() {
+        let Some(map_asset) = q_maps
+            .get(ev.map)
+            .ok()
+            .and_then(|map| map_assets.get(&map.asset))
+        else {
+            continue;
+        };
+        for (entity, brush_faces, props) in q_brushes.iter() {
+            if !q_parents
+                .iter_ancestors(entity)
+                .any(|ancestor| ancestor == ev.map)
+            {
+                continue;
+            }
+            let entity_settings = ScatterSettings::from_properties(props);
+            for face in brush_faces.faces.iter() {
+                let settings = entity_settings.clone().or_else(|| {
+                    map_asset
+                        .material_definitions
+                        .get(&face.texture)
+                        .and_then(|definition| definition.scatter.as_ref())
+                        .map(|scatter| scatter.settings())
+                });
+                let Some(settings) = settings else {
+                    continue;
+                };
+                let instances = settings.scatter(face, props.seed);
+                if instances.is_empty() {
+                    continue;
+                }
+                commands.entity(entity).with_children(|children| {
+                    for (model, transform) in instances {
+                        children.spawn((
+                            SceneBundle {
+                                scene: asset_server.load(settings.models[model].clone()),
+                                transform,
+                                ..default()
+                            },
+                            ScatteredFoliage,
+                            Name::new("scattered foliage"),
+                        ));
+                    }
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,442 @@
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::render::texture::{ImageAddressMode, ImageFilterMode, ImageSamplerDescriptor};
+#[cfg(feature = "render")]
+use bevy::render::view::RenderLayers;
+use serde::{Deserialize, Serialize};
+
+use crate::components::MapUnits;
+use crate::conversions::{to_bevy_distance, SHAMBLER_UNITS_TO_BEVY_METERS};
+use crate::materials::TextureSlot;
+
+/// How textures behave when a brush entity moves at runtime.
+/// Can be overridden per entity with the `_uv_lock` property (`entity` or `world`).
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UvLock {
+    /// Textures stay locked to the brush, like TrenchBroom's texture lock
+    #[default]
+    Entity,
+    /// Textures stay fixed relative to the map and slide across moving brushes
+    World,
+}
+
+impl UvLock {
+    pub fn from_property(value: &str) -> Option<Self> {
+        match value.trim() {
+            "entity" => Some(Self::Entity),
+            "world" => Some(Self::World),
+            _ => None,
+        }
+    }
+}
+
+/// How brush faces are turned into meshes.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushMeshMode {
+    /// One mesh per face
+    #[default]
+    PerFace,
+    /// The faces of a brush merged by texture, with a child mesh per
+    /// texture range of the merged brush. Bevy meshes can't share a vertex
+    /// buffer, so each range keeps its own copy of its vertices: this saves
+    /// meshes and entities, not vertex memory.
+    /// Brushes with world-locked UVs still get one mesh per face.
+    PerBrush,
+}
+
+/// When brush colliders are generated.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum ColliderGeneration {
+    /// Every collider is built along with the map
+    #[default]
+    Immediate,
+    /// Solid brush colliders are built over several frames, closest to a
+    /// [`crate::colliders::ColliderAnchor`] first. Triggers are built right away.
+    Deferred {
+        /// colliders within this distance of an anchor are built before the
+        /// first physics step, in Bevy units
+        sync_radius: f32,
+        /// how many more colliders are built each frame
+        per_frame: usize,
+    },
+}
+
+/// Whether generated trigger sensors can sleep.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorActivation {
+    /// Sensors start asleep and stay out of the solver until something touches them
+    #[default]
+    Sleeping,
+    /// Sensors are always awake, e.g. to detect bodies that are asleep themselves
+    AlwaysAwake,
+}
+
+/// Where mover positions are updated.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoverTimestep {
+    /// Once per frame in `Update`, before the physics backend steps in
+    /// `PostUpdate`
+    #[default]
+    Variable,
+    /// In `FixedUpdate` ahead of the physics step, with the rendered
+    /// transform interpolated between steps, so kinematic bodies don't
+    /// jitter or tunnel.
+    ///
+    /// The physics backend must run in `FixedUpdate` too, e.g. with
+    /// `PhysicsPlugins::new(FixedUpdate)` for xpbd or
+    /// `RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule()`
+    /// for rapier. Movers are only ordered before the backend's sets there;
+    /// with the backend left in `PostUpdate`, physics steps once per frame
+    /// while movers step zero or more times.
+    Fixed,
+}
+
+/// Physics settings of generated bodies. Static geometry always gets the
+/// backend's static body type, which never needs waking up.
+#[derive(Clone, Debug, Default)]
+pub struct PhysicsBuildSettings {
+    pub sensor_activation: SensorActivation,
+    pub hull_simplification: HullSimplification,
+}
+
+/// Cleanup of brush vertices before convex hull colliders are built from
+/// them, see [`crate::colliders::simplify_hull`].
+#[derive(Clone, Debug)]
+pub struct HullSimplification {
+    /// turn off to build hulls from every face vertex
+    pub enabled: bool,
+    /// vertices closer than this are merged, in Bevy units
+    pub weld_distance: f32,
+    /// vertices kept per hull at most. Past it, the ones closest to the kept
+    /// ones are dropped first, which shrinks the hull a little.
+    pub max_vertices: Option<usize>,
+}
+
+impl Default for HullSimplification {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            weld_distance: 0.001,
+            max_vertices: None,
+        }
+    }
+}
+
+/// Settings used when building a loaded map into entities
+#[derive(Resource, Clone, Default)]
+pub struct MapBuildSettings {
+    pub uv_lock: UvLock,
+    pub brush_mesh_mode: BrushMeshMode,
+    pub collider_generation: ColliderGeneration,
+    pub physics: PhysicsBuildSettings,
+    /// render layers of meshes by texture name, where `*` stands for any
+    /// characters. The `_render_layers` entity property takes precedence.
+    #[cfg(feature = "render")]
+    pub texture_render_layers: Vec<(String, RenderLayers)>,
+    pub lights: LightIntensityConversion,
+    pub degenerate_geometry: DegenerateGeometryPolicy,
+    /// seed of maps without a worldspawn `_seed` property
+    pub seed: u64,
+    /// bake every `prop_static` into merged meshes, not only the ones with
+    /// `_bake 1`, see [`crate::props`]
+    pub bake_static_props: bool,
+    /// directory to write the build warnings of each map to, see
+    /// [`crate::issue_log`]
+    pub issue_log_dir: Option<std::path::PathBuf>,
+}
+
+/// What to do with faces dropped by the geometry cleanup, see
+/// [`crate::core::GeometryReport`].
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DegenerateGeometryPolicy {
+    /// build the map without them
+    Skip,
+    /// build the map without them, logging a warning and sending a
+    /// [`crate::components::DegenerateGeometry`] event for each
+    #[default]
+    Warn,
+    /// send the events but don't build the map
+    Fail,
+}
+
+impl MapBuildSettings {
+    /// The render layers of the first pattern in [`Self::texture_render_layers`] matching `texture_name`.
+    #[cfg(feature = "render")]
+    pub fn texture_render_layers(&self, texture_name: &str) -> Option<RenderLayers> {
+        self.texture_render_layers
+            .iter()
+            .find(|(pattern, _)| matches_pattern(pattern, texture_name))
+            .map(|(_, layers)| *layers)
+    }
+}
+
+/// Converts Quake-style `light` values (around 300) into Bevy light
+/// intensities, per light type. Lights with an explicit `intensity` or
+/// `illuminance` property are left alone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightIntensityConversion {
+    /// lumens per unit of `light` of point lights, in a map built with
+    /// [`MapUnits::Bevy`] at scale 1
+    pub point_lumens: f32,
+    /// lux per unit of `light` of directional lights
+    pub directional_lux: f32,
+}
+
+impl Default for LightIntensityConversion {
+    fn default() -> Self {
+        Self {
+            point_lumens: 10.0,
+            directional_lux: 30.0,
+        }
+    }
+}
+
+impl LightIntensityConversion {
+    /// Point light lumens for a `light` value. Light falls off with the
+    /// square of the distance, so lumens grow with the square of the units
+    /// and scale the map is built with.
+    pub fn point_intensity(&self, light: f32, map_units: &MapUnits, scale: f32) -> f32 {
+        let units_per_meter = to_bevy_distance(1.0, map_units) / SHAMBLER_UNITS_TO_BEVY_METERS;
+        light * self.point_lumens * (units_per_meter * scale).powi(2)
+    }
+
+    /// Point light range for a `light` value, which Quake uses as the
+    /// distance in map units the light reaches.
+    pub fn point_range(&self, light: f32, map_units: &MapUnits, scale: f32) -> f32 {
+        to_bevy_distance(light, map_units) * scale
+    }
+
+    /// Directional light illuminance for a `light` value.
+    pub fn directional_illuminance(&self, light: f32) -> f32 {
+        light * self.directional_lux
+    }
+}
+
+/// How much work goes into loading map materials.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaterialQuality {
+    /// Base color only, nearest filtering and no parallax
+    Low,
+    /// Auxiliary textures with cheaper parallax occlusion mapping
+    Medium,
+    /// Everything, with relief parallax mapping
+    #[default]
+    High,
+}
+
+impl MaterialQuality {
+    /// Whether depth maps and other auxiliary textures are fetched at all
+    pub fn loads_auxiliary_textures(&self) -> bool {
+        *self != MaterialQuality::Low
+    }
+
+    #[cfg(feature = "render")]
+    pub fn filter_mode(&self) -> ImageFilterMode {
+        match self {
+            MaterialQuality::Low => ImageFilterMode::Nearest,
+            MaterialQuality::Medium | MaterialQuality::High => ImageFilterMode::Linear,
+        }
+    }
+}
+
+/// What parts of a map are loaded and built, inserted on the map entity.
+#[derive(Component, Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapBuildMode {
+    /// Entities along with their meshes and colliders
+    #[default]
+    Full,
+    /// Point and brush entities with their properties, targets and
+    /// transforms only. No textures are loaded, and no meshes or colliders
+    /// spawned, e.g. for tools that need placement data or servers that only
+    /// need logical entities.
+    EntitiesOnly,
+    /// World geometry and colliders, with entities kept as plain
+    /// [`crate::components::MapEntityProperties`]. Trigger, mover, light and
+    /// other entity logic is skipped, e.g. to import levels into a game
+    /// framework that handles entities its own way. Trigger brushes are
+    /// still sensors, marked with [`crate::components::TriggerVolume`].
+    GeometryOnly,
+}
+
+impl MapBuildMode {
+    pub fn spawns_geometry(&self) -> bool {
+        *self != MapBuildMode::EntitiesOnly
+    }
+
+    pub fn spawns_logic(&self) -> bool {
+        *self != MapBuildMode::GeometryOnly
+    }
+}
+
+/// Settings of the map asset loaders, see `AssetServer::load_with_settings`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapLoaderSettings {
+    pub build_mode: MapBuildMode,
+    pub material_quality: MaterialQuality,
+    /// don't look for auxiliary textures (depth maps, definitions) of
+    /// textures without a base color file
+    pub skip_auxiliary_without_base_color: bool,
+    /// file name suffixes of the auxiliary textures, e.g. `("_n", TextureSlot::Normal)`
+    /// looks for `textures/{name}_n.png`
+    pub texture_suffixes: Vec<(String, TextureSlot)>,
+    #[cfg(feature = "render")]
+    pub sampler: SamplerSettings,
+    pub geometry_tolerance: GeometryTolerance,
+    /// asset paths of the maps including this one through `misc_external_map`,
+    /// outermost first, set when loading external maps to skip include cycles
+    pub including_maps: Vec<String>,
+}
+
+impl Default for MapLoaderSettings {
+    fn default() -> Self {
+        Self {
+            build_mode: MapBuildMode::default(),
+            material_quality: MaterialQuality::default(),
+            skip_auxiliary_without_base_color: true,
+            texture_suffixes: vec![
+                ("_depth".into(), TextureSlot::Depth),
+                (".normal_map".into(), TextureSlot::Normal),
+                (".metallic_roughness".into(), TextureSlot::MetallicRoughness),
+                (".emissive".into(), TextureSlot::Emissive),
+                (".occlusion".into(), TextureSlot::Occlusion),
+                (".orm".into(), TextureSlot::OcclusionRoughnessMetallic),
+                (".detail".into(), TextureSlot::Detail),
+            ],
+            #[cfg(feature = "render")]
+            sampler: SamplerSettings::default(),
+            geometry_tolerance: GeometryTolerance::default(),
+            including_maps: Vec::new(),
+        }
+    }
+}
+
+/// Tolerances of the geometry cleanup run after brushes are meshed, in map
+/// units. Tiny or off-grid brushes produce slivers and duplicate vertices,
+/// see [`crate::core::GeometryReport`] for what was fixed.
+///
+/// The epsilon of the plane intersections that build the brush hulls isn't
+/// one of them: shambler's `brush_hulls` and `face_vertices` don't take one,
+/// so it stays shambler's own.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GeometryTolerance {
+    /// vertices are snapped to a grid of this size, 0 turns snapping off
+    pub grid_snap: f32,
+    /// vertices of a face closer than this are merged
+    pub weld_distance: f32,
+    /// triangles with a smaller area are dropped, and faces left without
+    /// triangles along with them
+    pub min_triangle_area: f32,
+}
+
+impl Default for GeometryTolerance {
+    fn default() -> Self {
+        Self {
+            grid_snap: 0.0,
+            weld_distance: 0.001,
+            min_triangle_area: 0.0001,
+        }
+    }
+}
+
+impl GeometryTolerance {
+    /// The tolerances in Bevy space.
+    pub fn to_bevy(&self, map_units: &MapUnits) -> Self {
+        let distance = |value: f32| to_bevy_distance(value, map_units);
+        Self {
+            grid_snap: distance(self.grid_snap),
+            weld_distance: distance(self.weld_distance),
+            min_triangle_area: distance(distance(self.min_triangle_area)),
+        }
+    }
+}
+
+/// Sampler settings of the map textures
+#[cfg(feature = "render")]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SamplerSettings {
+    /// anisotropic filtering level, 1 turns it off
+    pub anisotropy: u16,
+    /// lowest mip level that can be sampled, wgpu samplers have no mip bias
+    /// so raising this is the closest way to sharpen distant textures
+    pub lod_min_clamp: f32,
+    /// the first override matching a texture name wins
+    pub overrides: Vec<SamplerOverride>,
+}
+
+#[cfg(feature = "render")]
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            anisotropy: 1,
+            lod_min_clamp: 0.0,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SamplerOverride {
+    /// texture name pattern where `*` matches anything, e.g. `floor*`
+    pub pattern: String,
+    pub filter: Option<ImageFilterMode>,
+    pub anisotropy: Option<u16>,
+}
+
+#[cfg(feature = "render")]
+impl SamplerSettings {
+    /// The sampler of a texture, on top of the filter mode of the material quality.
+    pub fn descriptor(
+        &self,
+        texture_name: &str,
+        quality: MaterialQuality,
+    ) -> ImageSamplerDescriptor {
+        let matched = self
+            .overrides
+            .iter()
+            .find(|o| matches_pattern(&o.pattern, texture_name));
+        let filter = matched
+            .and_then(|o| o.filter)
+            .unwrap_or(quality.filter_mode());
+        let mut anisotropy = matched
+            .and_then(|o| o.anisotropy)
+            .unwrap_or(self.anisotropy)
+            .max(1);
+        // wgpu only allows anisotropy with linear filtering
+        if !matches!(filter, ImageFilterMode::Linear) {
+            anisotropy = 1;
+        }
+
+        ImageSamplerDescriptor {
+            address_mode_u: ImageAddressMode::Repeat,
+            address_mode_v: ImageAddressMode::Repeat,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            lod_min_clamp: self.lod_min_clamp,
+            anisotropy_clamp: anisotropy,
+            ..default()
+        }
+    }
+}
+
+/// Matches `name` against a pattern where `*` stands for any characters.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // no `*` at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -0,0 +1,61 @@
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+struct BlendSettings {
+    mask_scale: f32,
+    use_mask: u32,
+}
+
+@group(2) @binding(100) var<uniform> blend: BlendSettings;
+@group(2) @binding(101) var blend_texture: texture_2d<f32>;
+@group(2) @binding(102) var blend_sampler: sampler;
+@group(2) @binding(103) var mask_texture: texture_2d<f32>;
+@group(2) @binding(104) var mask_sampler: sampler;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+#ifdef VERTEX_UVS
+    // the vertex alpha, times the mask if there is one, blends from the
+    // base texture to the second layer
+    var factor = 1.0;
+#ifdef VERTEX_COLORS
+    factor = in.color.a;
+#endif
+    if blend.use_mask != 0u {
+        factor *= textureSample(mask_texture, mask_sampler, in.uv * blend.mask_scale).r;
+    }
+    let layer = textureSample(blend_texture, blend_sampler, in.uv).rgb;
+    pbr_input.material.base_color = vec4(mix(pbr_input.material.base_color.rgb, layer, factor), 1.0);
+#endif
+
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
@@ -0,0 +1,57 @@
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+    mesh_view_bindings::view,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+struct DetailSettings {
+    tiling: f32,
+    fade_distance: f32,
+    strength: f32,
+}
+
+@group(2) @binding(100) var<uniform> detail: DetailSettings;
+@group(2) @binding(101) var detail_texture: texture_2d<f32>;
+@group(2) @binding(102) var detail_sampler: sampler;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+#ifdef VERTEX_UVS
+    // detail textures are centered on mid grey: brighter texels lighten the
+    // base color, darker ones darken it, fading out with distance
+    let distance = length(view.world_position - in.world_position.xyz);
+    let fade = 1.0 - clamp(distance / detail.fade_distance, 0.0, 1.0);
+    let sample = textureSample(detail_texture, detail_sampler, in.uv * detail.tiling).rgb;
+    let factor = mix(vec3(1.0), sample * 2.0, fade * detail.strength);
+    pbr_input.material.base_color = vec4(pbr_input.material.base_color.rgb * factor, pbr_input.material.base_color.a);
+#endif
+
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
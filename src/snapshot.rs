@@ -0,0 +1,215 @@
+//! Geometry snapshots, to catch unintended meshing changes.
+//!
+//! A [`GeometrySnapshot`] keeps the vertex and index counts and hashes of the
+//! drawable geometry of each texture, small enough to commit next to a map as
+//! a golden file and compare against after upgrading qevy.
+
+use bevy::math::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::core::{is_nodraw_texture, CompiledMap};
+
+/// Positions, normals and UVs are hashed at this precision, so float noise
+/// below it doesn't change the snapshot.
+const QUANTIZATION: f32 = 1024.0;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeometrySnapshot {
+    /// by texture name
+    pub materials: BTreeMap<String, MaterialSnapshot>,
+}
+
+/// The geometry drawn with one texture.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaterialSnapshot {
+    pub vertex_count: usize,
+    pub index_count: usize,
+    /// hash of the positions, normals and UVs, in map order
+    pub vertex_hash: u64,
+    pub index_hash: u64,
+}
+
+impl GeometrySnapshot {
+    /// Snapshots the faces and patches of a compiled map, skipping nodraw textures.
+    pub fn new(compiled_map: &CompiledMap) -> Self {
+        let mut hashers = BTreeMap::<String, (MaterialSnapshot, Fnv1a, Fnv1a)>::new();
+        let mut add =
+            |texture: &str, vertices: &[Vec3], normals: &[Vec3], uvs: &[Vec2], indices: &[u32]| {
+                if is_nodraw_texture(texture) {
+                    return;
+                }
+                let (snapshot, vertex_hasher, index_hasher) = hashers
+                    .entry(texture.to_owned())
+                    .or_insert_with(|| (MaterialSnapshot::default(), Fnv1a::new(), Fnv1a::new()));
+                snapshot.vertex_count += vertices.len();
+                snapshot.index_count += indices.len();
+                for value in vertices.iter().chain(normals.iter()) {
+                    vertex_hasher.write_floats(&value.to_array());
+                }
+                for uv in uvs {
+                    vertex_hasher.write_floats(&uv.to_array());
+                }
+                for index in indices {
+                    index_hasher.write(&index.to_le_bytes());
+                }
+            };
+
+        for entity in compiled_map.entities.iter() {
+            for face in entity.brushes.iter().flat_map(|brush| brush.faces.iter()) {
+                add(
+                    &face.texture,
+                    &face.vertices,
+                    &face.normals,
+                    &face.uvs,
+                    &face.indices,
+                );
+            }
+        }
+        for patch in compiled_map.patches.iter() {
+            add(
+                &patch.texture,
+                &patch.vertices,
+                &patch.normals,
+                &patch.uvs,
+                &patch.indices,
+            );
+        }
+
+        Self {
+            materials: hashers
+                .into_iter()
+                .map(|(texture, (mut snapshot, vertex_hasher, index_hasher))| {
+                    snapshot.vertex_hash = vertex_hasher.finish();
+                    snapshot.index_hash = index_hasher.finish();
+                    (texture, snapshot)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("geometry snapshots are always serializable")
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::de::from_str(text)
+    }
+
+    /// Differences from `baseline`, one line per changed texture, empty if
+    /// the geometry matches.
+    pub fn diff(&self, baseline: &GeometrySnapshot) -> Vec<String> {
+        let mut differences = Vec::new();
+        for (texture, expected) in baseline.materials.iter() {
+            match self.materials.get(texture) {
+                None => differences.push(format!("{}: missing", texture)),
+                Some(actual) if actual != expected => differences.push(format!(
+                    "{}: {:?}, expected {:?}",
+                    texture, actual, expected
+                )),
+                Some(_) => {}
+            }
+        }
+        for texture in self.materials.keys() {
+            if !baseline.materials.contains_key(texture) {
+                differences.push(format!("{}: unexpected", texture));
+            }
+        }
+        differences
+    }
+}
+
+/// 64 bit FNV-1a, stable across Rust versions unlike `DefaultHasher`.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_floats(&mut self, values: &[f32]) {
+        for value in values {
+            let quantized = (value * QUANTIZATION).round() as i64;
+            self.write(&quantized.to_le_bytes());
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(bytes: &[u8]) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    fn hash_floats(values: &[f32]) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write_floats(values);
+        hasher.finish()
+    }
+
+    fn snapshot(materials: &[(&str, usize)]) -> GeometrySnapshot {
+        GeometrySnapshot {
+            materials: materials
+                .iter()
+                .map(|(texture, vertex_count)| {
+                    let snapshot = MaterialSnapshot {
+                        vertex_count: *vertex_count,
+                        ..MaterialSnapshot::default()
+                    };
+                    (texture.to_string(), snapshot)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn hashes_fnv1a() {
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn ignores_float_noise() {
+        assert_eq!(hash_floats(&[1.0, -2.5]), hash_floats(&[1.0001, -2.4999]));
+        assert_ne!(hash_floats(&[1.0, -2.5]), hash_floats(&[1.01, -2.5]));
+        assert_ne!(hash_floats(&[1.0, -2.5]), hash_floats(&[-2.5, 1.0]));
+    }
+
+    #[test]
+    fn diffs_materials() {
+        let baseline = snapshot(&[("brick", 4), ("metal", 8), ("wood", 12)]);
+        assert!(baseline.diff(&baseline).is_empty());
+
+        let differences = snapshot(&[("brick", 4), ("metal", 6), ("water", 4)]).diff(&baseline);
+        assert_eq!(differences.len(), 3);
+        assert!(differences[0].starts_with("metal: "));
+        assert_eq!(differences[1], "wood: missing");
+        assert_eq!(differences[2], "water: unexpected");
+    }
+
+    #[test]
+    fn round_trips_ron() {
+        let snapshot = snapshot(&[("brick", 4), ("metal", 8)]);
+        assert_eq!(
+            GeometrySnapshot::from_ron(&snapshot.to_ron()).unwrap(),
+            snapshot
+        );
+    }
+}
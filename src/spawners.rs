@@ -0,0 +1,177 @@
+//! `func_spawner` point entities.
+//!
+//! A spawner picks a classname from `spawn_class`, a list of classnames with
+//! optional weights (`monster_dog:3, monster_ogre:1`), and requests `count`
+//! spawns, `interval` seconds apart, at a random point within `radius` map
+//! units. Spawners with a `targetname` wait to be triggered, the others start
+//! right away.
+//!
+//! Each spawn runs the system registered for its classname in the
+//! [`SpawnerRegistry`], or sends a [`SpawnRequested`] event if there is none.
+
+use bevy::ecs::system::SystemId;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::time::Duration;
+
+use crate::components::{
+    MapEntityProperties, MapSpatialBundle, MapUnits, TriggerTarget, TriggeredEvent,
+};
+use crate::conversions::to_bevy_distance;
+use crate::intern::InternedStr;
+use crate::random::next_random;
+
+#[derive(Component, Debug, Clone)]
+pub struct Spawner {
+    /// classnames to spawn, with their relative weights
+    pub classes: Vec<(InternedStr, f32)>,
+    pub count: u32,
+    pub interval: Duration,
+    /// spawn radius, in Bevy units
+    pub radius: f32,
+    pub active: bool,
+    pub spawned: u32,
+    pub timer: Timer,
+    rng: u64,
+}
+
+impl Spawner {
+    /// Parses a `spawn_class` property, e.g. `monster_dog:3, monster_ogre`.
+    pub fn parse_classes(value: &str) -> Vec<(InternedStr, f32)> {
+        value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|class| !class.is_empty())
+            .filter_map(|class| match class.split_once(':') {
+                Some((name, weight)) => weight
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|weight| *weight > 0.0)
+                    .map(|weight| (InternedStr::from(name), weight)),
+                None => Some((InternedStr::from(class), 1.0)),
+            })
+            .collect()
+    }
+
+    fn next_random(&mut self) -> f32 {
+        next_random(&mut self.rng)
+    }
+
+    /// Picks a classname according to the weights.
+    pub fn pick_class(&mut self) -> Option<InternedStr> {
+        let total: f32 = self.classes.iter().map(|(_, weight)| weight).sum();
+        let mut pick = self.next_random() * total;
+        for (class, weight) in self.classes.iter() {
+            if pick < *weight {
+                return Some(class.clone());
+            }
+            pick -= weight;
+        }
+        self.classes.last().map(|(class, _)| class.clone())
+    }
+
+    /// A random offset on the horizontal disk of the spawner's radius.
+    pub fn pick_offset(&mut self) -> Vec3 {
+        let angle = self.next_random() * std::f32::consts::TAU;
+        let distance = self.next_random().sqrt() * self.radius;
+        Vec3::new(angle.cos() * distance, 0.0, angle.sin() * distance)
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct SpawnRequested {
+    pub spawner: Entity,
+    pub classname: InternedStr,
+    pub transform: Transform,
+}
+
+/// Systems to run instead of sending a [`SpawnRequested`] event, by classname.
+#[derive(Resource, Default)]
+pub struct SpawnerRegistry {
+    pub spawners: HashMap<InternedStr, SystemId<SpawnRequested>>,
+}
+
+impl SpawnerRegistry {
+    pub fn register(&mut self, classname: &str, system: SystemId<SpawnRequested>) {
+        self.spawners.insert(classname.into(), system);
+    }
+}
+
+/// Inserts the [`Spawner`] component for a `func_spawner` entity.
+pub fn apply_spawner_properties(
+    map_units: &MapUnits,
+    scale: f32,
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let interval = Duration::from_secs_f32(props.get_property_as_f32("interval", 1.0).max(0.0));
+    commands.entity(entity).insert((
+        Spawner {
+            classes: Spawner::parse_classes(
+                &props
+                    .get_property_as_string("spawn_class", None)
+                    .unwrap_or_default(),
+            ),
+            count: props.get_property_as_i32("count", 1).max(0) as u32,
+            interval,
+            radius: to_bevy_distance(props.get_property_as_f32("radius", 0.0), map_units) * scale,
+            active: !props.properties.contains_key("targetname"),
+            spawned: 0,
+            // the first spawn happens as soon as the spawner is active
+            timer: Timer::new(Duration::ZERO, TimerMode::Once),
+            rng: props.seed,
+        },
+        MapSpatialBundle::from_transform(props.transform),
+    ));
+}
+
+pub fn activate_spawners_system(
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut q_spawners: Query<(&TriggerTarget, &mut Spawner)>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (trigger_target, mut spawner) in q_spawners.iter_mut() {
+            if trigger_target.target_name == trigger_event.target {
+                spawner.active = true;
+            }
+        }
+    }
+}
+
+pub fn spawner_system(
+    time: Res<Time>,
+    registry: Res<SpawnerRegistry>,
+    mut commands: Commands,
+    mut q_spawners: Query<(Entity, &mut Spawner, &GlobalTransform)>,
+    mut spawn_events: EventWriter<SpawnRequested>,
+) {
+    for (entity, mut spawner, transform) in q_spawners.iter_mut() {
+        if !spawner.active || spawner.spawned >= spawner.count {
+            continue;
+        }
+        if !spawner.timer.tick(time.delta()).finished() {
+            continue;
+        }
+        let interval = spawner.interval;
+        spawner.timer = Timer::new(interval, TimerMode::Once);
+        spawner.spawned += 1;
+
+        let Some(classname) = spawner.pick_class() else {
+            continue;
+        };
+        let offset = spawner.pick_offset();
+        let request = SpawnRequested {
+            spawner: entity,
+            classname: classname.clone(),
+            transform: Transform::from_translation(transform.translation() + offset)
+                .with_rotation(transform.compute_transform().rotation),
+        };
+
+        match registry.spawners.get(&classname) {
+            Some(system) => commands.run_system_with_input(*system, request),
+            None => {
+                spawn_events.send(request);
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! `target_speaker` point entities.
+//!
+//! When its `targetname` is fired, a speaker sends a [`SpeakerEvent`] for its
+//! `sound`, at `volume` (1 by default) and with a playback speed randomly
+//! varied by up to `pitch_variance`. With the `audio` feature the sound is
+//! also played at the speaker's location; games with their own audio stack
+//! can disable the feature and play [`SpeakerEvent`]s themselves.
+
+use bevy::prelude::*;
+
+use crate::components::{MapEntityProperties, MapSpatialBundle, TriggerTarget, TriggeredEvent};
+use crate::intern::InternedStr;
+use crate::random::next_random;
+
+#[derive(Component, Debug, Clone)]
+pub struct Speaker {
+    /// path of the sound asset
+    pub sound: Option<InternedStr>,
+    pub volume: f32,
+    /// the playback speed varies randomly between `1 - pitch_variance` and
+    /// `1 + pitch_variance`
+    pub pitch_variance: f32,
+    rng: u64,
+}
+
+/// Sent when a [`Speaker`] is fired.
+#[derive(Event, Debug, Clone)]
+pub struct SpeakerEvent {
+    pub speaker: Entity,
+    pub sound: InternedStr,
+    pub volume: f32,
+    /// playback speed, varied by the speaker's `pitch_variance`
+    pub speed: f32,
+    pub translation: Vec3,
+}
+
+/// Inserts the [`Speaker`] component for a `target_speaker` entity.
+pub fn apply_speaker_properties(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    commands.entity(entity).insert((
+        Speaker {
+            sound: props.properties.get("sound").cloned(),
+            volume: props.get_property_as_f32("volume", 1.0).max(0.0),
+            pitch_variance: props
+                .get_property_as_f32("pitch_variance", 0.0)
+                .clamp(0.0, 1.0),
+            rng: props.seed,
+        },
+        MapSpatialBundle::from_transform(props.transform),
+    ));
+}
+
+pub fn speaker_system(
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut speaker_events: EventWriter<SpeakerEvent>,
+    mut q_speakers: Query<(Entity, &TriggerTarget, &mut Speaker, &GlobalTransform)>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (entity, trigger_target, mut speaker, transform) in q_speakers.iter_mut() {
+            if trigger_target.target_name != trigger_event.target {
+                continue;
+            }
+            let Some(sound) = speaker.sound.clone() else {
+                warn!("target_speaker {:?} has no sound", entity);
+                continue;
+            };
+            let variance = (next_random(&mut speaker.rng) * 2.0 - 1.0) * speaker.pitch_variance;
+            speaker_events.send(SpeakerEvent {
+                speaker: entity,
+                sound,
+                volume: speaker.volume,
+                speed: 1.0 + variance,
+                translation: transform.translation(),
+            });
+        }
+    }
+}
+
+/// Plays the sound of each [`SpeakerEvent`] once, at the speaker's location.
+#[cfg(feature = "audio")]
+pub fn play_speaker_sounds_system(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    mut speaker_events: EventReader<SpeakerEvent>,
+) {
+    for ev in speaker_events.read() {
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(ev.sound.to_string()),
+                settings: PlaybackSettings::DESPAWN
+                    .with_volume(bevy::audio::Volume::new(ev.volume))
+                    .with_speed(ev.speed)
+                    .with_spatial(true),
+            },
+            SpatialBundle::from_transform(Transform::from_translation(ev.translation)),
+            Name::new(format!("speaker sound ({})", ev.sound)),
+        ));
+    }
+}
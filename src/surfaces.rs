@@ -0,0 +1,152 @@
+//! Surface kinds of brush faces, for footstep and impact sounds.
+//!
+//! The [`SurfaceTable`] maps texture names to a [`SurfaceKind`], and
+//! [`SurfaceQuery::surface_at`] resolves the face a contact point lies on, so
+//! brushes with several textures sound right wherever they're hit.
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::picking::{BrushFace, BrushFaces};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum SurfaceKind {
+    #[default]
+    Default,
+    Stone,
+    Metal,
+    Wood,
+    Dirt,
+    Grass,
+    Snow,
+    Glass,
+    Custom(String),
+}
+
+/// Surface kinds by texture name. The first rule whose pattern is part of
+/// the texture name wins, ignoring case.
+#[derive(Resource, Debug, Clone)]
+pub struct SurfaceTable {
+    pub rules: Vec<(String, SurfaceKind)>,
+}
+
+impl Default for SurfaceTable {
+    fn default() -> Self {
+        Self {
+            rules: [
+                ("metal", SurfaceKind::Metal),
+                ("wood", SurfaceKind::Wood),
+                ("grass", SurfaceKind::Grass),
+                ("dirt", SurfaceKind::Dirt),
+                ("snow", SurfaceKind::Snow),
+                ("glass", SurfaceKind::Glass),
+                ("window", SurfaceKind::Glass),
+                ("stone", SurfaceKind::Stone),
+                ("rock", SurfaceKind::Stone),
+                ("brick", SurfaceKind::Stone),
+            ]
+            .into_iter()
+            .map(|(pattern, kind)| (pattern.to_owned(), kind))
+            .collect(),
+        }
+    }
+}
+
+impl SurfaceTable {
+    pub fn surface_of(&self, texture_name: &str) -> SurfaceKind {
+        let texture_name = texture_name.to_lowercase();
+        self.rules
+            .iter()
+            .find(|(pattern, _)| texture_name.contains(&pattern.to_lowercase()))
+            .map_or(SurfaceKind::Default, |(_, kind)| kind.clone())
+    }
+}
+
+/// Resolves the brush faces at contact points.
+#[derive(SystemParam)]
+pub struct SurfaceQuery<'w, 's> {
+    table: Res<'w, SurfaceTable>,
+    q_parents: Query<'w, 's, &'static Parent>,
+    q_brushes: Query<'w, 's, (&'static BrushFaces, &'static GlobalTransform)>,
+}
+
+impl<'w, 's> SurfaceQuery<'w, 's> {
+    /// The face of the brush entity owning `collider` (or `collider` itself)
+    /// closest to `point`, in world space.
+    pub fn face_at(&self, collider: Entity, point: Vec3) -> Option<&BrushFace> {
+        let (brush_faces, transform) = std::iter::once(collider)
+            .chain(self.q_parents.iter_ancestors(collider))
+            .find_map(|entity| self.q_brushes.get(entity).ok())?;
+        let point = transform.affine().inverse().transform_point3(point);
+        brush_faces
+            .faces
+            .iter()
+            .filter_map(|face| Some((face, face_distance_squared(face, point)?)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(face, _)| face)
+    }
+
+    /// The [`SurfaceKind`] of the face at `point`, [`SurfaceKind::Default`]
+    /// if `collider` doesn't belong to a brush entity.
+    pub fn surface_at(&self, collider: Entity, point: Vec3) -> SurfaceKind {
+        self.face_at(collider, point)
+            .map_or(SurfaceKind::Default, |face| {
+                self.table.surface_of(face.texture.as_str())
+            })
+    }
+}
+
+/// Squared distance from `point` to the closest triangle of `face`.
+fn face_distance_squared(face: &BrushFace, point: Vec3) -> Option<f32> {
+    face.indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| face.vertices[triangle[i] as usize]);
+            closest_point_on_triangle(point, a, b, c).distance_squared(point)
+        })
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// Closest point to `p` on the triangle `abc`, from Real-Time Collision Detection.
+fn closest_point_on_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
@@ -0,0 +1,193 @@
+//! Helpers to regression-test maps and entity handlers, behind the
+//! `test_utils` feature.
+//!
+//! [`test_app`] runs the build pipeline headlessly, without rendering or a
+//! physics step, and [`build_map_from_str`] or [`load_map`] build a map in it:
+//!
+//! ```ignore
+//! let mut app = qevy::test_utils::test_app();
+//! let map = qevy::test_utils::build_map_from_str(&mut app, include_str!("level.map"));
+//! qevy::test_utils::assert_entity_with_classname(&mut app, "info_player_start");
+//! assert_eq!(qevy::test_utils::face_count(&mut app, map), 36);
+//! ```
+
+use bevy::prelude::*;
+use std::path::Path;
+
+use crate::components::{Map, MapBuildReport, MapBundle, MapEntityProperties, MapUnits};
+use crate::snapshot::GeometrySnapshot;
+use crate::{MapAsset, QevyPlugins};
+
+/// Updates run by [`load_map`] before giving up on the map loading.
+const MAX_LOAD_UPDATES: usize = 10_000;
+
+/// A headless App with [`QevyPlugins`], loading assets from `assets`.
+/// Add your own entity handlers to it before building maps.
+pub fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        QevyPlugins {
+            headless: true,
+            ..default()
+        },
+    ));
+    app
+}
+
+/// Builds a map from its text, returning the map entity once it's built.
+/// Panics if the map can't be parsed.
+pub fn build_map_from_str(app: &mut App, text: &str) -> Entity {
+    let map_asset = text.parse::<MapAsset>().expect("invalid map");
+    let handle = app.world.resource_mut::<Assets<MapAsset>>().add(map_asset);
+    let map = spawn_map_bundle(app, handle);
+    update_until_built(app, map, 1);
+    map
+}
+
+/// Loads a map through the asset server, returning the map entity once it's
+/// built. Panics if the map doesn't load.
+pub fn load_map(app: &mut App, path: &str) -> Entity {
+    let handle = app.world.resource::<AssetServer>().load(path.to_owned());
+    let map = spawn_map_bundle(app, handle);
+    update_until_built(app, map, MAX_LOAD_UPDATES);
+    map
+}
+
+fn spawn_map_bundle(app: &mut App, handle: Handle<MapAsset>) -> Entity {
+    app.world
+        .spawn(MapBundle {
+            map: Map {
+                asset: handle,
+                ..default()
+            },
+            ..default()
+        })
+        .id()
+}
+
+fn update_until_built(app: &mut App, map: Entity, max_updates: usize) {
+    for _ in 0..max_updates {
+        app.update();
+        if app.world.get::<MapBuildReport>(map).is_some() {
+            return;
+        }
+        std::thread::yield_now();
+    }
+    panic!("map wasn't built after {} updates", max_updates);
+}
+
+/// The map entities with the given classname.
+pub fn entities_with_classname(app: &mut App, classname: &str) -> Vec<Entity> {
+    app.world
+        .query::<(Entity, &MapEntityProperties)>()
+        .iter(&app.world)
+        .filter(|(_, props)| props.classname.as_str() == classname)
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+/// Panics, listing the classnames found, if no map entity has `classname`.
+pub fn assert_entity_with_classname(app: &mut App, classname: &str) {
+    if !entities_with_classname(app, classname).is_empty() {
+        return;
+    }
+    let mut found = app
+        .world
+        .query::<&MapEntityProperties>()
+        .iter(&app.world)
+        .map(|props| props.classname.to_string())
+        .collect::<Vec<_>>();
+    found.sort();
+    found.dedup();
+    panic!("no entity with classname {}, found {:?}", classname, found);
+}
+
+/// Number of brush colliders, including the ones still pending generation.
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+pub fn collider_count(app: &mut App) -> usize {
+    use crate::colliders::PendingCollider;
+
+    #[cfg(feature = "xpbd")]
+    use bevy_xpbd_3d::prelude::Collider;
+
+    #[cfg(feature = "rapier")]
+    #[cfg(not(feature = "xpbd"))]
+    use bevy_rapier3d::prelude::Collider;
+
+    app.world
+        .query_filtered::<(), Or<(With<Collider>, With<PendingCollider>)>>()
+        .iter(&app.world)
+        .count()
+}
+
+#[cfg(any(feature = "xpbd", feature = "rapier"))]
+pub fn assert_collider_count(app: &mut App, expected: usize) {
+    let count = collider_count(app);
+    assert_eq!(
+        count, expected,
+        "expected {} colliders, found {}",
+        expected, count
+    );
+}
+
+fn map_asset(app: &App, map: Entity) -> &MapAsset {
+    let handle = &app.world.get::<Map>(map).expect("not a map").asset;
+    app.world
+        .resource::<Assets<MapAsset>>()
+        .get(handle)
+        .expect("map asset isn't loaded")
+}
+
+/// Number of brush faces in the map, drawable or not.
+pub fn face_count(app: &mut App, map: Entity) -> usize {
+    map_asset(app, map)
+        .compile(app.world.resource::<MapUnits>())
+        .map_or(0, |compiled_map| {
+            compiled_map
+                .entities
+                .iter()
+                .flat_map(|entity| entity.brushes.iter())
+                .map(|brush| brush.faces.len())
+                .sum()
+        })
+}
+
+/// Compares the map's [`GeometrySnapshot`] with the golden file at `path`.
+/// Set the `QEVY_UPDATE_SNAPSHOTS` environment variable to write the file
+/// instead, to create it or accept intended meshing changes. Panics if the
+/// file doesn't exist otherwise, so a missing golden file can't pass.
+pub fn assert_geometry_snapshot(app: &mut App, map: Entity, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let snapshot = map_asset(app, map)
+        .geometry_snapshot(app.world.resource::<MapUnits>())
+        .expect("map has no geometry");
+
+    if std::env::var_os("QEVY_UPDATE_SNAPSHOTS").is_some() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).expect("couldn't create snapshot directory");
+        }
+        std::fs::write(path, snapshot.to_ron()).expect("couldn't write snapshot");
+        return;
+    }
+
+    let baseline = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => panic!(
+            "couldn't read snapshot {}: {}, run with QEVY_UPDATE_SNAPSHOTS=1 to create it",
+            path.display(),
+            e
+        ),
+    };
+    let baseline = GeometrySnapshot::from_ron(&baseline).expect("invalid snapshot");
+    let differences = snapshot.diff(&baseline);
+    assert!(
+        differences.is_empty(),
+        "geometry differs from {}:\n{}",
+        path.display(),
+        differences.join("\n")
+    );
+}
@@ -0,0 +1,190 @@
+//! Map thumbnails for level selection menus.
+//!
+//! With [`MapThumbnailPlugin`] added, every built map gets a [`MapThumbnail`]
+//! image rendered by an offscreen camera, placed at the map's `info_overview`
+//! entity or framing the whole map when there is none. The image is filled a
+//! couple of frames after the map is built.
+
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::primitives::Aabb;
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+
+use crate::components::MapEntityProperties;
+use crate::PostBuildMapEvent;
+
+pub struct MapThumbnailPlugin {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for MapThumbnailPlugin {
+    fn default() -> Self {
+        Self {
+            width: 512,
+            height: 288,
+        }
+    }
+}
+
+impl Plugin for MapThumbnailPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MapThumbnailSettings {
+            width: self.width,
+            height: self.height,
+        })
+        .add_systems(
+            Update,
+            (spawn_thumbnail_camera_system, thumbnail_camera_system),
+        );
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct MapThumbnailSettings {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The rendered thumbnail of a map, on the map entity.
+#[derive(Component, Debug, Clone)]
+pub struct MapThumbnail {
+    pub image: Handle<Image>,
+}
+
+/// Offscreen camera capturing a map thumbnail, despawned once it rendered.
+#[derive(Component, Debug, Clone)]
+pub struct ThumbnailCamera {
+    pub map: Entity,
+    frames: u32,
+}
+
+pub fn spawn_thumbnail_camera_system(
+    settings: Res<MapThumbnailSettings>,
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut post_build_events: EventReader<PostBuildMapEvent>,
+) {
+    for ev in post_build_events.read() {
+        let size = Extent3d {
+            width: settings.width,
+            height: settings.height,
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image {
+            texture_descriptor: TextureDescriptor {
+                label: Some("map_thumbnail"),
+                size,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Bgra8UnormSrgb,
+                mip_level_count: 1,
+                sample_count: 1,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+            ..default()
+        };
+        image.resize(size);
+        let image = images.add(image);
+
+        commands.entity(ev.map).insert(MapThumbnail {
+            image: image.clone(),
+        });
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    target: RenderTarget::Image(image),
+                    is_active: false,
+                    order: -1,
+                    ..default()
+                },
+                ..default()
+            },
+            ThumbnailCamera {
+                map: ev.map,
+                frames: 0,
+            },
+            Name::new("thumbnail camera"),
+        ));
+    }
+}
+
+/// Places thumbnail cameras once the map meshes have their bounds, and
+/// despawns them after they rendered a frame.
+pub fn thumbnail_camera_system(
+    mut commands: Commands,
+    mut q_cameras: Query<(
+        Entity,
+        &mut ThumbnailCamera,
+        &mut Camera,
+        &mut Transform,
+        &Projection,
+    )>,
+    q_maps: Query<&GlobalTransform>,
+    q_children: Query<&Children>,
+    q_overviews: Query<&MapEntityProperties>,
+    q_bounds: Query<(&Aabb, &GlobalTransform)>,
+) {
+    for (entity, mut thumbnail_camera, mut camera, mut transform, projection) in
+        q_cameras.iter_mut()
+    {
+        thumbnail_camera.frames += 1;
+        if thumbnail_camera.frames > 1 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let Ok(map_transform) = q_maps.get(thumbnail_camera.map) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        let overview = q_children
+            .iter_descendants(thumbnail_camera.map)
+            .filter_map(|child| q_overviews.get(child).ok())
+            .find(|props| props.classname == "info_overview");
+
+        if let Some(overview) = overview {
+            *transform = map_transform
+                .mul_transform(overview.transform)
+                .compute_transform();
+        } else {
+            let mut min = Vec3::splat(f32::MAX);
+            let mut max = Vec3::splat(f32::MIN);
+            for child in q_children.iter_descendants(thumbnail_camera.map) {
+                let Ok((aabb, global_transform)) = q_bounds.get(child) else {
+                    continue;
+                };
+                for i in 0..8 {
+                    let corner = Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32);
+                    let local =
+                        Vec3::from(aabb.min()) + Vec3::from(aabb.half_extents) * 2.0 * corner;
+                    let point = global_transform.transform_point(local);
+                    min = min.min(point);
+                    max = max.max(point);
+                }
+            }
+            if min.cmpgt(max).any() {
+                continue;
+            }
+
+            // look down at the map from a 3/4 view, far enough to frame its bounding sphere
+            let center = (min + max) * 0.5;
+            let radius = (max - min).length() * 0.5;
+            let fov = match projection {
+                Projection::Perspective(perspective) => perspective.fov,
+                Projection::Orthographic(_) => std::f32::consts::FRAC_PI_4,
+            };
+            let distance = radius / (fov * 0.5).sin();
+            let direction = Vec3::new(1.0, 1.0, 1.0).normalize();
+            *transform = Transform::from_translation(center + direction * distance)
+                .looking_at(center, Vec3::Y);
+        }
+
+        camera.is_active = true;
+    }
+}
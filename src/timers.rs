@@ -0,0 +1,95 @@
+//! `func_timer` point entities.
+//!
+//! An enabled timer fires its `target` every `interval` seconds (1 by
+//! default), each wait randomly lengthened or shortened by up to
+//! `random_variance` seconds. Firing the timer's `targetname` toggles it on
+//! and off. Timers start disabled unless spawnflag 1 (start on) is set, or
+//! they have no `targetname` to be turned on with.
+
+use bevy::prelude::*;
+
+use crate::components::{MapEntityProperties, TriggerTarget, TriggeredEvent};
+use crate::intern::InternedStr;
+use crate::random::next_random;
+
+/// Spawnflag of timers enabled from the start.
+pub const TIMER_START_ON: i32 = 1;
+
+#[derive(Component, Debug, Clone)]
+pub struct MapTimer {
+    pub target: InternedStr,
+    /// seconds between firings
+    pub interval: f32,
+    /// each wait varies randomly between `interval - random_variance` and
+    /// `interval + random_variance`
+    pub random_variance: f32,
+    pub enabled: bool,
+    /// seconds until the next firing
+    pub remaining: f32,
+    rng: u64,
+}
+
+impl MapTimer {
+    fn next_wait(&mut self) -> f32 {
+        let variance = (next_random(&mut self.rng) * 2.0 - 1.0) * self.random_variance;
+        // a zero wait would fire every frame at best, or loop forever
+        (self.interval + variance).max(0.01)
+    }
+}
+
+/// Inserts the [`MapTimer`] component for a `func_timer` entity.
+pub fn apply_timer_properties(
+    commands: &mut Commands,
+    entity: Entity,
+    props: &MapEntityProperties,
+) {
+    let spawnflags = props.get_property_as_i32("spawnflags", 0);
+    let mut timer = MapTimer {
+        target: props.properties.get("target").cloned().unwrap_or_default(),
+        interval: props.get_property_as_f32("interval", 1.0).max(0.0),
+        random_variance: props.get_property_as_f32("random_variance", 0.0).max(0.0),
+        enabled: spawnflags & TIMER_START_ON != 0 || !props.properties.contains_key("targetname"),
+        remaining: 0.0,
+        rng: props.seed,
+    };
+    timer.remaining = timer.next_wait();
+    commands.entity(entity).insert(timer);
+}
+
+/// Toggles timers whose `targetname` is fired.
+pub fn toggle_timers_system(
+    mut trigger_events: EventReader<TriggeredEvent>,
+    mut q_timers: Query<(&TriggerTarget, &mut MapTimer)>,
+) {
+    for trigger_event in trigger_events.read() {
+        for (trigger_target, mut timer) in q_timers.iter_mut() {
+            if trigger_target.target_name != trigger_event.target {
+                continue;
+            }
+            timer.enabled = !timer.enabled;
+            if timer.enabled {
+                timer.remaining = timer.next_wait();
+            }
+        }
+    }
+}
+
+pub fn timer_system(
+    time: Res<Time>,
+    mut trigger_events: EventWriter<TriggeredEvent>,
+    mut q_timers: Query<(Entity, &mut MapTimer)>,
+) {
+    for (entity, mut timer) in q_timers.iter_mut() {
+        if !timer.enabled {
+            continue;
+        }
+        timer.remaining -= time.delta_seconds();
+        while timer.remaining <= 0.0 {
+            trigger_events.send(TriggeredEvent {
+                target: timer.target.clone(),
+                triggered_by: entity,
+            });
+            timer.remaining += timer.next_wait();
+        }
+    }
+}
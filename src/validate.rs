@@ -0,0 +1,267 @@
+use bevy::math::Vec3;
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+use crate::conversions::parse_rgba;
+use crate::core::{is_nodraw_texture, parse_vec3, CompiledMap};
+use crate::fgd::{EntityDefinitions, PropertyKind};
+
+/// A problem found while linting a map. Entity, brush and face numbers are
+/// their indices in the .map file.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum MapIssue {
+    #[error("entity {entity}: brush {brush} has fewer than 4 faces with geometry")]
+    DegenerateBrush { entity: usize, brush: usize },
+    #[error("entity {entity}: brush {brush}: face {face} has no triangles")]
+    DegenerateFace {
+        entity: usize,
+        brush: usize,
+        face: usize,
+    },
+    #[error("missing texture `{texture}`")]
+    MissingTexture { texture: String },
+    #[error("entity {entity}: target `{target}` does not match any targetname")]
+    UnmatchedTarget { entity: usize, target: String },
+    #[error("entity {entity}: targetname `{target_name}` is never targeted")]
+    UnmatchedTargetName { entity: usize, target_name: String },
+    #[error("movers {a} and {b} overlap")]
+    OverlappingMovers { a: usize, b: usize },
+    #[error("entity {entity}: property `{key}` has unparsable value `{value}`")]
+    UnparsableProperty {
+        entity: usize,
+        key: String,
+        value: String,
+    },
+    #[error("entity {entity}: property `{key}` has value `{value}`, not one of its choices")]
+    UnknownChoice {
+        entity: usize,
+        key: String,
+        value: String,
+    },
+}
+
+/// properties the built-in handlers parse as three numbers
+const VEC3_PROPERTIES: &[&str] = &[
+    "origin",
+    "angles",
+    "mangle",
+    "destination_offset",
+    "current_direction",
+    "_sun_mangle",
+];
+
+/// properties the built-in handlers parse as colors
+const COLOR_PROPERTIES: &[&str] = &["color", "_color", "_light", "_sunlight_color", "fog_color"];
+
+/// properties the built-in handlers parse as a single number
+const NUMBER_PROPERTIES: &[&str] = &[
+    "moving_time",
+    "destination_time",
+    "wait",
+    "block_margin",
+    "open_radius",
+    "radius",
+    "range",
+    "intensity",
+    "illuminance",
+    "light",
+    "_sunlight",
+    "shadow_distance",
+    "cascade_count",
+    "_health",
+    "volume",
+    "pitch_variance",
+    "_seed",
+    "_scatter_density",
+    "_blend_mask_scale",
+    "fog_start",
+    "fog_end",
+    "fog_density",
+    "priority",
+    "damage",
+    "tick_rate",
+    "current_speed",
+    "current_drag",
+    "required_count",
+    "required_mass",
+    "look_angle",
+    "look_time",
+    "interval",
+    "random_variance",
+    "spawnflags",
+    "use_radius",
+];
+
+/// Properties naming the `targetname` of the entities they fire.
+const TARGET_PROPERTIES: &[&str] = &["target", "target_release", "look_target"];
+
+/// Lints a compiled map.
+/// `known_textures` lists the textures that could be loaded; pass `None` to
+/// skip the missing texture check (e.g. when textures were never loaded).
+pub fn validate_compiled_map(
+    map: &CompiledMap,
+    known_textures: Option<&BTreeSet<String>>,
+) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+
+    // geometry
+    let mut missing_textures = BTreeSet::new();
+    for entity in map.entities.iter() {
+        for brush in entity.brushes.iter() {
+            let mut faces_with_geometry = 0;
+            for face in brush.faces.iter() {
+                if face.indices.len() < 3 {
+                    issues.push(MapIssue::DegenerateFace {
+                        entity: entity.index,
+                        brush: brush.index,
+                        face: face.index,
+                    });
+                } else {
+                    faces_with_geometry += 1;
+                }
+
+                if let Some(known_textures) = known_textures {
+                    if !is_nodraw_texture(&face.texture) && !known_textures.contains(&face.texture)
+                    {
+                        missing_textures.insert(face.texture.clone());
+                    }
+                }
+            }
+
+            if faces_with_geometry < 4 {
+                issues.push(MapIssue::DegenerateBrush {
+                    entity: entity.index,
+                    brush: brush.index,
+                });
+            }
+        }
+    }
+    for texture in missing_textures {
+        issues.push(MapIssue::MissingTexture { texture });
+    }
+
+    // targets
+    let targets = map
+        .entities
+        .iter()
+        .flat_map(|entity| {
+            TARGET_PROPERTIES
+                .iter()
+                .filter_map(|key| entity.properties.get(*key))
+        })
+        .collect::<BTreeSet<_>>();
+    let target_names = map
+        .entities
+        .iter()
+        .filter_map(|entity| entity.properties.get("targetname"))
+        .collect::<BTreeSet<_>>();
+    for entity in map.entities.iter() {
+        for target in TARGET_PROPERTIES
+            .iter()
+            .filter_map(|key| entity.properties.get(*key))
+        {
+            if !target_names.contains(target) {
+                issues.push(MapIssue::UnmatchedTarget {
+                    entity: entity.index,
+                    target: target.clone(),
+                });
+            }
+        }
+        if let Some(target_name) = entity.properties.get("targetname") {
+            if !targets.contains(target_name) {
+                issues.push(MapIssue::UnmatchedTargetName {
+                    entity: entity.index,
+                    target_name: target_name.clone(),
+                });
+            }
+        }
+    }
+
+    // movers
+    let movers = map
+        .entities
+        .iter()
+        .filter(|entity| entity.classname == "mover" && entity.is_brush_entity())
+        .map(|entity| {
+            let mut min = Vec3::splat(f32::MAX);
+            let mut max = Vec3::splat(f32::MIN);
+            for vertex in entity.brushes.iter().flat_map(|b| b.vertices.iter()) {
+                min = min.min(*vertex);
+                max = max.max(*vertex);
+            }
+            (entity.index, min, max)
+        })
+        .collect::<Vec<_>>();
+    for (i, (a, a_min, a_max)) in movers.iter().enumerate() {
+        for (b, b_min, b_max) in movers.iter().skip(i + 1) {
+            if a_min.cmplt(*b_max).all() && b_min.cmplt(*a_max).all() {
+                issues.push(MapIssue::OverlappingMovers { a: *a, b: *b });
+            }
+        }
+    }
+
+    // properties
+    for entity in map.entities.iter() {
+        for (key, value) in entity.properties.iter() {
+            let parsable = if VEC3_PROPERTIES.contains(&key.as_str()) {
+                parse_vec3(value).is_some()
+            } else if COLOR_PROPERTIES.contains(&key.as_str()) {
+                parse_rgba(value).is_some()
+            } else if NUMBER_PROPERTIES.contains(&key.as_str()) {
+                value.trim().parse::<f32>().is_ok()
+            } else {
+                true
+            };
+
+            if !parsable {
+                issues.push(MapIssue::UnparsableProperty {
+                    entity: entity.index,
+                    key: key.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+/// Checks the properties of the entities of classes from an FGD against
+/// their declared types: numbers, colors and flags must parse, and choices
+/// must be one of the listed values.
+pub fn validate_definitions(map: &CompiledMap, definitions: &EntityDefinitions) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+    for entity in map.entities.iter() {
+        let properties = definitions.properties(&entity.classname);
+        for (key, value) in entity.properties.iter() {
+            let Some(property) = properties.iter().find(|property| property.name == *key) else {
+                continue;
+            };
+            let value = value.trim();
+            let parsable = match &property.kind {
+                PropertyKind::Integer | PropertyKind::Flags(_) => value.parse::<i64>().is_ok(),
+                PropertyKind::Float => value.parse::<f32>().is_ok(),
+                PropertyKind::Color => parse_rgba(value).is_some(),
+                PropertyKind::Choices(choices) => {
+                    if !choices.iter().any(|(choice, _)| choice == value) {
+                        issues.push(MapIssue::UnknownChoice {
+                            entity: entity.index,
+                            key: key.clone(),
+                            value: value.to_owned(),
+                        });
+                    }
+                    true
+                }
+                PropertyKind::String | PropertyKind::Other(_) => true,
+            };
+            if !parsable {
+                issues.push(MapIssue::UnparsableProperty {
+                    entity: entity.index,
+                    key: key.clone(),
+                    value: value.to_owned(),
+                });
+            }
+        }
+    }
+    issues
+}
@@ -0,0 +1,19 @@
+//! Meshing regression test of the example map, see the `snapshot` module.
+//!
+//! The golden file is created, or updated after an intended meshing change,
+//! with `QEVY_UPDATE_SNAPSHOTS=1 cargo test --features test_utils`. Commit
+//! the diff along with the change.
+
+use qevy::test_utils::{assert_geometry_snapshot, build_map_from_str, face_count, test_app};
+
+#[test]
+fn example_map_geometry() {
+    let mut app = test_app();
+    let map = build_map_from_str(&mut app, include_str!("../assets/example.map"));
+    assert!(face_count(&mut app, map) > 0);
+    assert_geometry_snapshot(
+        &mut app,
+        map,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots/example.ron"),
+    );
+}